@@ -0,0 +1,334 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::durable;
+
+const BACKUP_PREFIX: &str = "workspace-";
+const BACKUP_SUFFIX: &str = ".json";
+
+const HOUR_MILLIS: u128 = 60 * 60 * 1000;
+const DAY_MILLIS: u128 = 24 * HOUR_MILLIS;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetentionPolicy {
+    /// A new backup is taken on save only when the newest one is at least this old.
+    pub min_interval_millis: u128,
+    /// The newest `keep_recent` backups are always kept (hourly generations).
+    pub keep_recent: usize,
+    /// Beyond those, the newest backup of each of the last `keep_daily` days is kept.
+    pub keep_daily: usize,
+}
+
+pub const DEFAULT_POLICY: RetentionPolicy = RetentionPolicy {
+    min_interval_millis: HOUR_MILLIS,
+    keep_recent: 24,
+    keep_daily: 14,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupFile {
+    pub file_name: String,
+    pub created_at_millis: u128,
+}
+
+pub fn backup_dir(workspace_path: &Path) -> PathBuf {
+    let parent = workspace_path.parent().unwrap_or_else(|| Path::new("."));
+    parent.join("backups")
+}
+
+pub fn is_backup_file_name(file_name: &str) -> bool {
+    parse_backup_file_name(file_name).is_some()
+}
+
+fn parse_backup_file_name(file_name: &str) -> Option<u128> {
+    file_name
+        .strip_prefix(BACKUP_PREFIX)?
+        .strip_suffix(BACKUP_SUFFIX)?
+        .parse()
+        .ok()
+}
+
+/// Newest first. A missing directory simply means no backups yet.
+pub fn list_backups(dir: &Path) -> Result<Vec<BackupFile>, String> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(dir).map_err(|e| e.to_string())? {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if let Some(created_at_millis) = parse_backup_file_name(&file_name) {
+            backups.push(BackupFile {
+                file_name,
+                created_at_millis,
+            });
+        }
+    }
+    backups.sort_by_key(|b| std::cmp::Reverse(b.created_at_millis));
+    Ok(backups)
+}
+
+pub fn create_backup(dir: &Path, now_millis: u128, text: &str) -> Result<PathBuf, String> {
+    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    let backup_path = dir.join(format!("{BACKUP_PREFIX}{now_millis}{BACKUP_SUFFIX}"));
+    durable::write_atomic(&durable::RealFs, &backup_path, text.as_bytes())?;
+    Ok(backup_path)
+}
+
+/// The text of the backup `file_name` in `dir`. Anything that is not a backup file name
+/// (including paths) is refused.
+pub fn read_backup(dir: &Path, file_name: &str) -> Result<String, String> {
+    if !is_backup_file_name(file_name) {
+        return Err(format!("not a backup file: {file_name}"));
+    }
+    fs::read_to_string(dir.join(file_name)).map_err(|e| e.to_string())
+}
+
+/// Called before the workspace is overwritten. `snapshot` yields the last saved workspace
+/// (or `None` if there is none yet) and is only read when a backup is actually due.
+pub fn backup_if_due(
+    dir: &Path,
+    now_millis: u128,
+    policy: RetentionPolicy,
+    snapshot: impl FnOnce() -> Option<String>,
+) -> Result<Option<PathBuf>, String> {
+    let backups = list_backups(dir)?;
+    let due = backups.first().is_none_or(|newest| {
+        now_millis.saturating_sub(newest.created_at_millis) >= policy.min_interval_millis
+    });
+    if !due {
+        return Ok(None);
+    }
+    let Some(text) = snapshot() else {
+        return Ok(None);
+    };
+    let created = create_backup(dir, now_millis, &text)?;
+    prune_backups(dir, policy)?;
+    Ok(Some(created))
+}
+
+/// Deletes every backup the policy does not retain and returns their file names.
+pub fn prune_backups(dir: &Path, policy: RetentionPolicy) -> Result<Vec<String>, String> {
+    let backups = list_backups(dir)?;
+    let keep = retained(&backups, policy);
+    let mut removed = Vec::new();
+    for backup in backups {
+        if !keep.contains(&backup.file_name) {
+            fs::remove_file(dir.join(&backup.file_name)).map_err(|e| e.to_string())?;
+            removed.push(backup.file_name);
+        }
+    }
+    Ok(removed)
+}
+
+/// `backups` must be newest first.
+fn retained(backups: &[BackupFile], policy: RetentionPolicy) -> HashSet<String> {
+    let times: Vec<u128> = backups.iter().map(|b| b.created_at_millis).collect();
+    backups
+        .iter()
+        .zip(keep_mask(&times, policy))
+        .filter(|(_, keep)| *keep)
+        .map(|(b, _)| b.file_name.clone())
+        .collect()
+}
+
+/// Which of `created_at_millis` (newest first) `policy` keeps: the newest `keep_recent`,
+/// plus the newest of each of the last `keep_daily` days.
+pub fn keep_mask(created_at_millis: &[u128], policy: RetentionPolicy) -> Vec<bool> {
+    let mut keep: Vec<bool> = (0..created_at_millis.len())
+        .map(|index| index < policy.keep_recent)
+        .collect();
+
+    let mut days_seen: Vec<u128> = Vec::new();
+    for (index, created) in created_at_millis.iter().enumerate() {
+        let day = created / DAY_MILLIS;
+        if days_seen.contains(&day) {
+            continue;
+        }
+        if days_seen.len() == policy.keep_daily {
+            break;
+        }
+        days_seen.push(day);
+        keep[index] = true;
+    }
+
+    keep
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::Scratch;
+
+    const POLICY: RetentionPolicy = RetentionPolicy {
+        min_interval_millis: HOUR_MILLIS,
+        keep_recent: 2,
+        keep_daily: 3,
+    };
+
+    fn names(dir: &Path) -> Vec<String> {
+        list_backups(dir)
+            .unwrap()
+            .into_iter()
+            .map(|b| b.file_name)
+            .collect()
+    }
+
+    #[test]
+    fn keep_mask_keeps_recent_and_newest_of_each_day() {
+        let times = [
+            5 * DAY_MILLIS + 3 * HOUR_MILLIS,
+            5 * DAY_MILLIS + 2 * HOUR_MILLIS,
+            5 * DAY_MILLIS + HOUR_MILLIS,
+            4 * DAY_MILLIS + 2 * HOUR_MILLIS,
+            4 * DAY_MILLIS + HOUR_MILLIS,
+            3 * DAY_MILLIS,
+            2 * DAY_MILLIS,
+        ];
+        assert_eq!(
+            keep_mask(&times, POLICY),
+            vec![true, true, false, true, false, true, false]
+        );
+        assert_eq!(keep_mask(&[], POLICY), Vec::<bool>::new());
+
+        let none = RetentionPolicy {
+            keep_recent: 0,
+            keep_daily: 0,
+            ..POLICY
+        };
+        assert!(keep_mask(&times, none).iter().all(|keep| !keep));
+    }
+
+    #[test]
+    fn list_is_newest_first_and_ignores_other_files() {
+        let scratch = Scratch::new("backup-list");
+        assert!(list_backups(&scratch.dir.join("missing"))
+            .unwrap()
+            .is_empty());
+
+        create_backup(&scratch.dir, 20, "b").unwrap();
+        create_backup(&scratch.dir, 100, "c").unwrap();
+        create_backup(&scratch.dir, 3, "a").unwrap();
+        fs::write(scratch.dir.join("notes.txt"), "x").unwrap();
+        fs::write(scratch.dir.join("workspace-abc.json"), "x").unwrap();
+
+        let backups = list_backups(&scratch.dir).unwrap();
+        assert_eq!(
+            backups,
+            vec![
+                BackupFile {
+                    file_name: "workspace-100.json".to_string(),
+                    created_at_millis: 100,
+                },
+                BackupFile {
+                    file_name: "workspace-20.json".to_string(),
+                    created_at_millis: 20,
+                },
+                BackupFile {
+                    file_name: "workspace-3.json".to_string(),
+                    created_at_millis: 3,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn backup_if_due_respects_interval_and_prunes() {
+        let scratch = Scratch::new("backup-due");
+        let mut snapshots = 0;
+        let mut snapshot = |text: &str| {
+            snapshots += 1;
+            Some(text.to_string())
+        };
+
+        let day = 10 * DAY_MILLIS;
+        assert!(backup_if_due(&scratch.dir, day, POLICY, || snapshot("1"))
+            .unwrap()
+            .is_some());
+        // Too soon: the snapshot is not even read.
+        assert!(
+            backup_if_due(&scratch.dir, day + HOUR_MILLIS - 1, POLICY, || snapshot(
+                "x"
+            ))
+            .unwrap()
+            .is_none()
+        );
+        assert_eq!(snapshots, 1);
+        assert!(
+            backup_if_due(&scratch.dir, day + HOUR_MILLIS, POLICY, || None)
+                .unwrap()
+                .is_none()
+        );
+
+        for (hour, text) in [(1, "2"), (2, "3"), (3, "4")] {
+            backup_if_due(&scratch.dir, day + hour * HOUR_MILLIS, POLICY, || {
+                Some(text.to_string())
+            })
+            .unwrap();
+        }
+        // Two recent generations, plus the newest of the day already among them.
+        assert_eq!(
+            names(&scratch.dir),
+            vec![
+                format!("workspace-{}.json", day + 3 * HOUR_MILLIS),
+                format!("workspace-{}.json", day + 2 * HOUR_MILLIS),
+            ]
+        );
+    }
+
+    #[test]
+    fn prune_keeps_daily_generations() {
+        let scratch = Scratch::new("backup-prune");
+        for time in [
+            DAY_MILLIS,
+            2 * DAY_MILLIS,
+            3 * DAY_MILLIS,
+            3 * DAY_MILLIS + HOUR_MILLIS,
+            4 * DAY_MILLIS,
+            4 * DAY_MILLIS + HOUR_MILLIS,
+            4 * DAY_MILLIS + 2 * HOUR_MILLIS,
+        ] {
+            create_backup(&scratch.dir, time, "x").unwrap();
+        }
+
+        let mut removed = prune_backups(&scratch.dir, POLICY).unwrap();
+        removed.sort();
+        let mut expected = vec![
+            format!("workspace-{}.json", DAY_MILLIS),
+            format!("workspace-{}.json", 3 * DAY_MILLIS),
+            format!("workspace-{}.json", 4 * DAY_MILLIS),
+        ];
+        expected.sort();
+        assert_eq!(removed, expected);
+        assert_eq!(
+            names(&scratch.dir),
+            vec![
+                format!("workspace-{}.json", 4 * DAY_MILLIS + 2 * HOUR_MILLIS),
+                format!("workspace-{}.json", 4 * DAY_MILLIS + HOUR_MILLIS),
+                format!("workspace-{}.json", 3 * DAY_MILLIS + HOUR_MILLIS),
+                format!("workspace-{}.json", 2 * DAY_MILLIS),
+            ]
+        );
+        assert!(prune_backups(&scratch.dir, POLICY).unwrap().is_empty());
+    }
+
+    #[test]
+    fn restore_reads_backup_text_and_refuses_other_names() {
+        let scratch = Scratch::new("backup-restore");
+        let path = create_backup(&scratch.dir, 42, "{\"documents\":[]}").unwrap();
+        assert_eq!(path, scratch.dir.join("workspace-42.json"));
+        assert_eq!(
+            read_backup(&scratch.dir, "workspace-42.json").unwrap(),
+            "{\"documents\":[]}"
+        );
+        // No temp file is left beside the backup.
+        assert_eq!(fs::read_dir(&scratch.dir).unwrap().count(), 1);
+
+        assert!(read_backup(&scratch.dir, "workspace-7.json").is_err());
+        assert!(read_backup(&scratch.dir, "../workspace.json").is_err());
+        assert!(read_backup(&scratch.dir, "workspace-42.json.tmp").is_err());
+    }
+}
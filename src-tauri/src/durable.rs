@@ -0,0 +1,302 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// The filesystem steps of a durable replace, split out so tests can fail each one.
+pub trait FileSystem {
+    fn write_file(&self, path: &Path, bytes: &[u8]) -> io::Result<()>;
+    fn sync_file(&self, path: &Path) -> io::Result<()>;
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+    fn sync_dir(&self, dir: &Path) -> io::Result<()>;
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+}
+
+pub struct RealFs;
+
+impl FileSystem for RealFs {
+    fn write_file(&self, path: &Path, bytes: &[u8]) -> io::Result<()> {
+        fs::write(path, bytes)
+    }
+
+    fn sync_file(&self, path: &Path) -> io::Result<()> {
+        fs::OpenOptions::new().write(true).open(path)?.sync_all()
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    #[cfg(unix)]
+    fn sync_dir(&self, dir: &Path) -> io::Result<()> {
+        fs::File::open(dir)?.sync_all()
+    }
+
+    /// Windows cannot open a directory handle for flushing; NTFS journals the rename itself.
+    #[cfg(not(unix))]
+    fn sync_dir(&self, _dir: &Path) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+}
+
+const RENAME_ATTEMPTS: u32 = 5;
+const RENAME_RETRY_DELAY: Duration = Duration::from_millis(20);
+
+/// Tells apart temp files of saves that start in the same millisecond in this process;
+/// the pid does the same across processes.
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+fn temp_path_for(path: &Path) -> Result<PathBuf, String> {
+    let parent = path
+        .parent()
+        .ok_or_else(|| format!("{} has no parent", path.display()))?;
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| format!("{} has no file name", path.display()))?
+        .to_string_lossy();
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let pid = std::process::id();
+    let count = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+    Ok(parent.join(format!("{file_name}.tmp-{pid}-{millis}-{count}")))
+}
+
+/// Replaces `path` with `bytes` so that after a crash at any point the file holds either
+/// the old or the new content in full: write temp, fsync temp, rename over, fsync the
+/// directory. The existing file is never removed; a rename that keeps failing (e.g. the
+/// target is briefly locked on Windows) is retried and then reported.
+pub fn write_atomic(fs: &dyn FileSystem, path: &Path, bytes: &[u8]) -> Result<(), String> {
+    let tmp_path = temp_path_for(path)?;
+
+    let staged = fs
+        .write_file(&tmp_path, bytes)
+        .and_then(|()| fs.sync_file(&tmp_path));
+    if let Err(e) = staged {
+        let _ = fs.remove_file(&tmp_path);
+        return Err(e.to_string());
+    }
+
+    let mut attempt = 1;
+    while let Err(e) = fs.rename(&tmp_path, path) {
+        if attempt == RENAME_ATTEMPTS {
+            let _ = fs.remove_file(&tmp_path);
+            return Err(e.to_string());
+        }
+        attempt += 1;
+        thread::sleep(RENAME_RETRY_DELAY);
+    }
+
+    // A bare file name has the empty path as its parent.
+    let parent = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    fs.sync_dir(parent).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::Scratch;
+    use std::cell::Cell;
+
+    #[derive(Clone, Copy, PartialEq)]
+    enum Step {
+        Write,
+        SyncFile,
+        Rename,
+        SyncDir,
+    }
+
+    /// Delegates to the real filesystem but fails `step` for its first `failures` calls.
+    struct FaultyFs {
+        step: Step,
+        failures: Cell<u32>,
+        /// Simulates a crash mid-write by leaving a partial temp file behind.
+        partial_write: bool,
+    }
+
+    impl FaultyFs {
+        fn new(step: Step, failures: u32) -> Self {
+            FaultyFs {
+                step,
+                failures: Cell::new(failures),
+                partial_write: false,
+            }
+        }
+
+        fn check(&self, step: Step) -> io::Result<()> {
+            if self.step == step && self.failures.get() > 0 {
+                self.failures.set(self.failures.get() - 1);
+                return Err(io::Error::other("injected failure"));
+            }
+            Ok(())
+        }
+    }
+
+    impl FileSystem for FaultyFs {
+        fn write_file(&self, path: &Path, bytes: &[u8]) -> io::Result<()> {
+            if self.step == Step::Write && self.partial_write {
+                RealFs.write_file(path, &bytes[..bytes.len() / 2])?;
+            }
+            self.check(Step::Write)?;
+            RealFs.write_file(path, bytes)
+        }
+
+        fn sync_file(&self, path: &Path) -> io::Result<()> {
+            self.check(Step::SyncFile)?;
+            RealFs.sync_file(path)
+        }
+
+        fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+            self.check(Step::Rename)?;
+            RealFs.rename(from, to)
+        }
+
+        fn sync_dir(&self, dir: &Path) -> io::Result<()> {
+            self.check(Step::SyncDir)?;
+            RealFs.sync_dir(dir)
+        }
+
+        fn remove_file(&self, path: &Path) -> io::Result<()> {
+            RealFs.remove_file(path)
+        }
+    }
+
+    fn workspace(scratch: &Scratch) -> PathBuf {
+        scratch.dir.join("workspace.json")
+    }
+
+    fn entries(scratch: &Scratch) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(&scratch.dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn assert_old_copy_intact(scratch: &Scratch) {
+        assert_eq!(fs::read_to_string(workspace(scratch)).unwrap(), "old");
+        assert_eq!(entries(scratch), vec!["workspace.json"]);
+    }
+
+    #[test]
+    fn writes_new_file() {
+        let scratch = Scratch::new("durable-new");
+        write_atomic(&RealFs, &workspace(&scratch), b"new").unwrap();
+        assert_eq!(fs::read_to_string(workspace(&scratch)).unwrap(), "new");
+        assert_eq!(entries(&scratch), vec!["workspace.json"]);
+    }
+
+    #[test]
+    fn replaces_existing_file() {
+        let scratch = Scratch::new("durable-replace");
+        fs::write(workspace(&scratch), "old").unwrap();
+        write_atomic(&RealFs, &workspace(&scratch), b"new").unwrap();
+        assert_eq!(fs::read_to_string(workspace(&scratch)).unwrap(), "new");
+        assert_eq!(entries(&scratch), vec!["workspace.json"]);
+    }
+
+    #[test]
+    fn write_failure_keeps_old_copy_and_cleans_temp() {
+        let scratch = Scratch::new("durable-write");
+        fs::write(workspace(&scratch), "old").unwrap();
+        let faulty = FaultyFs {
+            partial_write: true,
+            ..FaultyFs::new(Step::Write, 1)
+        };
+        assert!(write_atomic(&faulty, &workspace(&scratch), b"new content").is_err());
+        assert_old_copy_intact(&scratch);
+    }
+
+    #[test]
+    fn sync_failure_keeps_old_copy_and_cleans_temp() {
+        let scratch = Scratch::new("durable-sync-file");
+        fs::write(workspace(&scratch), "old").unwrap();
+        let faulty = FaultyFs::new(Step::SyncFile, 1);
+        assert!(write_atomic(&faulty, &workspace(&scratch), b"new").is_err());
+        assert_old_copy_intact(&scratch);
+    }
+
+    #[test]
+    fn persistent_rename_failure_never_removes_old_copy() {
+        let scratch = Scratch::new("durable-rename");
+        fs::write(workspace(&scratch), "old").unwrap();
+        let faulty = FaultyFs::new(Step::Rename, RENAME_ATTEMPTS);
+        assert!(write_atomic(&faulty, &workspace(&scratch), b"new").is_err());
+        assert_old_copy_intact(&scratch);
+    }
+
+    #[test]
+    fn transient_rename_failure_is_retried() {
+        let scratch = Scratch::new("durable-rename-retry");
+        fs::write(workspace(&scratch), "old").unwrap();
+        let faulty = FaultyFs::new(Step::Rename, RENAME_ATTEMPTS - 1);
+        write_atomic(&faulty, &workspace(&scratch), b"new").unwrap();
+        assert_eq!(fs::read_to_string(workspace(&scratch)).unwrap(), "new");
+        assert_eq!(entries(&scratch), vec!["workspace.json"]);
+    }
+
+    #[test]
+    fn dir_sync_failure_is_reported_after_new_copy_is_in_place() {
+        let scratch = Scratch::new("durable-sync-dir");
+        fs::write(workspace(&scratch), "old").unwrap();
+        let faulty = FaultyFs::new(Step::SyncDir, 1);
+        assert!(write_atomic(&faulty, &workspace(&scratch), b"new").is_err());
+        assert_eq!(fs::read_to_string(workspace(&scratch)).unwrap(), "new");
+        assert_eq!(entries(&scratch), vec!["workspace.json"]);
+    }
+
+    #[test]
+    fn temp_names_differ_within_a_millisecond() {
+        let path = Path::new("dir/workspace.json");
+        let (first, second) = (temp_path_for(path).unwrap(), temp_path_for(path).unwrap());
+        assert_ne!(first, second);
+        let name = first.file_name().unwrap().to_string_lossy().into_owned();
+        assert!(name.starts_with(&format!("workspace.json.tmp-{}-", std::process::id())));
+    }
+
+    /// Records which directory was synced without touching the disk.
+    #[derive(Default)]
+    struct SyncedDirs(std::cell::RefCell<Vec<PathBuf>>);
+
+    impl FileSystem for SyncedDirs {
+        fn write_file(&self, _path: &Path, _bytes: &[u8]) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn sync_file(&self, _path: &Path) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn rename(&self, _from: &Path, _to: &Path) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn sync_dir(&self, dir: &Path) -> io::Result<()> {
+            self.0.borrow_mut().push(dir.to_path_buf());
+            Ok(())
+        }
+
+        fn remove_file(&self, _path: &Path) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn bare_file_name_syncs_the_current_dir() {
+        let fs = SyncedDirs::default();
+        write_atomic(&fs, Path::new("plan.svg"), b"new").unwrap();
+        assert_eq!(*fs.0.borrow(), vec![PathBuf::from(".")]);
+    }
+}
@@ -0,0 +1,244 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use super::TreeBuilder;
+use crate::layout::{compute_layout, NODE_HEIGHT, NODE_WIDTH};
+use crate::model::Document;
+
+/// A JSON Canvas 1.0 file (`.canvas`). Only what the exporter writes and the importer reads
+/// is modelled; other keys are ignored.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct Canvas {
+    nodes: Vec<CanvasNode>,
+    edges: Vec<CanvasEdge>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct CanvasNode {
+    id: String,
+    #[serde(rename = "type")]
+    kind: String,
+    x: i64,
+    y: i64,
+    width: i64,
+    height: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(skip_serializing)]
+    file: Option<String>,
+    #[serde(skip_serializing)]
+    url: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct CanvasEdge {
+    id: String,
+    from_node: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    from_side: Option<String>,
+    to_node: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    to_side: Option<String>,
+}
+
+/// One text node per node, placed where the editor would draw it, and an edge from each
+/// parent's right side to its child's left side.
+pub fn export(doc: &Document) -> Result<String, String> {
+    let layout = compute_layout(doc);
+    let mut ids = vec![doc.root_id.as_str()];
+    ids.extend(layout.edges.iter().map(|(_, child_id)| child_id.as_str()));
+    let nodes = ids
+        .into_iter()
+        .filter_map(|id| {
+            let (node, pos) = (doc.nodes.get(id)?, layout.positions.get(id)?);
+            Some(CanvasNode {
+                id: node.id.clone(),
+                kind: "text".to_string(),
+                x: pos.x.round() as i64,
+                y: pos.y.round() as i64,
+                width: NODE_WIDTH as i64,
+                height: NODE_HEIGHT as i64,
+                text: Some(node.text.clone()),
+                ..CanvasNode::default()
+            })
+        })
+        .collect();
+    let edges = layout
+        .edges
+        .iter()
+        .enumerate()
+        .map(|(index, (parent_id, child_id))| CanvasEdge {
+            id: format!("edge-{index}"),
+            from_node: parent_id.clone(),
+            from_side: Some("right".to_string()),
+            to_node: child_id.clone(),
+            to_side: Some("left".to_string()),
+        })
+        .collect();
+    serde_json::to_string_pretty(&Canvas { nodes, edges }).map_err(|e| e.to_string())
+}
+
+/// Rebuilds a tree from the edges, which must form a forest: no node with two incoming
+/// edges and no cycles. Edges point from parent to child; siblings, and several top-level
+/// nodes, are ordered top to bottom by position. Text, file and link nodes become nodes;
+/// groups and edges to them are left out with a warning.
+pub fn import(text: &str, title: &str, id_prefix: &str) -> Result<TreeBuilder, String> {
+    let canvas: Canvas = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    // Node kinds left out, with how many of each, and edges touching them.
+    let mut ignored: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut ignored_edges = 0;
+    let mut nodes: HashMap<&str, &CanvasNode> = HashMap::new();
+    for node in &canvas.nodes {
+        match node.kind.as_str() {
+            "text" | "file" | "link" => {
+                if nodes.insert(&node.id, node).is_some() {
+                    return Err(format!("node {} appears more than once", node.id));
+                }
+            }
+            other => *ignored.entry(other).or_default() += 1,
+        }
+    }
+
+    let mut parents: HashMap<&str, &str> = HashMap::new();
+    let mut children: HashMap<&str, Vec<&CanvasNode>> = HashMap::new();
+    for edge in &canvas.edges {
+        let (Some(from), Some(to)) = (
+            nodes.get(edge.from_node.as_str()),
+            nodes.get(edge.to_node.as_str()),
+        ) else {
+            ignored_edges += 1;
+            continue;
+        };
+        if parents.insert(&to.id, &from.id).is_some() {
+            return Err(format!(
+                "node {} has more than one incoming edge, so the canvas is not a tree",
+                to.id
+            ));
+        }
+        children.entry(&from.id).or_default().push(to);
+    }
+    let by_position = |a: &&CanvasNode, b: &&CanvasNode| (a.y, a.x).cmp(&(b.y, b.x));
+    for list in children.values_mut() {
+        list.sort_by(by_position);
+    }
+    let mut roots: Vec<&CanvasNode> = canvas
+        .nodes
+        .iter()
+        .filter(|node| nodes.contains_key(node.id.as_str()))
+        .filter(|node| !parents.contains_key(node.id.as_str()))
+        .collect();
+    roots.sort_by(by_position);
+
+    let mut builder = TreeBuilder::new(id_prefix, title);
+    let mut stack: Vec<(String, &CanvasNode)> = roots
+        .into_iter()
+        .rev()
+        .map(|node| (builder.root_id().to_string(), node))
+        .collect();
+    let mut placed = 0;
+    while let Some((parent_id, node)) = stack.pop() {
+        let text = node
+            .text
+            .as_ref()
+            .or(node.file.as_ref())
+            .or(node.url.as_ref())
+            .map_or("", String::as_str);
+        let id = builder.add_child_with_id(&parent_id, Some(&node.id), text);
+        placed += 1;
+        if let Some(list) = children.get(node.id.as_str()) {
+            stack.extend(list.iter().rev().map(|child| (id.clone(), *child)));
+        }
+    }
+    // Nodes on a cycle all have a parent, so none of them was reached from a root.
+    if placed < nodes.len() {
+        return Err("the canvas edges form a cycle, so the canvas is not a tree".to_string());
+    }
+    let plural = |count: usize| if count == 1 { "" } else { "s" };
+    for (kind, count) in ignored {
+        builder.warn(format!("ignored {count} {kind} node{}", plural(count)));
+    }
+    if ignored_edges > 0 {
+        builder.warn(format!(
+            "ignored {ignored_edges} edge{} to left-out nodes",
+            plural(ignored_edges)
+        ));
+    }
+    builder.unwrap_single_child();
+    Ok(builder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::document;
+
+    #[test]
+    fn round_trips_through_the_layout_and_rejects_non_trees() {
+        let canvas = r#"{
+            "nodes": [
+                {"id": "b", "type": "text", "x": 300, "y": 90, "width": 100, "height": 40, "text": "Team"},
+                {"id": "a", "type": "text", "x": 300, "y": 10, "width": 100, "height": 40, "text": "Budget\nQ3"},
+                {"id": "root", "type": "text", "x": 0, "y": 50, "width": 100, "height": 40, "text": "Plan"},
+                {"id": "link", "type": "link", "x": 600, "y": 0, "width": 100, "height": 40, "url": "https://example.com"},
+                {"id": "group", "type": "group", "x": 0, "y": 0, "width": 900, "height": 200, "label": "All"}
+            ],
+            "edges": [
+                {"id": "e1", "fromNode": "root", "toNode": "b"},
+                {"id": "e2", "fromNode": "root", "toNode": "a"},
+                {"id": "e3", "fromNode": "a", "toNode": "link"},
+                {"id": "e4", "fromNode": "group", "toNode": "root"}
+            ]
+        }"#;
+        let mut builder = import(canvas, "file", "canvas").unwrap();
+        assert_eq!(
+            std::mem::take(&mut builder.warnings),
+            vec!["ignored 1 group node", "ignored 1 edge to left-out nodes"]
+        );
+        let doc = builder.finish("canvas");
+        assert_eq!(doc.root_id, "root");
+        assert_eq!(doc.nodes["root"].children_ids, vec!["a", "b"]);
+        assert_eq!(doc.nodes["link"].text, "https://example.com");
+
+        let again = import(&export(&doc).unwrap(), "file", "canvas")
+            .unwrap()
+            .finish("canvas");
+        assert_eq!(again.nodes, doc.nodes);
+
+        let two_parents = r#"{"nodes": [{"id": "a", "type": "text"}, {"id": "b", "type": "text"}, {"id": "c", "type": "text"}],
+            "edges": [{"id": "1", "fromNode": "a", "toNode": "c"}, {"id": "2", "fromNode": "b", "toNode": "c"}]}"#;
+        assert!(import(two_parents, "file", "canvas").is_err());
+        let cycle = r#"{"nodes": [{"id": "r", "type": "text"}, {"id": "a", "type": "text"}, {"id": "b", "type": "text"}],
+            "edges": [{"id": "1", "fromNode": "a", "toNode": "b"}, {"id": "2", "fromNode": "b", "toNode": "a"}]}"#;
+        assert!(import(cycle, "file", "canvas").is_err());
+    }
+
+    #[test]
+    fn places_nodes_where_the_editor_draws_them() {
+        // "b" lists "a" again, as a damaged file might; it is written once.
+        let doc = document(&[
+            ("root", "Plan", &["a", "b"]),
+            ("a", "Budget", &[]),
+            ("b", "Team", &["a"]),
+        ]);
+        let canvas: Canvas = serde_json::from_str(&export(&doc).unwrap()).unwrap();
+
+        let nodes: Vec<(&str, i64, i64)> = canvas
+            .nodes
+            .iter()
+            .map(|node| (node.id.as_str(), node.x, node.y))
+            .collect();
+        assert_eq!(
+            nodes,
+            vec![("root", 32, 57), ("a", 292, 32), ("b", 292, 82)]
+        );
+        let edges: Vec<(&str, &str)> = canvas
+            .edges
+            .iter()
+            .map(|edge| (edge.from_node.as_str(), edge.to_node.as_str()))
+            .collect();
+        assert_eq!(edges, vec![("root", "a"), ("root", "b")]);
+    }
+}
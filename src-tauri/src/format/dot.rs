@@ -0,0 +1,59 @@
+use super::outline;
+use crate::model::Document;
+
+/// For a quoted DOT string. Backslashes are doubled so label escapes such as `\N` and `\G`
+/// stay literal; line breaks become `\n`.
+fn escape_label(text: &str) -> String {
+    let escaped = text.replace('\\', "\\\\").replace('"', "\\\"");
+    let lines: Vec<&str> = escaped.lines().collect();
+    lines.join("\\n")
+}
+
+/// A Graphviz digraph laid out left to right, like the editor, with one rounded box per
+/// node and an edge from each parent to its children in order.
+pub fn export(doc: &Document) -> String {
+    let nodes = outline(doc);
+    let title = nodes.first().map_or("", |(_, root)| root.text.as_str());
+    let mut out = format!("digraph \"{}\" {{\n", escape_label(title));
+    out.push_str("  rankdir=LR;\n");
+    out.push_str("  node [shape=box, style=rounded];\n");
+    let mut edges = Vec::new();
+    // The indices of the current node's ancestors: `outline` is depth first.
+    let mut path: Vec<usize> = Vec::new();
+    for (index, (depth, node)) in nodes.iter().enumerate() {
+        path.truncate(*depth);
+        if let Some(parent) = path.last() {
+            edges.push((*parent, index));
+        }
+        path.push(index);
+        out.push_str(&format!(
+            "  n{index} [label=\"{}\"];\n",
+            escape_label(&node.text)
+        ));
+    }
+    for (parent, child) in edges {
+        out.push_str(&format!("  n{parent} -> n{child};\n"));
+    }
+    out.push_str("}\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::document;
+
+    #[test]
+    fn links_parents_to_children_and_escapes_labels() {
+        let doc = document(&[
+            ("root", "Plan", &["a", "b"]),
+            ("a", "Say \"hi\"\nC:\\N", &["c"]),
+            ("b", "Team", &[]),
+            ("c", "Travel", &[]),
+        ]);
+        assert_eq!(
+            export(&doc),
+            "digraph \"Plan\" {\n  rankdir=LR;\n  node [shape=box, style=rounded];\n  n0 [label=\"Plan\"];\n  n1 [label=\"Say \\\"hi\\\"\\nC:\\\\N\"];\n  n2 [label=\"Travel\"];\n  n3 [label=\"Team\"];\n  n0 -> n1;\n  n1 -> n2;\n  n0 -> n3;\n}\n"
+        );
+    }
+}
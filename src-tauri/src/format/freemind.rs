@@ -0,0 +1,230 @@
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::BTreeMap;
+
+use super::xml::{attribute, escape_attr, push_text};
+use super::{write_nested, TreeBuilder};
+use crate::model::Document;
+
+/// Read and written. Other attributes are reported as ignored, except the ones below.
+const NODE_ATTRIBUTES: [&str; 2] = ["ID", "TEXT"];
+/// Timestamps and which side of the root a branch is drawn on: dropped without a warning,
+/// since every map has them and the tree does not depend on them.
+const LAYOUT_ATTRIBUTES: [&str; 3] = ["CREATED", "MODIFIED", "POSITION"];
+
+/// A FreeMind 1.0 map. Node ids are written as `ID` so they survive a round trip.
+pub fn export(doc: &Document) -> String {
+    let mut out = String::from("<map version=\"1.0.1\">\n");
+    write_nested(
+        doc,
+        &mut out,
+        |out, _, node| {
+            let attrs = format!(
+                "ID=\"{}\" TEXT=\"{}\"",
+                escape_attr(&node.id),
+                escape_attr(&node.text)
+            );
+            if node.children_ids.is_empty() {
+                out.push_str(&format!("<node {attrs}/>\n"));
+                return false;
+            }
+            out.push_str(&format!("<node {attrs}>\n"));
+            true
+        },
+        |out, _| out.push_str("</node>\n"),
+    );
+    out.push_str("</map>\n");
+    out
+}
+
+/// Tallies what was skipped so each kind is reported once, with how often it occurred.
+#[derive(Default)]
+struct Ignored(BTreeMap<String, usize>);
+
+impl Ignored {
+    fn add(&mut self, what: String) {
+        *self.0.entry(what).or_default() += 1;
+    }
+
+    fn unknown_attributes(&mut self, element: &BytesStart) {
+        for attr in element.attributes().flatten() {
+            let key = String::from_utf8_lossy(attr.key.as_ref()).into_owned();
+            if !NODE_ATTRIBUTES.contains(&key.as_str())
+                && !LAYOUT_ATTRIBUTES.contains(&key.as_str())
+            {
+                self.add(format!("attribute {key}"));
+            }
+        }
+    }
+
+    fn into_warnings(self) -> Vec<String> {
+        self.0
+            .into_iter()
+            .map(|(what, count)| match count {
+                1 => format!("ignored {what} on 1 node"),
+                n => format!("ignored {what} on {n} nodes"),
+            })
+            .collect()
+    }
+}
+
+/// Rich content is HTML: whitespace in the source is layout, and only paragraph breaks
+/// (already turned into line breaks) separate lines.
+fn paragraphs(text: &str) -> String {
+    let lines: Vec<String> = text
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect();
+    lines.join("\n")
+}
+
+fn element_name(element: &BytesStart) -> String {
+    String::from_utf8_lossy(element.local_name().as_ref()).into_owned()
+}
+
+/// Reads `<node>` elements (FreeMind and Freeplane). A node without `TEXT` takes the text
+/// of its HTML `richcontent`, one line per paragraph. Icons, notes, styles and the like are
+/// skipped and listed as warnings.
+pub fn import(text: &str, title: &str, id_prefix: &str) -> Result<TreeBuilder, String> {
+    let mut reader = Reader::from_str(text);
+    let mut builder = TreeBuilder::new(id_prefix, title);
+    let mut open: Vec<String> = vec![builder.root_id().to_string()];
+    let mut ignored = Ignored::default();
+    // Set inside `<richcontent TYPE="NODE">`: the node it belongs to and its text so far.
+    let mut rich: Option<(String, String)> = None;
+    // Depth inside an element that is skipped as a whole.
+    let mut skipping = 0usize;
+    loop {
+        let event = reader.read_event().map_err(|e| e.to_string())?;
+        let start = matches!(event, Event::Start(_));
+        match &event {
+            Event::Start(element) | Event::Empty(element) if skipping > 0 => {
+                if start {
+                    skipping += 1;
+                }
+                if let (Some((_, text)), b"p" | b"br" | b"div" | b"li") =
+                    (&mut rich, element.local_name().as_ref())
+                {
+                    if !text.is_empty() {
+                        text.push('\n');
+                    }
+                }
+            }
+            Event::End(_) if skipping > 0 => {
+                skipping -= 1;
+                if skipping == 0 {
+                    if let Some((node_id, text)) = rich.take() {
+                        builder.set_text(&node_id, &paragraphs(&text));
+                    }
+                }
+            }
+            Event::Start(element) | Event::Empty(element) => match element.local_name().as_ref() {
+                b"map" => {}
+                b"node" => {
+                    ignored.unknown_attributes(element);
+                    let id = attribute(element, "ID")?;
+                    let text = attribute(element, "TEXT")?.unwrap_or_default();
+                    let parent_id = open.last().cloned().unwrap_or_default();
+                    let node_id = builder.add_child_with_id(&parent_id, id.as_deref(), &text);
+                    if start {
+                        open.push(node_id);
+                    }
+                }
+                b"richcontent" => {
+                    let kind = attribute(element, "TYPE")?.unwrap_or_default();
+                    let node_id = open.last().cloned().unwrap_or_default();
+                    if kind == "NODE" && open.len() > 1 {
+                        rich = Some((node_id, String::new()));
+                    } else {
+                        ignored.add(format!("{} richcontent", kind.to_lowercase()));
+                    }
+                    if start {
+                        skipping = 1;
+                    }
+                }
+                _ => {
+                    ignored.add(format!("<{}>", element_name(element)));
+                    if start {
+                        skipping = 1;
+                    }
+                }
+            },
+            Event::End(element) => {
+                if element.local_name().as_ref() == b"node" && open.len() > 1 {
+                    open.pop();
+                }
+            }
+            Event::Eof => break,
+            _ => {
+                if let Some((_, text)) = &mut rich {
+                    let mut chunk = String::new();
+                    push_text(&mut chunk, &event)?;
+                    text.push_str(&chunk.replace(char::is_whitespace, " "));
+                }
+            }
+        }
+    }
+    for warning in ignored.into_warnings() {
+        builder.warn(warning);
+    }
+    builder.unwrap_single_child();
+    Ok(builder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_ids_and_text_and_warns_about_the_rest() {
+        let mm = r##"<map version="1.0.1">
+<node CREATED="1" ID="ID_1" MODIFIED="2" TEXT="Plan">
+<node ID="ID_2" POSITION="right" TEXT="Budget" COLOR="#990000">
+<icon BUILTIN="yes"/>
+<node ID="ID_3"><richcontent TYPE="NODE"><html><body><p>Travel</p><p>&amp; lodging</p></body></html></richcontent></node>
+</node>
+<node ID="ID_2" TEXT="Team"><richcontent TYPE="NOTE"><html><body><p>note</p></body></html></richcontent></node>
+</node>
+</map>"##;
+        let mut builder = import(mm, "file", "mm").unwrap();
+        let warnings = std::mem::take(&mut builder.warnings);
+        let doc = builder.finish("mm");
+
+        assert_eq!(doc.root_id, "ID_1");
+        assert_eq!(doc.nodes["ID_1"].children_ids, vec!["ID_2", "mm-1"]);
+        assert_eq!(doc.nodes["mm-1"].text, "Team");
+        assert_eq!(doc.nodes["ID_3"].text, "Travel\n& lodging");
+        assert_eq!(
+            warnings,
+            vec![
+                "ignored <icon> on 1 node",
+                "ignored attribute COLOR on 1 node",
+                "ignored note richcontent on 1 node",
+            ]
+        );
+
+        let again = import(&export(&doc), "file", "mm").unwrap().finish("mm");
+        assert_eq!(again.nodes, doc.nodes);
+    }
+
+    #[test]
+    fn writes_a_very_deep_chain() {
+        let ids: Vec<String> = (0..100_000).map(|i| format!("n{i}")).collect();
+        let ids: Vec<&str> = ids.iter().map(String::as_str).collect();
+        let rows: Vec<(&str, &str, &[&str])> = (0..ids.len())
+            .map(|i| {
+                (
+                    ids[i],
+                    ids[i],
+                    &ids[(i + 1).min(ids.len())..(i + 2).min(ids.len())],
+                )
+            })
+            .collect();
+        let exported = export(&crate::test_support::document(&rows));
+
+        assert_eq!(exported.matches("<node ").count(), 100_000);
+        assert_eq!(exported.matches("</node>").count(), 99_999);
+        assert!(exported.contains("<node ID=\"n99998\" TEXT=\"n99998\">\n<node ID=\"n99999\" TEXT=\"n99999\"/>\n</node>\n"));
+    }
+}
@@ -0,0 +1,131 @@
+use super::write_nested;
+use super::xml::escape_text;
+use crate::model::Document;
+use crate::render::Theme;
+
+const STYLE: &str = r#"
+body { margin: 0; font: 14px/1.4 ui-sans-serif, system-ui, -apple-system, "Segoe UI", sans-serif; background: var(--bg); color: var(--text); }
+header { position: sticky; top: 0; display: flex; gap: 8px; align-items: center; padding: 10px 16px; background: var(--bg); border-bottom: 1px solid var(--border); }
+header input { flex: 1; max-width: 320px; padding: 4px 8px; font: inherit; color: inherit; background: var(--surface); border: 1px solid var(--border); border-radius: 6px; }
+header button { font: inherit; color: inherit; background: var(--surface); border: 1px solid var(--border); border-radius: 6px; cursor: pointer; }
+#status { opacity: 0.6; }
+main { padding: 16px; }
+ul { list-style: none; margin: 0; padding-left: 22px; border-left: 1px solid var(--edge); }
+main > ul { padding-left: 0; border-left: none; }
+li { margin: 4px 0; }
+li.collapsed > ul { display: none; }
+.row { display: flex; align-items: flex-start; gap: 4px; }
+.toggle { width: 18px; padding: 0; flex: none; font: inherit; color: inherit; opacity: 0.6; background: none; border: none; cursor: pointer; }
+.toggle::before { content: "\25BE"; }
+li.collapsed > .row > .toggle::before { content: "\25B8"; }
+.leaf { width: 18px; flex: none; }
+.text { padding: 2px 10px; white-space: pre-wrap; background: var(--surface); border: 1px solid var(--border); border-radius: 8px; }
+.text.match { border-color: var(--text); font-weight: 600; }
+"#;
+
+const SCRIPT: &str = r#"
+const items = Array.from(document.querySelectorAll("main li"));
+const textOf = (li) => li.querySelector(":scope > .row > .text");
+const childrenOf = (li) => Array.from(li.querySelectorAll(":scope > ul > li"));
+for (const button of document.querySelectorAll(".toggle")) {
+  button.addEventListener("click", () => button.closest("li").classList.toggle("collapsed"));
+}
+document.getElementById("expand").addEventListener("click", () => {
+  for (const li of items) li.classList.remove("collapsed");
+});
+document.getElementById("collapse").addEventListener("click", () => {
+  for (const li of items) if (childrenOf(li).length > 0) li.classList.add("collapsed");
+});
+document.getElementById("search").addEventListener("input", (event) => {
+  const query = event.target.value.trim().toLowerCase();
+  const shown = new Set();
+  let matches = 0;
+  // Children come after their parent, so walking backwards settles them first.
+  for (const li of items.slice().reverse()) {
+    const hit = query !== "" && textOf(li).textContent.toLowerCase().includes(query);
+    textOf(li).classList.toggle("match", hit);
+    if (hit) matches += 1;
+    const childShown = childrenOf(li).some((child) => shown.has(child));
+    if (hit || childShown) shown.add(li);
+    li.hidden = query !== "" && !shown.has(li);
+    if (query !== "" && childShown) li.classList.remove("collapsed");
+  }
+  document.getElementById("status").textContent =
+    query === "" ? "" : matches === 1 ? "1 match" : `${matches} matches`;
+});
+"#;
+
+/// A single HTML page with the tree as nested lists, branches that collapse and expand, and
+/// a search box that narrows the tree to matching nodes and their ancestors. Styles and
+/// script are inline, so the file works on its own.
+pub fn export(doc: &Document, theme: Theme) -> String {
+    let palette = theme.palette();
+    let title = doc
+        .nodes
+        .get(&doc.root_id)
+        .map_or("", |root| root.text.as_str());
+    let mut tree = String::new();
+    write_nested(
+        doc,
+        &mut tree,
+        |out, _, node| {
+            let text = escape_text(&node.text);
+            if node.children_ids.is_empty() {
+                out.push_str(&format!(
+                    "<li><div class=\"row\"><span class=\"leaf\"></span><span class=\"text\">{text}</span></div></li>\n"
+                ));
+                return false;
+            }
+            out.push_str(&format!(
+                "<li><div class=\"row\"><button class=\"toggle\" type=\"button\"></button><span class=\"text\">{text}</span></div>\n<ul>\n"
+            ));
+            true
+        },
+        |out, _| out.push_str("</ul></li>\n"),
+    );
+
+    let mut out = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    out.push_str("<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n");
+    out.push_str(&format!("<title>{}</title>\n", escape_text(title)));
+    out.push_str(&format!(
+        "<style>\n:root {{ --bg: {}; --surface: {}; --border: {}; --edge: {}; --text: {}; }}{STYLE}</style>\n",
+        palette.background.hex(),
+        palette.node_fill.hex(),
+        palette.node_border.hex(),
+        palette.edge.hex(),
+        palette.text.hex(),
+    ));
+    out.push_str("</head>\n<body>\n<header>\n");
+    out.push_str("<input id=\"search\" type=\"search\" placeholder=\"Search\" autofocus>\n");
+    out.push_str("<button id=\"expand\" type=\"button\">Expand all</button>\n");
+    out.push_str("<button id=\"collapse\" type=\"button\">Collapse all</button>\n");
+    out.push_str("<span id=\"status\"></span>\n</header>\n");
+    out.push_str(&format!("<main>\n<ul>\n{tree}</ul>\n</main>\n"));
+    out.push_str(&format!("<script>{SCRIPT}</script>\n</body>\n</html>\n"));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::document;
+
+    #[test]
+    fn nests_escaped_nodes_in_a_self_contained_page() {
+        let doc = document(&[
+            ("root", "Plan & goals", &["a"]),
+            ("a", "<script>alert(1)</script>", &["b"]),
+            ("b", "Travel", &[]),
+        ]);
+        let html = export(&doc, Theme::Light);
+
+        assert!(html.contains("<title>Plan &amp; goals</title>"));
+        assert!(html.contains("--bg: #f7f8fb;"));
+        assert!(html.contains(
+            "<span class=\"text\">&lt;script&gt;alert(1)&lt;/script&gt;</span></div>\n<ul>\n<li><div class=\"row\"><span class=\"leaf\"></span><span class=\"text\">Travel</span></div></li>\n</ul></li>\n</ul></li>\n"
+        ));
+        assert_eq!(html.matches("<li>").count(), 3);
+        assert_eq!(html.matches("<script>").count(), 1);
+        assert!(!html.contains(" src=") && !html.contains(" href="));
+    }
+}
@@ -0,0 +1,258 @@
+use serde::Deserialize;
+
+use super::TreeBuilder;
+use crate::model::Document;
+
+const MAX_HEADING_LEVEL: usize = 6;
+const TAB_WIDTH: usize = 4;
+/// Where list items and plain lines start nesting on import: below every heading level,
+/// deeper by their indentation.
+const LIST_LEVEL: usize = MAX_HEADING_LEVEL + 1;
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct MarkdownOptions {
+    /// How many levels, counting the root, become headings (`#`, `##`, …); deeper nodes
+    /// become a bulleted list. Capped at 6, Markdown's deepest heading.
+    pub heading_depth: usize,
+}
+
+impl Default for MarkdownOptions {
+    fn default() -> Self {
+        MarkdownOptions { heading_depth: 1 }
+    }
+}
+
+/// Keeps text that starts like Markdown syntax (`#`, `-`, `1.`, …) from being read as such.
+/// A backslash before punctuation is doubled, so it is not taken for an escape on import.
+pub(super) fn escape_line(line: &str) -> String {
+    let mut escaped = String::with_capacity(line.len());
+    let mut chars = line.trim_start().chars().peekable();
+    while let Some(c) = chars.next() {
+        escaped.push(c);
+        if c == '\\' && chars.peek().is_some_and(char::is_ascii_punctuation) {
+            escaped.push('\\');
+        }
+    }
+    let trimmed = escaped.as_str();
+    let digits = trimmed.chars().take_while(char::is_ascii_digit).count();
+    if digits > 0 && matches!(trimmed[digits..].chars().next(), Some('.' | ')')) {
+        return format!("{}\\{}", &trimmed[..digits], &trimmed[digits..]);
+    }
+    if matches!(trimmed.chars().next(), Some('#' | '>' | '-' | '+' | '*')) {
+        return format!("\\{trimmed}");
+    }
+    trimmed.to_string()
+}
+
+pub fn export(doc: &Document, options: &MarkdownOptions) -> String {
+    let heading_depth = options.heading_depth.clamp(1, MAX_HEADING_LEVEL);
+    let mut out = String::new();
+    let mut in_list = false;
+    for (depth, node) in super::outline(doc) {
+        let lines: Vec<String> = node.text.lines().map(escape_line).collect();
+        if depth < heading_depth {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out.push_str(&format!("{} {}\n", "#".repeat(depth + 1), lines.join(" ")));
+            in_list = false;
+            continue;
+        }
+        if !in_list {
+            out.push('\n');
+            in_list = true;
+        }
+        let indent = "  ".repeat(depth - heading_depth);
+        let first = lines.first().map_or("", String::as_str);
+        out.push_str(&format!("{indent}- {first}\n"));
+        for line in lines.iter().skip(1) {
+            out.push_str(&format!("{indent}  {line}\n"));
+        }
+    }
+    out
+}
+
+/// The indentation of `line` in columns, tabs going to the next multiple of 4, and the
+/// rest of the line.
+fn split_indent(line: &str) -> (usize, &str) {
+    let mut width = 0;
+    for (index, c) in line.char_indices() {
+        match c {
+            ' ' => width += 1,
+            '\t' => width += TAB_WIDTH - width % TAB_WIDTH,
+            _ => return (width, &line[index..]),
+        }
+    }
+    (width, "")
+}
+
+fn heading(rest: &str) -> Option<(usize, &str)> {
+    let level = rest.chars().take_while(|c| *c == '#').count();
+    let text = &rest[level..];
+    if !(1..=MAX_HEADING_LEVEL).contains(&level) || !(text.is_empty() || text.starts_with([' ', '\t'])) {
+        return None;
+    }
+    Some((level, text.trim().trim_end_matches('#').trim_end()))
+}
+
+/// The text after a bullet (`-`, `*`, `+`) or number (`1.`, `1)`) marker.
+fn list_item(rest: &str) -> Option<&str> {
+    let digits = rest.chars().take_while(char::is_ascii_digit).count();
+    let marker_len = match rest[digits..].chars().next() {
+        Some('.' | ')') if (1..=9).contains(&digits) => digits + 1,
+        Some('-' | '*' | '+') if digits == 0 => 1,
+        _ => return None,
+    };
+    let text = &rest[marker_len..];
+    (text.is_empty() || text.starts_with([' ', '\t'])).then(|| text.trim())
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if let Some(escaped) = chars.next_if(char::is_ascii_punctuation) {
+                out.push(escaped);
+                continue;
+            }
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Reads Markdown headings and lists, or plain text indented with tabs and/or spaces.
+/// Headings nest by level, list items and plain lines by indentation beneath them. In
+/// Markdown, a line indented past the item above it continues that item's text.
+pub fn import(text: &str, title: &str, id_prefix: &str) -> TreeBuilder {
+    let lines: Vec<(usize, &str)> = text
+        .lines()
+        .map(split_indent)
+        .filter(|(_, rest)| !rest.trim().is_empty())
+        .collect();
+    let markdown = lines
+        .iter()
+        .any(|(indent, rest)| (*indent < TAB_WIDTH && heading(rest).is_some()) || list_item(rest).is_some());
+
+    let mut builder = TreeBuilder::new(id_prefix, title);
+    let mut open: Vec<(usize, String)> = Vec::new();
+    let mut last_item: Option<(usize, String)> = None;
+    for (indent, rest) in lines {
+        let mut is_heading = false;
+        let (level, text) = match (markdown, heading(rest), list_item(rest)) {
+            (true, Some((level, text)), _) if indent < TAB_WIDTH => {
+                is_heading = true;
+                (level, text)
+            }
+            (true, _, Some(text)) => (LIST_LEVEL + indent, text),
+            _ => {
+                if let Some((item_indent, id)) = &last_item {
+                    if markdown && indent > *item_indent {
+                        builder.append_text(id, &unescape(rest.trim_end()));
+                        continue;
+                    }
+                }
+                (LIST_LEVEL + indent, rest.trim_end())
+            }
+        };
+        while open.last().is_some_and(|(open_level, _)| *open_level >= level) {
+            open.pop();
+        }
+        let parent_id = open
+            .last()
+            .map_or_else(|| builder.root_id().to_string(), |(_, id)| id.clone());
+        let text = if markdown { unescape(text) } else { text.to_string() };
+        let id = builder.add_child(&parent_id, &text);
+        last_item = (!is_heading).then(|| (indent, id.clone()));
+        open.push((level, id));
+    }
+    builder.unwrap_single_child();
+    builder
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::document;
+
+    fn texts(doc: &Document) -> Vec<(usize, String)> {
+        super::super::outline(doc)
+            .into_iter()
+            .map(|(depth, node)| (depth, node.text.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn renders_headings_down_to_the_depth_and_bullets_below() {
+        let doc = document(&[
+            ("root", "Plan", &["a", "b"]),
+            ("a", "Budget", &["c"]),
+            ("b", "# of hires", &[]),
+            ("c", "Travel\nand lodging", &["d"]),
+            ("d", "1. Flights", &[]),
+        ]);
+
+        let flat = export(&doc, &MarkdownOptions::default());
+        assert_eq!(
+            flat,
+            "# Plan\n\n- Budget\n  - Travel\n    and lodging\n    - 1\\. Flights\n- \\# of hires\n"
+        );
+        let headed = export(&doc, &MarkdownOptions { heading_depth: 2 });
+        assert_eq!(
+            headed,
+            "# Plan\n\n## Budget\n\n- Travel\n  and lodging\n  - 1\\. Flights\n\n## \\# of hires\n"
+        );
+
+        for text in [flat, headed] {
+            let imported = import(&text, "Imported", "md").finish("md");
+            assert_eq!(texts(&imported), texts(&doc));
+        }
+    }
+
+    #[test]
+    fn imports_indented_text_and_wraps_several_top_level_items() {
+        let text = "Budget\n\tTravel\n    \tFlights\n  Hotels\nSchedule\n";
+        let doc = import(text, "Notes", "txt").finish("txt");
+        assert_eq!(
+            texts(&doc),
+            vec![
+                (0, "Notes".to_string()),
+                (1, "Budget".to_string()),
+                (2, "Travel".to_string()),
+                (3, "Flights".to_string()),
+                (2, "Hotels".to_string()),
+                (1, "Schedule".to_string()),
+            ]
+        );
+
+        let doc = import("1. Goals\n2) Risks\n   * Budget\n", "List", "md").finish("md");
+        assert_eq!(
+            texts(&doc),
+            vec![
+                (0, "List".to_string()),
+                (1, "Goals".to_string()),
+                (1, "Risks".to_string()),
+                (2, "Budget".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn backslashes_survive_a_round_trip() {
+        let doc = document(&[
+            ("root", "C:\\*.txt", &["a", "b", "c"]),
+            ("a", "a\\_b", &[]),
+            ("b", "\\# not a heading \\", &[]),
+            ("c", "C:\\temp\\\\share", &[]),
+        ]);
+        let text = export(&doc, &MarkdownOptions::default());
+        assert_eq!(
+            text,
+            "# C:\\\\*.txt\n\n- a\\\\_b\n- \\\\# not a heading \\\n- C:\\temp\\\\\\share\n"
+        );
+        let imported = import(&text, "Imported", "md").finish("md");
+        assert_eq!(texts(&imported), texts(&doc));
+    }
+}
@@ -0,0 +1,54 @@
+use super::outline;
+use crate::model::Document;
+
+/// For a quoted Mermaid label. `#` starts an entity code (`#quot;`, `#35;`), so it is escaped
+/// first; line breaks become `<br>`.
+fn escape_label(text: &str) -> String {
+    let escaped = text
+        .replace('#', "#35;")
+        .replace('"', "#quot;")
+        .replace('<', "#lt;")
+        .replace('>', "#gt;");
+    let lines: Vec<&str> = escaped.lines().collect();
+    match lines.join("<br>") {
+        label if label.trim().is_empty() => " ".to_string(),
+        label => label,
+    }
+}
+
+/// A Mermaid `mindmap`: nesting is indentation, the root is drawn as a circle and the rest
+/// as rounded boxes. Nodes get short ids of their own, since document ids may hold
+/// characters Mermaid does not accept.
+pub fn export(doc: &Document) -> String {
+    let mut out = String::from("mindmap\n");
+    for (index, (depth, node)) in outline(doc).into_iter().enumerate() {
+        let indent = "  ".repeat(depth + 1);
+        let label = escape_label(&node.text);
+        if depth == 0 {
+            out.push_str(&format!("{indent}n{index}((\"{label}\"))\n"));
+        } else {
+            out.push_str(&format!("{indent}n{index}(\"{label}\")\n"));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::document;
+
+    #[test]
+    fn indents_children_and_escapes_labels() {
+        let doc = document(&[
+            ("root", "Plan", &["a", "b"]),
+            ("a", "Say \"hi\" #1\n<b>", &["c"]),
+            ("b", "", &[]),
+            ("c", "Travel", &[]),
+        ]);
+        assert_eq!(
+            export(&doc),
+            "mindmap\n  n0((\"Plan\"))\n    n1(\"Say #quot;hi#quot; #35;1<br>#lt;b#gt;\")\n      n2(\"Travel\")\n    n3(\" \")\n"
+        );
+    }
+}
@@ -0,0 +1,297 @@
+mod canvas;
+mod dot;
+mod freemind;
+mod html;
+mod markdown;
+mod mermaid;
+mod opml;
+mod org;
+mod slides;
+mod xml;
+
+pub use markdown::MarkdownOptions;
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::model::{Document, Node, UndoTree};
+use crate::render::png::PngOptions;
+use crate::render::pdf::PdfOptions;
+use crate::render::{self, Theme};
+
+/// What `export_document` writes, with the format's own options.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(
+    tag = "kind",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum ExportFormat {
+    Markdown {
+        #[serde(flatten)]
+        options: MarkdownOptions,
+    },
+    Opml,
+    Freemind,
+    Mermaid,
+    Dot,
+    Svg {
+        #[serde(default)]
+        theme: Theme,
+    },
+    Png {
+        #[serde(flatten)]
+        options: PngOptions,
+    },
+    Pdf {
+        #[serde(flatten)]
+        options: PdfOptions,
+    },
+    Html {
+        #[serde(default)]
+        theme: Theme,
+    },
+    Canvas,
+    Org,
+    Marp,
+    Reveal {
+        #[serde(default)]
+        theme: Theme,
+    },
+}
+
+/// The export format a file name asks for, with default options. Marp and reveal.js decks
+/// share their extensions with Markdown and HTML, so they are only reachable from the app.
+pub fn format_for_extension(extension: &str) -> Result<ExportFormat, String> {
+    Ok(match extension.to_ascii_lowercase().as_str() {
+        "md" | "markdown" => ExportFormat::Markdown {
+            options: MarkdownOptions::default(),
+        },
+        "opml" => ExportFormat::Opml,
+        "mm" => ExportFormat::Freemind,
+        "mmd" | "mermaid" => ExportFormat::Mermaid,
+        "dot" | "gv" => ExportFormat::Dot,
+        "svg" => ExportFormat::Svg {
+            theme: Theme::default(),
+        },
+        "png" => ExportFormat::Png {
+            options: PngOptions::default(),
+        },
+        "pdf" => ExportFormat::Pdf {
+            options: PdfOptions::default(),
+        },
+        "html" | "htm" => ExportFormat::Html {
+            theme: Theme::default(),
+        },
+        "canvas" => ExportFormat::Canvas,
+        "org" => ExportFormat::Org,
+        other => return Err(format!("cannot export .{other} files")),
+    })
+}
+
+pub fn export(doc: &Document, format: &ExportFormat) -> Result<Vec<u8>, String> {
+    match format {
+        ExportFormat::Markdown { options } => Ok(markdown::export(doc, options).into_bytes()),
+        ExportFormat::Opml => Ok(opml::export(doc).into_bytes()),
+        ExportFormat::Freemind => Ok(freemind::export(doc).into_bytes()),
+        ExportFormat::Mermaid => Ok(mermaid::export(doc).into_bytes()),
+        ExportFormat::Dot => Ok(dot::export(doc).into_bytes()),
+        ExportFormat::Svg { theme } => Ok(render::svg::render(doc, *theme).into_bytes()),
+        ExportFormat::Png { options } => render::png::render(doc, options),
+        ExportFormat::Pdf { options } => render::pdf::render(doc, options),
+        ExportFormat::Html { theme } => Ok(html::export(doc, *theme).into_bytes()),
+        ExportFormat::Canvas => Ok(canvas::export(doc)?.into_bytes()),
+        ExportFormat::Org => Ok(org::export(doc).into_bytes()),
+        ExportFormat::Marp => Ok(slides::export_marp(doc).into_bytes()),
+        ExportFormat::Reveal { theme } => Ok(slides::export_reveal(doc, *theme).into_bytes()),
+    }
+}
+
+/// Every node reachable from the root, depth first in `children_ids` order, with its depth
+/// (the root is 0). Children that do not exist or were already visited are skipped, so a
+/// damaged tree still exports as far as it goes.
+pub fn outline(doc: &Document) -> Vec<(usize, &Node)> {
+    let mut nodes = Vec::new();
+    let mut seen = HashSet::new();
+    let mut stack: Vec<(usize, &String)> = vec![(0, &doc.root_id)];
+    while let Some((depth, id)) = stack.pop() {
+        let Some(node) = doc.nodes.get(id) else {
+            continue;
+        };
+        if !seen.insert(id) {
+            continue;
+        }
+        nodes.push((depth, node));
+        stack.extend(node.children_ids.iter().rev().map(|child| (depth + 1, child)));
+    }
+    nodes
+}
+
+/// Writes `outline` as nested elements without recursing, so a very deep tree cannot
+/// overflow the stack. `open` writes a node and returns whether it was left open for its
+/// children; `close` ends it after the last of them.
+pub fn write_nested<'a>(
+    doc: &'a Document,
+    out: &mut String,
+    mut open: impl FnMut(&mut String, usize, &'a Node) -> bool,
+    mut close: impl FnMut(&mut String, usize),
+) {
+    let mut open_depths: Vec<usize> = Vec::new();
+    for (depth, node) in outline(doc) {
+        while let Some(&open_depth) = open_depths.last().filter(|&&d| d >= depth) {
+            close(out, open_depth);
+            open_depths.pop();
+        }
+        if open(out, depth, node) {
+            open_depths.push(depth);
+        }
+    }
+    while let Some(open_depth) = open_depths.pop() {
+        close(out, open_depth);
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Imported {
+    pub document: Document,
+    /// What the file had that a document cannot hold and was left out.
+    pub warnings: Vec<String>,
+}
+
+/// Reads an outline file into a new document, choosing the reader by file extension.
+/// `id_prefix` becomes the document id and starts every fresh node id; `title` names the
+/// root when the file has several top-level items or no title of its own.
+pub fn import(
+    text: &str,
+    extension: &str,
+    title: &str,
+    id_prefix: &str,
+) -> Result<Imported, String> {
+    let mut builder = match extension.to_ascii_lowercase().as_str() {
+        "md" | "markdown" | "txt" | "text" => markdown::import(text, title, id_prefix),
+        "opml" => opml::import(text, title, id_prefix)?,
+        "mm" => freemind::import(text, title, id_prefix)?,
+        "canvas" => canvas::import(text, title, id_prefix)?,
+        "org" => org::import(text, title, id_prefix),
+        other => return Err(format!("cannot import .{other} files")),
+    };
+    let warnings = std::mem::take(&mut builder.warnings);
+    Ok(Imported {
+        document: builder.finish(id_prefix),
+        warnings,
+    })
+}
+
+/// Assembles a document top-down, for the importers. Node ids are fresh unless the file
+/// has usable ones.
+pub struct TreeBuilder {
+    id_prefix: String,
+    root_id: String,
+    nodes: HashMap<String, Node>,
+    next_id: usize,
+    warnings: Vec<String>,
+}
+
+impl TreeBuilder {
+    pub fn new(id_prefix: &str, root_text: &str) -> Self {
+        let root_id = format!("{id_prefix}-0");
+        let root = Node {
+            id: root_id.clone(),
+            text: root_text.to_string(),
+            parent_id: None,
+            children_ids: Vec::new(),
+        };
+        TreeBuilder {
+            id_prefix: id_prefix.to_string(),
+            root_id: root_id.clone(),
+            nodes: HashMap::from([(root_id, root)]),
+            next_id: 1,
+            warnings: Vec::new(),
+        }
+    }
+
+    pub fn root_id(&self) -> &str {
+        &self.root_id
+    }
+
+    pub fn set_text(&mut self, node_id: &str, text: &str) {
+        if let Some(node) = self.nodes.get_mut(node_id) {
+            node.text = text.to_string();
+        }
+    }
+
+    pub fn append_text(&mut self, node_id: &str, line: &str) {
+        if let Some(node) = self.nodes.get_mut(node_id) {
+            node.text.push('\n');
+            node.text.push_str(line);
+        }
+    }
+
+    pub fn warn(&mut self, warning: String) {
+        self.warnings.push(warning);
+    }
+
+    /// Adds a last child under `parent_id` and returns its id.
+    pub fn add_child(&mut self, parent_id: &str, text: &str) -> String {
+        self.add_child_with_id(parent_id, None, text)
+    }
+
+    /// Like `add_child`, keeping `preferred_id` when it is not empty and not taken yet.
+    pub fn add_child_with_id(
+        &mut self,
+        parent_id: &str,
+        preferred_id: Option<&str>,
+        text: &str,
+    ) -> String {
+        let id = match preferred_id {
+            Some(id) if !id.is_empty() && !self.nodes.contains_key(id) => id.to_string(),
+            _ => loop {
+                let id = format!("{}-{}", self.id_prefix, self.next_id);
+                self.next_id += 1;
+                if !self.nodes.contains_key(&id) {
+                    break id;
+                }
+            },
+        };
+        self.nodes.insert(
+            id.clone(),
+            Node {
+                id: id.clone(),
+                text: text.to_string(),
+                parent_id: Some(parent_id.to_string()),
+                children_ids: Vec::new(),
+            },
+        );
+        if let Some(parent) = self.nodes.get_mut(parent_id) {
+            parent.children_ids.push(id.clone());
+        }
+        id
+    }
+
+    /// For readers that wrap top-level items in a synthetic root: when there turned out to
+    /// be only one, it becomes the root itself.
+    pub fn unwrap_single_child(&mut self) {
+        let children = &self.nodes[&self.root_id].children_ids;
+        if let [only] = &children[..] {
+            let only = only.clone();
+            self.nodes.remove(&self.root_id);
+            if let Some(node) = self.nodes.get_mut(&only) {
+                node.parent_id = None;
+            }
+            self.root_id = only;
+        }
+    }
+
+    pub fn finish(self, doc_id: &str) -> Document {
+        Document {
+            id: doc_id.to_string(),
+            root_id: self.root_id.clone(),
+            cursor_id: self.root_id,
+            nodes: self.nodes,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            undo_tree: UndoTree::default(),
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+use super::xml::{attribute, escape_attr, escape_text, push_text};
+use super::{write_nested, TreeBuilder};
+use crate::model::Document;
+
+/// OPML 2.0: the root's text is the `<head><title>`, and its children are the top-level
+/// `<outline>` elements of `<body>`.
+pub fn export(doc: &Document) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<opml version=\"2.0\">\n");
+    out.push_str("  <head>\n");
+    let title = doc.nodes.get(&doc.root_id).map_or("", |root| root.text.as_str());
+    out.push_str(&format!("    <title>{}</title>\n", escape_text(title)));
+    out.push_str("  </head>\n  <body>\n");
+    // The root is the title, so its children start the body.
+    write_nested(
+        doc,
+        &mut out,
+        |out, depth, node| {
+            if depth == 0 {
+                return false;
+            }
+            let indent = "  ".repeat(depth + 1);
+            let text = escape_attr(&node.text);
+            if node.children_ids.is_empty() {
+                out.push_str(&format!("{indent}<outline text=\"{text}\"/>\n"));
+                return false;
+            }
+            out.push_str(&format!("{indent}<outline text=\"{text}\">\n"));
+            true
+        },
+        |out, depth| out.push_str(&format!("{}</outline>\n", "  ".repeat(depth + 1))),
+    );
+    out.push_str("  </body>\n</opml>\n");
+    out
+}
+
+/// `title` stands in for a missing or empty `<title>`. Attributes other than `text` are not
+/// kept.
+pub fn import(text: &str, title: &str, id_prefix: &str) -> Result<TreeBuilder, String> {
+    let mut reader = Reader::from_str(text);
+    let mut builder = TreeBuilder::new(id_prefix, title);
+    let mut open: Vec<String> = vec![builder.root_id().to_string()];
+    let mut in_title = false;
+    let mut head_title = String::new();
+    loop {
+        let event = reader.read_event().map_err(|e| e.to_string())?;
+        match &event {
+            Event::Start(element) | Event::Empty(element) => {
+                match element.local_name().as_ref() {
+                    b"outline" => {
+                        let text = attribute(element, "text")?.unwrap_or_default();
+                        let parent_id = open.last().cloned().unwrap_or_default();
+                        let id = builder.add_child(&parent_id, &text);
+                        if matches!(event, Event::Start(_)) {
+                            open.push(id);
+                        }
+                    }
+                    b"title" => in_title = matches!(event, Event::Start(_)),
+                    _ => {}
+                }
+            }
+            Event::End(element) => match element.local_name().as_ref() {
+                b"outline" if open.len() > 1 => {
+                    open.pop();
+                }
+                b"title" => in_title = false,
+                _ => {}
+            },
+            Event::Eof => break,
+            _ if in_title => push_text(&mut head_title, &event)?,
+            _ => {}
+        }
+    }
+    if !head_title.trim().is_empty() {
+        let root_id = builder.root_id().to_string();
+        builder.set_text(&root_id, head_title.trim());
+    }
+    Ok(builder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_text_and_nesting() {
+        let opml = "<?xml version=\"1.0\"?>\n<opml version=\"2.0\"><head><title>Plan &amp; goals</title></head>\n<body>\n<outline text=\"Budget\" _note=\"ignored\"><outline text=\"Travel&#10;&lt;2 trips&gt;\"/></outline>\n<outline text=\"Team\"></outline>\n</body></opml>";
+        let doc = import(opml, "file", "opml").unwrap().finish("opml");
+        let tree: Vec<(usize, &str)> = super::super::outline(&doc)
+            .into_iter()
+            .map(|(depth, node)| (depth, node.text.as_str()))
+            .collect();
+        assert_eq!(
+            tree,
+            vec![
+                (0, "Plan & goals"),
+                (1, "Budget"),
+                (2, "Travel\n<2 trips>"),
+                (1, "Team"),
+            ]
+        );
+
+        let exported = export(&doc);
+        assert!(exported.contains("<outline text=\"Travel&#10;&lt;2 trips&gt;\"/>"));
+        let again = import(&exported, "file", "opml").unwrap().finish("opml");
+        let texts = |doc: &Document| -> Vec<(usize, String)> {
+            super::super::outline(doc)
+                .into_iter()
+                .map(|(depth, node)| (depth, node.text.clone()))
+                .collect()
+        };
+        assert_eq!(texts(&again), texts(&doc));
+    }
+}
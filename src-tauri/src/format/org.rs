@@ -0,0 +1,292 @@
+use std::collections::BTreeSet;
+
+use super::TreeBuilder;
+use crate::model::Document;
+
+/// Org-mode: the root is `#+TITLE:` and every other node a heading, one star per level. A
+/// heading is the first line of the node's text, so TODO keywords, priorities and tags in
+/// it are written as they are; the other lines become the heading's body, indented to line
+/// up with its text so none of them reads as a heading.
+pub fn export(doc: &Document) -> String {
+    let mut out = String::new();
+    for (depth, node) in super::outline(doc) {
+        let mut lines = node.text.split('\n');
+        let first = lines.next().unwrap_or_default();
+        if depth == 0 {
+            out.push_str(format!("#+TITLE: {first}").trim_end());
+        } else {
+            out.push_str(&format!("{} {first}", "*".repeat(depth)));
+        }
+        out.push('\n');
+        let indent = " ".repeat(depth + 1);
+        let lines: Vec<&str> = lines.collect();
+        for (index, line) in lines.iter().enumerate() {
+            if !line.trim().is_empty() {
+                out.push_str(&indent);
+                if needs_comma(line, &lines[index + 1..]) {
+                    let text = line.trim_start_matches(' ');
+                    out.push_str(&line[..line.len() - text.len()]);
+                    out.push(',');
+                    out.push_str(text);
+                } else {
+                    out.push_str(line);
+                }
+            }
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Body lines that would read as a keyword or block line (`#+BEGIN_SRC`), or open a drawer
+/// that a later line closes, get Org's escaping comma in front of their text. Lines that
+/// already start with commas get one more, so `import` can always take one away.
+fn needs_comma(line: &str, later: &[&str]) -> bool {
+    let text = line.trim_start_matches(' ');
+    let rest = text.trim_start_matches(',');
+    rest.starts_with("#+")
+        || (is_drawer(rest)
+            && (rest.len() < text.len()
+                || later
+                    .iter()
+                    .any(|line| line.trim().eq_ignore_ascii_case(":END:"))))
+}
+
+/// A body line without the comma `export` put before it.
+fn unescape(body: &str) -> String {
+    let text = body.trim_start_matches(' ');
+    let rest = text.trim_start_matches(',');
+    if rest.len() < text.len() && (rest.starts_with("#+") || is_drawer(rest)) {
+        format!("{}{}", &body[..body.len() - text.len()], &text[1..])
+    } else {
+        body.to_string()
+    }
+}
+
+/// The level and the rest of a heading line: stars at the start of the line, then a space
+/// or nothing.
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|c| *c == '*').count();
+    let rest = &line[level..];
+    (level > 0 && (rest.is_empty() || rest.starts_with([' ', '\t']))).then_some((level, rest))
+}
+
+/// `:work:urgent:` at the end of a heading.
+fn is_tags(word: &str) -> bool {
+    word.len() > 2
+        && word.starts_with(':')
+        && word.ends_with(':')
+        && word[1..word.len() - 1].split(':').all(|tag| {
+            !tag.is_empty()
+                && tag
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || matches!(c, '_' | '@' | '#' | '%'))
+        })
+}
+
+/// The heading's text with tags kept but not the spaces Org puts before them to align them.
+fn heading_text(rest: &str) -> String {
+    let text = rest.trim();
+    match text.rsplit_once([' ', '\t']) {
+        Some((before, tags)) if is_tags(tags) => format!("{} {tags}", before.trim_end()),
+        _ => text.to_string(),
+    }
+}
+
+/// The key of an `#+KEY: value` line, and the value.
+fn keyword(line: &str) -> Option<(&str, &str)> {
+    let (key, value) = line.trim_start().strip_prefix("#+")?.split_once(':')?;
+    (!key.is_empty() && !key.contains(char::is_whitespace)).then(|| (key, value.trim()))
+}
+
+/// `:PROPERTIES:`, `:LOGBOOK:` and other drawer openings.
+fn is_drawer(line: &str) -> bool {
+    let line = line.trim();
+    line.len() > 2
+        && line.starts_with(':')
+        && line.ends_with(':')
+        && line[1..line.len() - 1]
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-'))
+}
+
+/// Headings nest by their stars; lines up to the next heading are the heading's body and
+/// continue its text, less the indentation and escaping commas `export` adds. Without `#+TITLE:` the root is
+/// named `title`, unless the file has just one top-level heading, which becomes the root.
+/// Drawers and keywords other than the title are left out with a warning.
+pub fn import(text: &str, title: &str, id_prefix: &str) -> TreeBuilder {
+    let mut builder = TreeBuilder::new(id_prefix, title);
+    let mut open: Vec<(usize, String)> = vec![(0, builder.root_id().to_string())];
+    let mut has_title = false;
+    let mut has_preamble = false;
+    let mut in_drawer = false;
+    let mut drawers = 0;
+    let mut keywords = BTreeSet::new();
+    // Blank lines are only kept when more body text follows them.
+    let mut blank_lines = 0;
+    let lines: Vec<&str> = text.lines().collect();
+    for (index, line) in lines.iter().copied().enumerate() {
+        if in_drawer {
+            in_drawer = !line.trim().eq_ignore_ascii_case(":END:");
+            continue;
+        }
+        if let Some((level, rest)) = heading(line) {
+            while open.len() > 1
+                && open
+                    .last()
+                    .is_some_and(|(open_level, _)| *open_level >= level)
+            {
+                open.pop();
+            }
+            let parent_id = open.last().map(|(_, id)| id.clone()).unwrap_or_default();
+            let id = builder.add_child(&parent_id, &heading_text(rest));
+            open.push((level, id));
+            blank_lines = 0;
+            continue;
+        }
+        let (level, id) = open.last().cloned().unwrap_or_default();
+        if let Some((key, value)) = keyword(line) {
+            if level == 0 && key.eq_ignore_ascii_case("TITLE") && !value.is_empty() {
+                builder.set_text(&id, value);
+                has_title = true;
+            } else if !key.eq_ignore_ascii_case("TITLE") {
+                keywords.insert(format!("#+{}", key.to_ascii_uppercase()));
+            }
+            continue;
+        }
+        // Only with its `:END:` before the next heading: otherwise it is text like `:tada:`.
+        let closed = || {
+            lines[index + 1..]
+                .iter()
+                .take_while(|line| heading(line).is_none())
+                .any(|line| line.trim().eq_ignore_ascii_case(":END:"))
+        };
+        if is_drawer(line) && closed() {
+            in_drawer = true;
+            drawers += 1;
+            continue;
+        }
+        if line.trim().is_empty() {
+            blank_lines += 1;
+            continue;
+        }
+        let indent = line.len() - line.trim_start_matches(' ').len();
+        let body = &line[indent.min(level + 1)..];
+        for _ in 0..std::mem::take(&mut blank_lines) {
+            builder.append_text(&id, "");
+        }
+        builder.append_text(&id, &unescape(body.trim_end()));
+        has_preamble |= level == 0;
+    }
+    if drawers > 0 {
+        builder.warn(match drawers {
+            1 => "ignored 1 drawer".to_string(),
+            n => format!("ignored {n} drawers"),
+        });
+    }
+    for key in keywords {
+        builder.warn(format!("ignored {key}"));
+    }
+    if !has_title && !has_preamble {
+        builder.unwrap_single_child();
+    }
+    builder
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Files in the form `export` writes: importing and exporting them changes nothing.
+    const CANONICAL: [(&str, &str); 2] = [
+        (
+            "plan.org",
+            include_str!("../../tests/fixtures/org/plan.org"),
+        ),
+        (
+            "tasks.org",
+            include_str!("../../tests/fixtures/org/tasks.org"),
+        ),
+    ];
+    const NOTES: &str = include_str!("../../tests/fixtures/org/notes.org");
+
+    fn texts(doc: &Document) -> Vec<(usize, String)> {
+        super::super::outline(doc)
+            .into_iter()
+            .map(|(depth, node)| (depth, node.text.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn round_trips_sample_files() {
+        for (name, text) in CANONICAL {
+            let doc = import(text, name, "org").finish("org");
+            assert_eq!(export(&doc), text, "{name}");
+        }
+
+        let doc = import(CANONICAL[0].1, "plan", "org").finish("org");
+        let tree = texts(&doc);
+        assert_eq!(
+            tree[..3],
+            [
+                (
+                    0,
+                    "Q3 plan\nGoals for the quarter, agreed in the kickoff.".to_string()
+                ),
+                (
+                    1,
+                    "TODO [#A] Budget :finance:\nNumbers are in the shared sheet.\n\nAsk for approval by Friday."
+                        .to_string()
+                ),
+                (2, "DONE Travel :finance:trips:".to_string()),
+            ]
+        );
+        assert_eq!(tree[9], (2, "* not a heading".to_string()));
+        assert_eq!(tree[10], (1, String::new()));
+
+        let doc = import(CANONICAL[1].1, "tasks", "org").finish("org");
+        assert_eq!(
+            texts(&doc).last().unwrap().1,
+            "Snippets\n#+BEGIN_SRC sh\nmake release\n#+END_SRC\n:note:\nCheck the changelog first.\n:END:"
+        );
+    }
+
+    #[test]
+    fn normalizes_other_files_and_warns_about_what_it_drops() {
+        let mut builder = import(NOTES, "notes", "org");
+        assert_eq!(
+            std::mem::take(&mut builder.warnings),
+            vec!["ignored 1 drawer", "ignored #+AUTHOR", "ignored #+STARTUP"]
+        );
+        let doc = builder.finish("org");
+        assert_eq!(
+            texts(&doc),
+            vec![
+                (0, "Notes".to_string()),
+                (
+                    1,
+                    "TODO Call the venue :phone:work:\nAsk about parking.".to_string()
+                ),
+                (
+                    1,
+                    "Ideas\n:tada:\n*bold* text is not a heading\n- list items stay text".to_string()
+                ),
+            ]
+        );
+
+        let again = import(&export(&doc), "notes", "org").finish("org");
+        assert_eq!(texts(&again), texts(&doc));
+    }
+
+    #[test]
+    fn lines_with_commas_of_their_own_keep_them() {
+        let doc = crate::test_support::document(&[
+            ("root", "Notes", &["a"]),
+            ("a", "Code\n,#+BEGIN_SRC\n,:tada:\n  #+indented\n:END:", &[]),
+        ]);
+        let text = export(&doc);
+        assert!(text.contains("\n  ,,#+BEGIN_SRC\n  ,,:tada:\n    ,#+indented\n"));
+        let again = import(&text, "notes", "org").finish("org");
+        assert_eq!(texts(&again), texts(&doc));
+    }
+}
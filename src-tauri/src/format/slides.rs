@@ -0,0 +1,176 @@
+use super::markdown::escape_line;
+use super::outline;
+use super::xml::escape_text;
+use crate::model::Document;
+use crate::render::Theme;
+
+/// Loaded from a CDN, so the deck is one file but needs a network connection to show.
+const REVEAL_URL: &str = "https://cdn.jsdelivr.net/npm/reveal.js@5.1.0/dist";
+
+/// Offline the slides are plain sections; this says why instead of leaving a bare page.
+const OFFLINE_FALLBACK: &str = r#"<script>
+if (window.Reveal) {
+  Reveal.initialize({ hash: true });
+} else {
+  const note = document.createElement("p");
+  note.textContent = "reveal.js could not be loaded from cdn.jsdelivr.net. Open this file with an internet connection to present it.";
+  document.body.prepend(note);
+}
+</script>
+"#;
+
+/// A Marp deck: the root is a centred title slide, each depth-1 node a slide headed by its
+/// first line, and deeper nodes nested bullets on their slide.
+pub fn export_marp(doc: &Document) -> String {
+    let mut out = String::from("---\nmarp: true\npaginate: true\n---\n");
+    let mut in_list = false;
+    for (depth, node) in outline(doc) {
+        let lines: Vec<String> = node.text.lines().map(escape_line).collect();
+        let first = lines.first().map_or("", String::as_str);
+        if depth < 2 {
+            if depth == 0 {
+                out.push_str(&format!("\n<!-- _class: lead -->\n\n# {first}\n"));
+            } else {
+                out.push_str(&format!("\n---\n\n## {first}\n"));
+            }
+            if lines.len() > 1 {
+                out.push('\n');
+                out.push_str(&lines[1..].join("  \n"));
+                out.push('\n');
+            }
+            in_list = false;
+            continue;
+        }
+        if !in_list {
+            out.push('\n');
+            in_list = true;
+        }
+        let indent = "  ".repeat(depth - 2);
+        out.push_str(&format!("{indent}- {first}\n"));
+        for line in lines.iter().skip(1) {
+            out.push_str(&format!("{indent}  {line}\n"));
+        }
+    }
+    out
+}
+
+/// Line breaks in a node's text as `<br>`.
+fn html_lines(lines: &[&str]) -> String {
+    lines
+        .iter()
+        .map(|line| escape_text(line))
+        .collect::<Vec<_>>()
+        .join("<br>")
+}
+
+/// The same slides as `export_marp` in a single reveal.js page, in the reveal theme closest
+/// to the editor's.
+pub fn export_reveal(doc: &Document, theme: Theme) -> String {
+    let reveal_theme = match theme {
+        Theme::Dark => "black",
+        Theme::Light => "white",
+        Theme::TokyoNight => "moon",
+    };
+    let title = doc
+        .nodes
+        .get(&doc.root_id)
+        .map_or("", |root| root.text.as_str());
+    let mut out = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    out.push_str(&format!(
+        "<title>{}</title>\n",
+        escape_text(title.lines().next().unwrap_or_default())
+    ));
+    out.push_str(&format!(
+        "<link rel=\"stylesheet\" href=\"{REVEAL_URL}/reveal.css\">\n"
+    ));
+    out.push_str(&format!(
+        "<link rel=\"stylesheet\" href=\"{REVEAL_URL}/theme/{reveal_theme}.css\">\n"
+    ));
+    out.push_str("</head>\n<body>\n<div class=\"reveal\">\n<div class=\"slides\">\n");
+
+    // How deep the bullet list of the current slide is open.
+    let mut level = 0;
+    let close_list = |level: &mut usize, out: &mut String| {
+        if *level > 0 {
+            out.push_str("</li>\n");
+            for _ in 1..*level {
+                out.push_str("</ul></li>\n");
+            }
+            out.push_str("</ul>\n");
+            *level = 0;
+        }
+    };
+    let mut in_slide = false;
+    for (depth, node) in outline(doc) {
+        let lines: Vec<&str> = node.text.lines().collect();
+        if depth < 2 {
+            close_list(&mut level, &mut out);
+            if in_slide {
+                out.push_str("</section>\n");
+            }
+            in_slide = true;
+            let tag = if depth == 0 { "h1" } else { "h2" };
+            let first = lines.first().copied().unwrap_or_default();
+            out.push_str(&format!(
+                "<section>\n<{tag}>{}</{tag}>\n",
+                escape_text(first)
+            ));
+            if lines.len() > 1 {
+                out.push_str(&format!("<p>{}</p>\n", html_lines(&lines[1..])));
+            }
+            continue;
+        }
+        let item_level = depth - 1;
+        if item_level > level {
+            out.push_str("<ul>\n");
+        } else {
+            out.push_str("</li>\n");
+            while level > item_level {
+                out.push_str("</ul></li>\n");
+                level -= 1;
+            }
+        }
+        level = item_level;
+        out.push_str(&format!("<li>{}", html_lines(&lines)));
+    }
+    close_list(&mut level, &mut out);
+    if in_slide {
+        out.push_str("</section>\n");
+    }
+    out.push_str("</div>\n</div>\n");
+    out.push_str(&format!(
+        "<script src=\"{REVEAL_URL}/reveal.js\"></script>\n"
+    ));
+    out.push_str(OFFLINE_FALLBACK);
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::document;
+
+    #[test]
+    fn makes_a_slide_per_first_level_node() {
+        let doc = document(&[
+            ("root", "Q3 review\nTeam meeting", &["a", "b"]),
+            ("a", "Budget", &["c", "e"]),
+            ("b", "Next <steps>", &[]),
+            ("c", "Travel", &["d"]),
+            ("d", "# of trips", &[]),
+            ("e", "Hotels\nand food", &[]),
+        ]);
+
+        assert_eq!(
+            export_marp(&doc),
+            "---\nmarp: true\npaginate: true\n---\n\n<!-- _class: lead -->\n\n# Q3 review\n\nTeam meeting\n\n---\n\n## Budget\n\n- Travel\n  - \\# of trips\n- Hotels\n  and food\n\n---\n\n## Next <steps>\n"
+        );
+
+        let html = export_reveal(&doc, Theme::Light);
+        assert!(html.contains("/theme/white.css\">"));
+        assert!(html.contains(
+            "<section>\n<h1>Q3 review</h1>\n<p>Team meeting</p>\n</section>\n<section>\n<h2>Budget</h2>\n<ul>\n<li>Travel<ul>\n<li># of trips</li>\n</ul></li>\n<li>Hotels<br>and food</li>\n</ul>\n</section>\n<section>\n<h2>Next &lt;steps&gt;</h2>\n</section>\n"
+        ));
+    }
+}
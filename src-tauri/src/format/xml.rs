@@ -0,0 +1,47 @@
+use quick_xml::escape::{escape, resolve_predefined_entity};
+use quick_xml::events::{BytesStart, Event};
+
+/// For attribute values. Line breaks and tabs are written as character references, since a
+/// parser would otherwise turn them into spaces.
+pub fn escape_attr(text: &str) -> String {
+    escape(text)
+        .replace('\n', "&#10;")
+        .replace('\r', "&#13;")
+        .replace('\t', "&#9;")
+}
+
+pub fn escape_text(text: &str) -> String {
+    escape(text).into_owned()
+}
+
+pub fn attribute(element: &BytesStart, name: &str) -> Result<Option<String>, String> {
+    let Some(attr) = element
+        .try_get_attribute(name)
+        .map_err(|e| e.to_string())?
+    else {
+        return Ok(None);
+    };
+    let value = attr.unescape_value().map_err(|e| e.to_string())?;
+    Ok(Some(value.into_owned()))
+}
+
+/// Appends the character data of `event` to `text`; other events are ignored. Entity
+/// references arrive as events of their own and are resolved here.
+pub fn push_text(text: &mut String, event: &Event) -> Result<(), String> {
+    match event {
+        Event::Text(chunk) => text.push_str(&chunk.decode().map_err(|e| e.to_string())?),
+        Event::CData(chunk) => text.push_str(&chunk.decode().map_err(|e| e.to_string())?),
+        Event::GeneralRef(reference) => {
+            if let Some(c) = reference.resolve_char_ref().map_err(|e| e.to_string())? {
+                text.push(c);
+            } else {
+                let name = reference.decode().map_err(|e| e.to_string())?;
+                let resolved = resolve_predefined_entity(&name)
+                    .ok_or_else(|| format!("unknown entity &{name};"))?;
+                text.push_str(resolved);
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
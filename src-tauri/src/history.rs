@@ -0,0 +1,129 @@
+use serde::{Deserialize, Serialize};
+
+use crate::model::Document;
+use crate::undo_tree;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HistoryLimits {
+    /// Steps kept behind the current state, and ahead of it on the redo line.
+    pub max_depth: usize,
+    /// Serialized size of the undo tree, which holds all of the history, in bytes.
+    pub max_bytes: usize,
+    /// Nodes kept in the undo tree.
+    pub max_tree_nodes: usize,
+}
+
+pub const DEFAULT_LIMITS: HistoryLimits = HistoryLimits {
+    max_depth: 200,
+    max_bytes: 8 * 1024 * 1024,
+    max_tree_nodes: 1000,
+};
+
+impl Default for HistoryLimits {
+    fn default() -> Self {
+        DEFAULT_LIMITS
+    }
+}
+
+/// Applies `limits` to the document's history. Only the undo tree is trimmed; the stacks
+/// are then rebuilt from it, so they never hold a step the tree no longer has. Returns how
+/// many tree nodes were removed.
+pub fn enforce_document_limits(doc: &mut Document, limits: HistoryLimits) -> usize {
+    undo_tree::seed(doc);
+    let mut redo_tip = undo_tree::redo_tip(&doc.undo_tree, &doc.redo_stack);
+    let removed = undo_tree::prune(&mut doc.undo_tree, &mut redo_tip, limits);
+    let (undo, redo) = undo_tree::stacks(&doc.undo_tree, redo_tip.as_deref());
+    doc.undo_stack = undo;
+    doc.redo_stack = redo;
+    removed.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{EditOp, HistoryEntry, StoredDocument, UndoTree};
+
+    /// An entry whose text names it, so the tests can see which ones survive.
+    fn entry(name: &str) -> HistoryEntry {
+        HistoryEntry {
+            cursor_before: "root".to_string(),
+            cursor_after: "root".to_string(),
+            ops: vec![EditOp::EditText {
+                node_id: "root".to_string(),
+                before: String::new(),
+                after: name.to_string(),
+            }],
+        }
+    }
+
+    fn stack(names: &[&str]) -> Vec<HistoryEntry> {
+        names.iter().map(|name| entry(name)).collect()
+    }
+
+    fn limits(max_depth: usize, max_bytes: usize) -> HistoryLimits {
+        HistoryLimits {
+            max_depth,
+            max_bytes,
+            ..DEFAULT_LIMITS
+        }
+    }
+
+    fn tree_len(tree: &UndoTree) -> usize {
+        tree.nodes
+            .values()
+            .map(|node| serde_json::to_vec(node).unwrap().len())
+            .sum()
+    }
+
+    #[test]
+    fn depth_cap_keeps_the_steps_next_to_the_current_state() {
+        let mut doc = crate::test_support::document(&[("root", "Root", &[])]);
+        doc.undo_stack = stack(&["u1", "u2", "u3", "u4"]);
+        doc.redo_stack = stack(&["r1", "r2", "r3"]);
+        let removed = enforce_document_limits(&mut doc, limits(2, usize::MAX));
+        assert_eq!(removed, 2);
+        // The top of each stack (the end) is the step next to the current state.
+        assert_eq!(doc.undo_stack, stack(&["u3", "u4"]));
+        assert_eq!(doc.redo_stack, stack(&["r2", "r3"]));
+
+        assert_eq!(enforce_document_limits(&mut doc, limits(2, usize::MAX)), 0);
+        assert_eq!(doc.redo_stack, stack(&["r2", "r3"]));
+    }
+
+    #[test]
+    fn stacks_follow_the_tree_when_it_is_trimmed() {
+        let mut doc = crate::test_support::document(&[("root", "Root", &[])]);
+        for (i, name) in ["u1", "u2", "u3", "u4"].into_iter().enumerate() {
+            undo_tree::push(&mut doc, entry(name), i as u64);
+        }
+        let budget = limits(10, tree_len(&doc.undo_tree) / 2);
+        assert!(enforce_document_limits(&mut doc, budget) > 0);
+        assert!(tree_len(&doc.undo_tree) <= budget.max_bytes);
+
+        // Undoing everything lands on the tree's root, with nothing older left behind.
+        let path = undo_tree::ancestors(&doc.undo_tree, &doc.undo_tree.current_id);
+        assert_eq!(doc.undo_stack.len(), path.len() - 1);
+        assert_eq!(doc.undo_stack.last(), Some(&entry("u4")));
+        assert_eq!(
+            doc.undo_stack,
+            undo_tree::stacks(&doc.undo_tree, None).0,
+            "undo is the tree's path"
+        );
+    }
+
+    #[test]
+    fn stored_documents_get_their_stacks_back() {
+        let mut doc = crate::test_support::document(&[("root", "Root", &[])]);
+        doc.undo_stack = stack(&["u1", "u2"]);
+        doc.redo_stack = stack(&["r1"]);
+        enforce_document_limits(&mut doc, DEFAULT_LIMITS);
+
+        let text = serde_json::to_string(&StoredDocument::from(&doc)).unwrap();
+        assert!(!text.contains("undoStack") && !text.contains("redoStack"));
+        let read: Document = serde_json::from_str(&text).unwrap();
+        assert_eq!(read.undo_stack, doc.undo_stack);
+        assert_eq!(read.redo_stack, doc.redo_stack);
+        assert_eq!(read.undo_tree, doc.undo_tree);
+    }
+}
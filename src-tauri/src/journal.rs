@@ -0,0 +1,486 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::durable;
+use crate::model::{Document, HistoryEntry, Node, UndoNode};
+
+/// Compact once the journal grows past either limit.
+pub const COMPACT_AFTER_BYTES: u64 = 1024 * 1024;
+pub const COMPACT_AFTER_BATCHES: usize = 2000;
+
+/// How much of the end of the journal is read at first when looking for the last batch.
+const TAIL_WINDOW: u64 = 4096;
+
+/// The length of each journal as this process last wrote it or read it to the end. Only a
+/// journal still at that length can be appended to by trusting its first and last lines.
+static VERIFIED_LENGTHS: Mutex<BTreeMap<PathBuf, u64>> = Mutex::new(BTreeMap::new());
+
+fn remember_length(path: &Path, len: u64) {
+    if let Ok(mut lengths) = VERIFIED_LENGTHS.lock() {
+        lengths.insert(path.to_path_buf(), len);
+    }
+}
+
+/// Whether every line of the journal has been read or written by this process, so that
+/// nothing unreadable can hide between its ends. A missing journal counts as verified.
+pub fn is_verified(path: &Path) -> bool {
+    let Ok(metadata) = fs::metadata(path) else {
+        return !path.exists();
+    };
+    VERIFIED_LENGTHS
+        .lock()
+        .is_ok_and(|lengths| lengths.get(path) == Some(&metadata.len()))
+}
+
+/// Node ops set absolute values; history ops are relative, which is safe because replay
+/// never applies a batch the snapshot already contains.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(
+    tag = "op",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum JournalOp {
+    AddNode { node: Node },
+    SetText { node_id: String, text: String },
+    MoveNode {
+        node_id: String,
+        parent_id: Option<String>,
+    },
+    SetChildren {
+        node_id: String,
+        children_ids: Vec<String>,
+    },
+    DeleteNode { node_id: String },
+    SetCursor { node_id: String },
+    SetRoot { node_id: String },
+    /// History ops count from the top of the stack, so they still apply after the store
+    /// has dropped old entries to stay within its history limits.
+    PopUndo { count: usize },
+    PushUndo { entry: HistoryEntry },
+    PopRedo { count: usize },
+    PushRedo { entry: HistoryEntry },
+    PutUndoNode { node: UndoNode },
+    RemoveUndoNode { node_id: String },
+    SetUndoCurrent { node_id: String },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentOps {
+    pub doc_id: String,
+    pub ops: Vec<JournalOp>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Batch {
+    seq: u64,
+    ops: Vec<JournalOp>,
+    /// Ops that parsed as JSON but not as a `JournalOp`, e.g. ones this build does not
+    /// know.
+    #[serde(skip)]
+    skipped_ops: usize,
+}
+
+/// A batch as read back, before its ops are parsed one by one.
+#[derive(Debug, Clone, Deserialize)]
+struct RawBatch {
+    seq: u64,
+    ops: Vec<serde_json::Value>,
+}
+
+pub struct JournalStats {
+    pub last_seq: u64,
+    pub batches: usize,
+    pub bytes: u64,
+}
+
+impl JournalStats {
+    pub fn needs_compaction(&self) -> bool {
+        self.bytes > COMPACT_AFTER_BYTES || self.batches > COMPACT_AFTER_BATCHES
+    }
+}
+
+pub fn apply_op(doc: &mut Document, op: JournalOp) {
+    match op {
+        JournalOp::AddNode { node } => {
+            doc.nodes.insert(node.id.clone(), node);
+        }
+        JournalOp::SetText { node_id, text } => {
+            if let Some(node) = doc.nodes.get_mut(&node_id) {
+                node.text = text;
+            }
+        }
+        JournalOp::MoveNode { node_id, parent_id } => {
+            if let Some(node) = doc.nodes.get_mut(&node_id) {
+                node.parent_id = parent_id;
+            }
+        }
+        JournalOp::SetChildren {
+            node_id,
+            children_ids,
+        } => {
+            if let Some(node) = doc.nodes.get_mut(&node_id) {
+                node.children_ids = children_ids;
+            }
+        }
+        JournalOp::DeleteNode { node_id } => {
+            doc.nodes.remove(&node_id);
+        }
+        JournalOp::SetCursor { node_id } => doc.cursor_id = node_id,
+        JournalOp::SetRoot { node_id } => doc.root_id = node_id,
+        JournalOp::PopUndo { count } => pop(&mut doc.undo_stack, count),
+        JournalOp::PushUndo { entry } => doc.undo_stack.push(entry),
+        JournalOp::PopRedo { count } => pop(&mut doc.redo_stack, count),
+        JournalOp::PushRedo { entry } => doc.redo_stack.push(entry),
+        JournalOp::PutUndoNode { node } => {
+            doc.undo_tree.nodes.insert(node.id.clone(), node);
+        }
+        JournalOp::RemoveUndoNode { node_id } => {
+            doc.undo_tree.nodes.remove(&node_id);
+        }
+        JournalOp::SetUndoCurrent { node_id } => doc.undo_tree.current_id = node_id,
+    }
+}
+
+fn pop(stack: &mut Vec<HistoryEntry>, count: usize) {
+    stack.truncate(stack.len().saturating_sub(count));
+}
+
+/// Only the sequence number of a batch, for reading the first and last lines.
+#[derive(Deserialize)]
+struct BatchSeq {
+    seq: u64,
+}
+
+struct ReadBatches {
+    batches: Vec<Batch>,
+    /// Length of the lines that were read; anything after that was not.
+    valid_bytes: u64,
+    /// Why reading stopped before the end of the file.
+    stopped_early: Option<String>,
+}
+
+impl ReadBatches {
+    fn stats(&self) -> JournalStats {
+        JournalStats {
+            last_seq: self.batches.last().map_or(0, |b| b.seq),
+            batches: self.batches.len(),
+            bytes: self.valid_bytes,
+        }
+    }
+}
+
+/// Reads batches in order, stopping at the first line that does not parse: that is a
+/// write cut short by a crash, and nothing after it can be trusted.
+fn read_batches(path: &Path) -> Result<ReadBatches, String> {
+    let mut read = ReadBatches {
+        batches: Vec::new(),
+        valid_bytes: 0,
+        stopped_early: None,
+    };
+    if !path.exists() {
+        return Ok(read);
+    }
+    let file = fs::File::open(path).map_err(|e| e.to_string())?;
+    let mut reader = BufReader::new(file);
+    let mut line = String::new();
+    for line_number in 1.. {
+        line.clear();
+        let len = match reader.read_line(&mut line) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(e) => {
+                read.stopped_early = Some(format!("line {line_number}: {e}"));
+                break;
+            }
+        };
+        if !line.ends_with('\n') {
+            read.stopped_early = Some(format!("line {line_number}: cut short"));
+            break;
+        }
+        if line.trim().is_empty() {
+            read.valid_bytes += len as u64;
+            continue;
+        }
+        let raw = match serde_json::from_str::<RawBatch>(&line) {
+            Ok(raw) => raw,
+            Err(e) => {
+                read.stopped_early = Some(format!("line {line_number}: {e}"));
+                break;
+            }
+        };
+        let mut batch = Batch {
+            seq: raw.seq,
+            ops: Vec::with_capacity(raw.ops.len()),
+            skipped_ops: 0,
+        };
+        for op in raw.ops {
+            match serde_json::from_value::<JournalOp>(op) {
+                Ok(op) => batch.ops.push(op),
+                Err(_) => batch.skipped_ops += 1,
+            }
+        }
+        read.batches.push(batch);
+        read.valid_bytes += len as u64;
+    }
+    Ok(read)
+}
+
+/// Stats from the first and last line alone; sequence numbers are consecutive from the
+/// first batch on. `None` when the tail is not a complete batch and a full read is needed.
+fn tail_stats(path: &Path) -> io::Result<Option<JournalStats>> {
+    let mut file = fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    if len == 0 {
+        return Ok(None);
+    }
+    let mut first = String::new();
+    BufReader::new(&mut file).read_line(&mut first)?;
+
+    let mut window = TAIL_WINDOW.min(len);
+    let last = loop {
+        file.seek(SeekFrom::Start(len - window))?;
+        let mut buf = vec![0; window as usize];
+        file.read_exact(&mut buf)?;
+        let Some(body) = buf.strip_suffix(b"\n") else {
+            return Ok(None);
+        };
+        if let Some(pos) = body.iter().rposition(|&b| b == b'\n') {
+            break body[pos + 1..].to_vec();
+        }
+        if window == len {
+            break body.to_vec();
+        }
+        window = (window * 2).min(len);
+    };
+
+    let (Ok(first), Ok(last)) = (
+        serde_json::from_str::<BatchSeq>(&first),
+        serde_json::from_slice::<BatchSeq>(&last),
+    ) else {
+        return Ok(None);
+    };
+    if last.seq < first.seq {
+        return Ok(None);
+    }
+    Ok(Some(JournalStats {
+        last_seq: last.seq,
+        batches: (last.seq - first.seq) as usize + 1,
+        bytes: len,
+    }))
+}
+
+pub fn stats(path: &Path) -> Result<Option<JournalStats>, String> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    if let Some(stats) = tail_stats(path).map_err(|e| e.to_string())? {
+        return Ok(Some(stats));
+    }
+    Ok(Some(read_batches(path)?.stats()))
+}
+
+pub struct Replayed {
+    pub last_seq: u64,
+    /// Set when the journal could not be read to the end; batches after that point are lost.
+    pub stopped_early: Option<String>,
+}
+
+/// Applies every batch newer than `snapshot_seq` and returns the last applied sequence.
+/// If any op could not be read, later history ops may refer to entries that are missing,
+/// so both stacks and the undo tree are cleared; the node edits still apply.
+pub fn replay(path: &Path, snapshot_seq: u64, doc: &mut Document) -> Result<Replayed, String> {
+    let read = read_batches(path)?;
+    if read.stopped_early.is_none() && path.exists() {
+        remember_length(path, read.valid_bytes);
+    }
+    let mut last_seq = snapshot_seq;
+    let mut skipped_ops = 0;
+    for batch in read.batches {
+        if batch.seq <= last_seq {
+            continue;
+        }
+        last_seq = batch.seq;
+        skipped_ops += batch.skipped_ops;
+        for op in batch.ops {
+            apply_op(doc, op);
+        }
+    }
+    if skipped_ops > 0 {
+        doc.undo_stack.clear();
+        doc.redo_stack.clear();
+        doc.undo_tree = Default::default();
+    }
+    Ok(Replayed {
+        last_seq,
+        stopped_early: read.stopped_early,
+    })
+}
+
+/// Appends one batch and fsyncs it. `base_seq` is only consulted when the journal does not
+/// exist yet and must return the `journalSeq` of the snapshot it continues from. Only the
+/// ends of the journal are read, so callers check `is_verified` first; a torn last line is
+/// cut off, since a batch appended after it could never be read back.
+pub fn append(
+    path: &Path,
+    ops: Vec<JournalOp>,
+    base_seq: impl FnOnce() -> u64,
+) -> Result<JournalStats, String> {
+    let verified = is_verified(path);
+    let mut previous = None;
+    if path.exists() {
+        previous = tail_stats(path).map_err(|e| e.to_string())?;
+        if previous.is_none() {
+            let read = read_batches(path)?;
+            if read.stopped_early.is_some() {
+                fs::OpenOptions::new()
+                    .write(true)
+                    .open(path)
+                    .and_then(|file| file.set_len(read.valid_bytes))
+                    .map_err(|e| e.to_string())?;
+            }
+            previous = Some(read.stats());
+        }
+    }
+    let last_seq = previous.as_ref().map_or_else(base_seq, |s| s.last_seq);
+    let seq = last_seq + 1;
+
+    let mut line = serde_json::to_string(&Batch {
+        seq,
+        ops,
+        skipped_ops: 0,
+    })
+    .map_err(|e| e.to_string())?;
+    line.push('\n');
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| e.to_string())?;
+    file.write_all(line.as_bytes())
+        .and_then(|()| file.sync_data())
+        .map_err(|e| e.to_string())?;
+
+    let stats = JournalStats {
+        last_seq: seq,
+        batches: previous.as_ref().map_or(0, |s| s.batches) + 1,
+        bytes: previous.as_ref().map_or(0, |s| s.bytes) + line.len() as u64,
+    };
+    if verified {
+        remember_length(path, stats.bytes);
+    }
+    Ok(stats)
+}
+
+/// Replaces the journal with an empty batch at `seq`, once a snapshot covering `seq` is on
+/// disk. Keeping the marker lets the next append continue the sequence without reading the
+/// snapshot.
+pub fn reset(path: &Path, seq: u64) -> Result<(), String> {
+    let mut line = serde_json::to_string(&Batch {
+        seq,
+        ops: Vec::new(),
+        skipped_ops: 0,
+    })
+    .map_err(|e| e.to_string())?;
+    line.push('\n');
+    durable::write_atomic(&durable::RealFs, path, line.as_bytes())?;
+    remember_length(path, line.len() as u64);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, Scratch};
+
+    fn set_text(text: &str) -> Vec<JournalOp> {
+        vec![JournalOp::SetText {
+            node_id: "root".to_string(),
+            text: text.to_string(),
+        }]
+    }
+
+    fn root_text(path: &Path) -> (String, Replayed) {
+        let mut doc = test_support::document(&[("root", "Root", &[])]);
+        let replayed = replay(path, 0, &mut doc).unwrap();
+        (doc.nodes["root"].text.clone(), replayed)
+    }
+
+    #[test]
+    fn stats_from_the_tail_match_a_full_read() {
+        let scratch = Scratch::new("journal-stats");
+        let path = scratch.dir.join("doc.journal");
+        reset(&path, 7).unwrap();
+        let long = "x".repeat(3 * TAIL_WINDOW as usize);
+        for text in ["a", long.as_str(), "b"] {
+            append(&path, set_text(text), || unreachable!()).unwrap();
+        }
+
+        let tail = tail_stats(&path).unwrap().unwrap();
+        let full = read_batches(&path).unwrap().stats();
+        assert_eq!(
+            (tail.last_seq, tail.batches, tail.bytes),
+            (full.last_seq, full.batches, full.bytes)
+        );
+        assert_eq!((tail.last_seq, tail.batches), (10, 4));
+
+        let appended = append(&path, set_text(&long), || unreachable!()).unwrap();
+        assert_eq!((appended.last_seq, appended.batches), (11, 5));
+        assert_eq!(appended.bytes, fs::metadata(&path).unwrap().len());
+    }
+
+    #[test]
+    fn new_journal_continues_from_the_snapshot() {
+        let scratch = Scratch::new("journal-base");
+        let path = scratch.dir.join("doc.journal");
+        let stats = append(&path, set_text("a"), || 41).unwrap();
+        assert_eq!((stats.last_seq, stats.batches), (42, 1));
+    }
+
+    #[test]
+    fn torn_line_is_reported_and_cut_off_before_appending() {
+        let scratch = Scratch::new("journal-torn");
+        let path = scratch.dir.join("doc.journal");
+        append(&path, set_text("a"), || 0).unwrap();
+        append(&path, set_text("b"), || 0).unwrap();
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"seq\":3,\"ops\":[{\"op\":\"setT")
+            .unwrap();
+
+        let (text, replayed) = root_text(&path);
+        assert_eq!(text, "b");
+        assert_eq!(replayed.last_seq, 2);
+        assert_eq!(replayed.stopped_early.as_deref(), Some("line 3: cut short"));
+        assert!(tail_stats(&path).unwrap().is_none());
+        assert_eq!(stats(&path).unwrap().unwrap().last_seq, 2);
+
+        let stats = append(&path, set_text("c"), || 0).unwrap();
+        assert_eq!(stats.last_seq, 3);
+        let (text, replayed) = root_text(&path);
+        assert_eq!(text, "c");
+        assert!(replayed.stopped_early.is_none());
+    }
+
+    #[test]
+    fn unreadable_line_stops_the_replay() {
+        let scratch = Scratch::new("journal-garbage");
+        let path = scratch.dir.join("doc.journal");
+        append(&path, set_text("a"), || 0).unwrap();
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"not json\n{\"seq\":2,\"ops\":[]}\n")
+            .unwrap();
+
+        let (text, replayed) = root_text(&path);
+        assert_eq!(text, "a");
+        assert!(replayed
+            .stopped_early
+            .is_some_and(|reason| reason.starts_with("line 2: ")));
+    }
+}
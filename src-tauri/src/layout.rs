@@ -0,0 +1,218 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::model::Document;
+
+// Same geometry as `src/editor/layout.ts`, so exported images match the editor.
+pub const NODE_WIDTH: f64 = 180.0;
+pub const NODE_HEIGHT: f64 = 34.0;
+pub const H_GAP: f64 = 80.0;
+pub const V_GAP: f64 = 16.0;
+pub const PADDING_X: f64 = 32.0;
+pub const PADDING_Y: f64 = 32.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NodePosition {
+    pub x: f64,
+    pub y: f64,
+    pub depth: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct LayoutResult {
+    pub positions: HashMap<String, NodePosition>,
+    /// Parent and child ids of every edge to draw, depth first in `children_ids` order.
+    pub edges: Vec<(String, String)>,
+    pub content_width: f64,
+    pub content_height: f64,
+}
+
+/// Leaves stack top to bottom and each parent is centred on its children, one column per
+/// depth. Unlike the editor, a node reached a second time (a damaged tree) is not laid out
+/// again.
+pub fn compute_layout(doc: &Document) -> LayoutResult {
+    let mut layout = Layout {
+        doc,
+        positions: HashMap::new(),
+        edges: Vec::new(),
+        seen: HashSet::new(),
+        next_y: PADDING_Y,
+        max_depth: 0,
+        max_y: 0.0,
+    };
+    layout.run(&doc.root_id);
+
+    let max_depth = layout.max_depth as f64;
+    LayoutResult {
+        content_width: PADDING_X + (max_depth + 1.0) * NODE_WIDTH + max_depth * H_GAP + PADDING_X,
+        content_height: layout.max_y + NODE_HEIGHT + PADDING_Y,
+        positions: layout.positions,
+        edges: layout.edges,
+    }
+}
+
+struct Layout<'a> {
+    doc: &'a Document,
+    positions: HashMap<String, NodePosition>,
+    edges: Vec<(String, String)>,
+    seen: HashSet<&'a str>,
+    next_y: f64,
+    max_depth: usize,
+    max_y: f64,
+}
+
+/// A node whose children are being laid out.
+struct Frame<'a> {
+    node_id: &'a str,
+    children_ids: &'a [String],
+    depth: usize,
+    next_child: usize,
+    child_ys: Vec<f64>,
+}
+
+impl<'a> Layout<'a> {
+    /// Depth first with an explicit stack rather than recursion, so a very deep tree cannot
+    /// overflow the stack.
+    fn run(&mut self, root_id: &'a str) {
+        let mut stack = Vec::new();
+        stack.extend(self.enter(root_id, 0));
+        while let Some(frame) = stack.last_mut() {
+            if let Some(child_id) = frame.children_ids.get(frame.next_child) {
+                frame.next_child += 1;
+                let depth = frame.depth + 1;
+                self.edges.push((frame.node_id.to_string(), child_id.clone()));
+                match self.enter(child_id, depth) {
+                    Some(child) => stack.push(child),
+                    // Nothing was added since the push above.
+                    None => {
+                        self.edges.pop();
+                    }
+                }
+                continue;
+            }
+            let y = self.place(frame);
+            stack.pop();
+            if let Some(parent) = stack.last_mut() {
+                parent.child_ys.push(y);
+            }
+        }
+    }
+
+    /// `None` when the node is missing or was already laid out.
+    fn enter(&mut self, node_id: &'a str, depth: usize) -> Option<Frame<'a>> {
+        let node = self.doc.nodes.get(node_id)?;
+        if !self.seen.insert(node_id) {
+            return None;
+        }
+        self.max_depth = self.max_depth.max(depth);
+        Some(Frame {
+            node_id,
+            children_ids: &node.children_ids,
+            depth,
+            next_child: 0,
+            child_ys: Vec::new(),
+        })
+    }
+
+    /// Positions a node once all its children are placed, and returns its y.
+    fn place(&mut self, frame: &Frame<'a>) -> f64 {
+        let y = match (frame.child_ys.first(), frame.child_ys.last()) {
+            // Children are laid out in order, so the first is the highest.
+            (Some(min), Some(max)) => (min + max) / 2.0,
+            _ => {
+                let y = self.next_y;
+                self.next_y += NODE_HEIGHT + V_GAP;
+                y
+            }
+        };
+        let x = PADDING_X + frame.depth as f64 * (NODE_WIDTH + H_GAP);
+        self.positions.insert(
+            frame.node_id.to_string(),
+            NodePosition {
+                x,
+                y,
+                depth: frame.depth,
+            },
+        );
+        self.max_y = self.max_y.max(y);
+        y
+    }
+}
+
+/// A horizontal S-curve between two points.
+pub fn svg_path_for_edge(from: (f64, f64), to: (f64, f64)) -> String {
+    let mid_x = (from.0 + to.0) / 2.0;
+    format!(
+        "M {} {} C {mid_x} {}, {mid_x} {}, {} {}",
+        from.0, from.1, from.1, to.1, to.0, to.1
+    )
+}
+
+/// Where the edge from `parent` to `child` starts and ends: the middle of the parent's
+/// right side and of the child's left side.
+pub fn edge_points(parent: &NodePosition, child: &NodePosition) -> ((f64, f64), (f64, f64)) {
+    (
+        (parent.x + NODE_WIDTH, parent.y + NODE_HEIGHT / 2.0),
+        (child.x, child.y + NODE_HEIGHT / 2.0),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::document;
+
+    #[test]
+    fn centres_parents_on_their_children() {
+        // "c" lists the root again, as a damaged file might.
+        let doc = document(&[
+            ("root", "root", &["a", "b"]),
+            ("a", "a", &["c", "d"]),
+            ("b", "b", &[]),
+            ("c", "c", &["root"]),
+            ("d", "d", &[]),
+        ]);
+        let layout = compute_layout(&doc);
+
+        let y = |id: &str| layout.positions[id].y;
+        assert_eq!((y("c"), y("d"), y("b")), (32.0, 82.0, 132.0));
+        assert_eq!(y("a"), 57.0);
+        assert_eq!(y("root"), 94.5);
+        assert_eq!(layout.positions["d"].x, 32.0 + 2.0 * 260.0);
+        assert_eq!(layout.content_width, 32.0 + 3.0 * 180.0 + 2.0 * 80.0 + 32.0);
+        assert_eq!(layout.content_height, 132.0 + 34.0 + 32.0);
+        let edges: Vec<(&str, &str)> = layout
+            .edges
+            .iter()
+            .map(|(from, to)| (from.as_str(), to.as_str()))
+            .collect();
+        assert_eq!(
+            edges,
+            vec![("root", "a"), ("a", "c"), ("a", "d"), ("root", "b")]
+        );
+        assert_eq!(
+            svg_path_for_edge((10.0, 20.0), (30.0, 60.5)),
+            "M 10 20 C 20 20, 20 60.5, 30 60.5"
+        );
+    }
+
+    #[test]
+    fn lays_out_a_very_deep_chain() {
+        let ids: Vec<String> = (0..100_000).map(|i| format!("n{i}")).collect();
+        let ids: Vec<&str> = ids.iter().map(String::as_str).collect();
+        let rows: Vec<(&str, &str, &[&str])> = (0..ids.len())
+            .map(|i| {
+                (
+                    ids[i],
+                    "x",
+                    &ids[(i + 1).min(ids.len())..(i + 2).min(ids.len())],
+                )
+            })
+            .collect();
+        let layout = compute_layout(&document(&rows));
+
+        assert_eq!(layout.positions.len(), 100_000);
+        assert_eq!(layout.edges.len(), 99_999);
+        assert_eq!(layout.positions["n0"].y, 32.0);
+        assert_eq!(layout.positions["n99999"].depth, 99_999);
+    }
+}
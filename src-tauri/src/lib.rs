@@ -1,52 +1,134 @@
-use serde::{Deserialize, Serialize};
+mod backup;
+mod durable;
+mod format;
+mod history;
+mod journal;
+mod layout;
+mod merge;
+mod migrate;
+mod model;
+mod render;
+mod revision;
+mod salvage;
+mod settings;
+mod store;
+#[cfg(test)]
+mod test_support;
+mod tree_diff;
+mod undo;
+mod undo_tree;
+mod validate;
+
+use format::{ExportFormat, Imported};
+use merge::MergedWorkspace;
+use migrate::{MigrateError, CURRENT_SCHEMA_VERSION};
+use model::{Document, DocumentState, StoredWorkspace, Workspace, WorkspaceChanges};
+use salvage::{LostDocument, SalvageReport};
+use serde::Serialize;
+use settings::Settings;
 use std::{
-    collections::HashMap,
     fs,
     path::{Path, PathBuf},
     time::{SystemTime, UNIX_EPOCH},
 };
+use store::{NodeMatch, SplitStore, SqliteStore, WorkspaceStore};
 use tauri::Manager;
+use tree_diff::TreeDiff;
+use undo_tree::UndoBranch;
+use validate::RepairReport;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
-struct Workspace {
-    tabs: Vec<TabRef>,
-    active_doc_id: String,
-    documents: HashMap<String, Document>,
+struct LoadedWorkspace {
+    workspace: Workspace,
+    repair_report: Option<RepairReport>,
+    salvage_report: Option<SalvageReport>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
-struct TabRef {
+struct DocumentSummary {
     doc_id: String,
+    title: String,
+    node_count: usize,
+}
+
+impl DocumentSummary {
+    fn of(doc: &Document) -> Self {
+        DocumentSummary {
+            doc_id: doc.id.clone(),
+            title: doc.title().to_string(),
+            node_count: doc.nodes.len(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BrokenWorkspaceInfo {
+    file_name: String,
+    broken_at_millis: u128,
+    documents: Vec<DocumentSummary>,
+    lost: Vec<LostDocument>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BackupInfo {
+    file_name: String,
+    created_at_millis: u128,
+    documents: Vec<DocumentSummary>,
+    error: Option<String>,
+}
+
+/// A copy of the workspace next to ours, such as the conflicted copy a sync tool leaves,
+/// with the backup taken to be the common ancestor.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MergeCandidate {
+    file_name: String,
+    modified_at_millis: u128,
+    base_file_name: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
-struct Document {
-    id: String,
-    root_id: String,
-    cursor_id: String,
-    nodes: HashMap<String, Node>,
-    undo_stack: Vec<DocumentState>,
-    redo_stack: Vec<DocumentState>,
+struct RecoveredDocuments {
+    documents: Vec<Document>,
+    report: SalvageReport,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
-struct DocumentState {
-    root_id: String,
-    cursor_id: String,
-    nodes: HashMap<String, Node>,
+struct RevisionInfo {
+    saved_at_millis: u128,
+    title: String,
+    node_count: usize,
+    /// Against the revision before it; `None` for the oldest.
+    changes: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// One line per history entry, most recent first.
+#[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
-struct Node {
-    id: String,
-    text: String,
-    parent_id: Option<String>,
-    children_ids: Vec<String>,
+struct HistoryDescription {
+    undo: Vec<String>,
+    redo: Vec<String>,
+}
+
+fn now_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+}
+
+fn summarize_documents(workspace: &Workspace) -> Vec<DocumentSummary> {
+    let order = workspace.tabs.iter().map(|tab| &tab.doc_id);
+    order
+        .filter_map(|doc_id| workspace.documents.get(doc_id))
+        .map(DocumentSummary::of)
+        .collect()
 }
 
 fn workspace_json_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
@@ -60,65 +142,834 @@ fn workspace_json_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
     Ok(path)
 }
 
+fn settings_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .resolve("settings.json", tauri::path::BaseDirectory::AppData)
+        .map_err(|e| e.to_string())
+}
+
+fn load_settings(app: &tauri::AppHandle) -> Result<Settings, String> {
+    Ok(settings::load(&settings_path(app)?))
+}
+
+fn workspace_store(app: &tauri::AppHandle) -> Result<SplitStore, String> {
+    let root = app
+        .path()
+        .resolve("workspace", tauri::path::BaseDirectory::AppData)
+        .map_err(|e| e.to_string())?;
+    fs::create_dir_all(&root).map_err(|e| e.to_string())?;
+    Ok(SplitStore::new(root).with_history_limits(load_settings(app)?.history))
+}
+
+fn revisions_dir(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .resolve("revisions", tauri::path::BaseDirectory::AppData)
+        .map_err(|e| e.to_string())
+}
+
+fn sqlite_path(app: &tauri::AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .resolve("workspace.sqlite3", tauri::path::BaseDirectory::AppData)
+        .map_err(|e| e.to_string())
+}
+
+/// The SQLite database once `import_into_sqlite` has created it, the split layout otherwise.
+fn open_store(app: &tauri::AppHandle) -> Result<Box<dyn WorkspaceStore>, String> {
+    let path = sqlite_path(app)?;
+    if path.exists() {
+        let limits = load_settings(app)?.history;
+        return Ok(Box::new(SqliteStore::open(&path)?.with_history_limits(limits)));
+    }
+    Ok(Box::new(workspace_store(app)?))
+}
+
+/// The last saved workspace as a single JSON document, for backups.
+fn current_workspace_text(store: &dyn WorkspaceStore, legacy_path: &Path) -> Option<String> {
+    if store.exists() {
+        let workspace = store.load().ok()?.workspace?;
+        let text = serde_json::to_string_pretty(&StoredWorkspace::from(&workspace)).ok()?;
+        return Some(format!("{text}\n"));
+    }
+    fs::read_to_string(legacy_path).ok()
+}
+
+/// Once the split layout has been written, the single-file `workspace.json` is moved aside
+/// so it is never mistaken for current data.
+fn retire_legacy_workspace(path: &Path) {
+    if path.exists() {
+        let parent = path.parent().unwrap_or_else(|| Path::new("."));
+        let _ = fs::rename(path, parent.join(format!("workspace.json.split-{}", now_millis())));
+    }
+}
+
 fn workspace_broken_backup_path(path: &Path) -> PathBuf {
-    let millis = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_millis();
+    let millis = now_millis();
 
     let parent = path.parent().unwrap_or_else(|| Path::new("."));
     parent.join(format!("workspace.json.broken-{millis}"))
 }
 
-fn write_workspace_json_atomic(path: &Path, text: &str) -> Result<(), String> {
-    let parent = path
-        .parent()
-        .ok_or_else(|| "workspace.json path has no parent".to_string())?;
+const BROKEN_PREFIX: &str = "workspace.json.broken-";
 
-    let millis = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_millis();
-    let tmp_path = parent.join(format!("workspace.json.tmp-{millis}"));
-
-    fs::write(&tmp_path, text).map_err(|e| e.to_string())?;
-    match fs::rename(&tmp_path, path) {
-        Ok(()) => Ok(()),
-        Err(_) => {
-            if path.exists() {
-                let _ = fs::remove_file(path);
-            }
-            fs::rename(&tmp_path, path).map_err(|e| e.to_string())?;
-            Ok(())
+fn broken_workspace_paths(path: &Path) -> Result<Vec<(String, u128)>, String> {
+    let parent = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut found = Vec::new();
+    for entry in fs::read_dir(parent).map_err(|e| e.to_string())? {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if let Some(millis) = file_name
+            .strip_prefix(BROKEN_PREFIX)
+            .and_then(|suffix| suffix.parse::<u128>().ok())
+        {
+            found.push((file_name, millis));
         }
     }
+    found.sort_by_key(|(_, millis)| std::cmp::Reverse(*millis));
+    Ok(found)
+}
+
+fn salvage_file(path: &Path) -> Result<salvage::Salvaged, String> {
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+    let text = String::from_utf8_lossy(&bytes);
+    salvage::salvage_workspace(&text).map_err(|e| e.to_string())
+}
+
+fn workspace_migration_backup_path(path: &Path, from_version: u32) -> PathBuf {
+    let millis = now_millis();
+
+    let parent = path.parent().unwrap_or_else(|| Path::new("."));
+    parent.join(format!("workspace.json.v{from_version}-{millis}"))
+}
+
+fn load_from_store(store: &dyn WorkspaceStore) -> Result<Option<LoadedWorkspace>, String> {
+    let loaded = store.load().map_err(|e| e.to_string())?;
+    let Some(mut workspace) = loaded.workspace else {
+        return Ok(None);
+    };
+    let report = validate::repair_workspace(&mut workspace);
+    let salvage_report = (loaded.manifest_broken
+        || !loaded.lost.is_empty()
+        || !loaded.warnings.is_empty())
+    .then(|| {
+        let mut recovered: Vec<String> = workspace.documents.keys().cloned().collect();
+        recovered.sort();
+        SalvageReport {
+            recovered,
+            lost: loaded.lost,
+            warnings: loaded.warnings,
+        }
+    });
+    Ok(Some(LoadedWorkspace {
+        workspace,
+        repair_report: (!report.is_empty()).then_some(report),
+        salvage_report,
+    }))
 }
 
 #[tauri::command]
-fn load_workspace(app: tauri::AppHandle) -> Result<Option<Workspace>, String> {
+fn load_workspace(app: tauri::AppHandle) -> Result<Option<LoadedWorkspace>, String> {
+    let store = open_store(&app)?;
+    if store.exists() {
+        return load_from_store(store.as_ref());
+    }
+
     let path = workspace_json_path(&app)?;
     if !path.exists() {
         return Ok(None);
     }
     let text = fs::read_to_string(&path).map_err(|e| e.to_string())?;
-    match serde_json::from_str::<Workspace>(&text) {
-        Ok(workspace) => Ok(Some(workspace)),
-        Err(_) => {
+    match migrate::parse_workspace(&text) {
+        Ok(migrated) => {
+            if migrated.from_version < CURRENT_SCHEMA_VERSION {
+                let backup_path = workspace_migration_backup_path(&path, migrated.from_version);
+                let _ = fs::copy(&path, &backup_path);
+            }
+            let mut workspace = migrated.workspace;
+            let report = validate::repair_workspace(&mut workspace);
+            Ok(Some(LoadedWorkspace {
+                workspace,
+                repair_report: (!report.is_empty()).then_some(report),
+                salvage_report: None,
+            }))
+        }
+        Err(e @ MigrateError::TooNew { .. }) => Err(e.to_string()),
+        Err(MigrateError::Invalid(_)) => {
             let backup_path = workspace_broken_backup_path(&path);
             let _ = fs::rename(&path, &backup_path);
-            Ok(None)
+            let Ok(salvaged) = salvage::salvage_workspace(&text) else {
+                return Ok(None);
+            };
+            let Some(mut workspace) = salvaged.workspace else {
+                return Ok(None);
+            };
+            let report = validate::repair_workspace(&mut workspace);
+            Ok(Some(LoadedWorkspace {
+                workspace,
+                repair_report: (!report.is_empty()).then_some(report),
+                salvage_report: Some(salvaged.report),
+            }))
         }
     }
 }
 
+#[tauri::command]
+fn list_broken_workspaces(app: tauri::AppHandle) -> Result<Vec<BrokenWorkspaceInfo>, String> {
+    let path = workspace_json_path(&app)?;
+    let parent = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut infos = Vec::new();
+    for (file_name, broken_at_millis) in broken_workspace_paths(&path)? {
+        let (documents, lost) = match salvage_file(&parent.join(&file_name)) {
+            Ok(salvaged) => {
+                let mut documents: Vec<DocumentSummary> = salvaged
+                    .workspace
+                    .iter()
+                    .flat_map(|workspace| workspace.documents.values())
+                    .map(DocumentSummary::of)
+                    .collect();
+                documents.sort_by(|a, b| a.doc_id.cmp(&b.doc_id));
+                (documents, salvaged.report.lost)
+            }
+            Err(reason) => (
+                Vec::new(),
+                vec![LostDocument {
+                    doc_id: None,
+                    reason,
+                }],
+            ),
+        };
+        infos.push(BrokenWorkspaceInfo {
+            file_name,
+            broken_at_millis,
+            documents,
+            lost,
+        });
+    }
+    Ok(infos)
+}
+
+#[tauri::command]
+fn recover_broken_workspace(
+    app: tauri::AppHandle,
+    file_name: String,
+) -> Result<RecoveredDocuments, String> {
+    if !file_name.starts_with(BROKEN_PREFIX) || file_name.contains(['/', '\\']) {
+        return Err(format!("not a broken workspace file: {file_name}"));
+    }
+    let path = workspace_json_path(&app)?;
+    let parent = path.parent().unwrap_or_else(|| Path::new("."));
+    let salvaged = salvage_file(&parent.join(&file_name))?;
+
+    let mut documents: Vec<Document> = salvaged
+        .workspace
+        .map(|workspace| {
+            let order: Vec<String> = workspace.tabs.iter().map(|t| t.doc_id.clone()).collect();
+            let mut documents = workspace.documents;
+            order
+                .iter()
+                .filter_map(|doc_id| documents.remove(doc_id))
+                .collect()
+        })
+        .unwrap_or_default();
+    for doc in &mut documents {
+        validate::repair_document(doc);
+    }
+    Ok(RecoveredDocuments {
+        documents,
+        report: salvaged.report,
+    })
+}
+
+fn backup_before_save(store: &dyn WorkspaceStore, legacy_path: &Path) {
+    let _ = backup::backup_if_due(
+        &backup::backup_dir(legacy_path),
+        now_millis(),
+        backup::DEFAULT_POLICY,
+        || current_workspace_text(store, legacy_path),
+    );
+}
+
+/// Revisions are a convenience on top of the save itself, so failing to record one never
+/// fails the save. Revisions of documents that are no longer in `doc_ids` go with them.
+fn record_revisions(
+    dir: &Path,
+    documents: &[&Document],
+    journaled: &[&str],
+    doc_ids: &[String],
+    store: &dyn WorkspaceStore,
+) {
+    let _ = revision::remove_unlisted(dir, doc_ids);
+    let now = now_millis();
+    for doc in documents {
+        let path = revision::revision_path(dir, &doc.id);
+        let _ = revision::record_if_due(&path, now, revision::DEFAULT_POLICY, || {
+            Some((*doc).clone())
+        });
+    }
+    for doc_id in journaled {
+        let path = revision::revision_path(dir, doc_id);
+        let _ = revision::record_if_due(&path, now, revision::DEFAULT_POLICY, || {
+            store.load_document(doc_id).ok().flatten()
+        });
+    }
+}
+
+/// Full save: every document is rewritten. The frontend uses this once per session and
+/// `save_workspace_changes` afterwards.
 #[tauri::command]
 fn save_workspace(app: tauri::AppHandle, workspace: Workspace) -> Result<(), String> {
+    let store = open_store(&app)?;
     let path = workspace_json_path(&app)?;
-    let text = serde_json::to_string_pretty(&workspace).map_err(|e| e.to_string())?;
-    write_workspace_json_atomic(&path, &format!("{text}\n"))?;
+    backup_before_save(store.as_ref(), &path);
+    store.save_all(&workspace)?;
+    retire_legacy_workspace(&path);
+    let documents: Vec<&Document> = workspace.documents.values().collect();
+    let doc_ids: Vec<String> = workspace.documents.keys().cloned().collect();
+    record_revisions(&revisions_dir(&app)?, &documents, &[], &doc_ids, store.as_ref());
     Ok(())
 }
 
+#[tauri::command]
+fn save_workspace_changes(app: tauri::AppHandle, changes: WorkspaceChanges) -> Result<(), String> {
+    let store = open_store(&app)?;
+    let path = workspace_json_path(&app)?;
+    backup_before_save(store.as_ref(), &path);
+    store.save_changes(&changes)?;
+    retire_legacy_workspace(&path);
+    let documents: Vec<&Document> = changes.changed.iter().collect();
+    let journaled: Vec<&str> = changes
+        .journal
+        .iter()
+        .filter(|entry| changes.doc_ids.contains(&entry.doc_id))
+        .map(|entry| entry.doc_id.as_str())
+        .collect();
+    record_revisions(
+        &revisions_dir(&app)?,
+        &documents,
+        &journaled,
+        &changes.doc_ids,
+        store.as_ref(),
+    );
+    Ok(())
+}
+
+#[tauri::command]
+fn list_backups(app: tauri::AppHandle) -> Result<Vec<BackupInfo>, String> {
+    let path = workspace_json_path(&app)?;
+    let dir = backup::backup_dir(&path);
+    let mut infos = Vec::new();
+    for file in backup::list_backups(&dir)? {
+        let parsed = fs::read_to_string(dir.join(&file.file_name))
+            .map_err(|e| e.to_string())
+            .and_then(|text| migrate::parse_workspace(&text).map_err(|e| e.to_string()));
+        let (documents, error) = match parsed {
+            Ok(migrated) => (summarize_documents(&migrated.workspace), None),
+            Err(e) => (Vec::new(), Some(e)),
+        };
+        infos.push(BackupInfo {
+            file_name: file.file_name,
+            created_at_millis: file.created_at_millis,
+            documents,
+            error,
+        });
+    }
+    Ok(infos)
+}
+
+/// Replaces the saved workspace with a backup. The current file is backed up first, so a
+/// restore can itself be undone by restoring that newer backup.
+#[tauri::command]
+fn restore_backup(app: tauri::AppHandle, file_name: String) -> Result<LoadedWorkspace, String> {
+    let path = workspace_json_path(&app)?;
+    let dir = backup::backup_dir(&path);
+    let text = backup::read_backup(&dir, &file_name)?;
+    let mut workspace = migrate::parse_workspace(&text)
+        .map_err(|e| e.to_string())?
+        .workspace;
+    let report = validate::repair_workspace(&mut workspace);
+
+    let store = open_store(&app)?;
+    if let Some(current) = current_workspace_text(store.as_ref(), &path) {
+        backup::create_backup(&dir, now_millis(), &current)?;
+    }
+    store.save_all(&workspace)?;
+    retire_legacy_workspace(&path);
+
+    Ok(LoadedWorkspace {
+        workspace,
+        repair_report: (!report.is_empty()).then_some(report),
+        salvage_report: None,
+    })
+}
+
+fn read_workspace_file(path: &Path) -> Result<Workspace, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut workspace = migrate::parse_workspace(&text)
+        .map_err(|e| e.to_string())?
+        .workspace;
+    validate::repair_workspace(&mut workspace);
+    Ok(workspace)
+}
+
+/// Another copy of the workspace, as sync tools leave them when two machines saved the
+/// same file.
+enum WorkspaceCopy {
+    /// `workspace*.json` in the single-file layout of older versions.
+    File(PathBuf),
+    /// `workspace*.sqlite3` beside `workspace.sqlite3`.
+    Database(PathBuf),
+    /// A `workspace*` directory in the split layout beside `workspace/`.
+    Directory(PathBuf),
+    /// A manifest or document file copied inside `workspace/` itself.
+    InStore(PathBuf),
+}
+
+impl WorkspaceCopy {
+    /// The file whose modification time dates the copy.
+    fn dated_by(&self) -> PathBuf {
+        match self {
+            WorkspaceCopy::Directory(dir) => dir.join("manifest.json"),
+            WorkspaceCopy::File(path)
+            | WorkspaceCopy::Database(path)
+            | WorkspaceCopy::InStore(path) => path.clone(),
+        }
+    }
+}
+
+/// Copies in every layout the app writes, each named by its path under the AppData
+/// directory.
+fn workspace_copies(app: &tauri::AppHandle) -> Result<Vec<(String, WorkspaceCopy)>, String> {
+    let path = workspace_json_path(app)?;
+    let parent = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut copies = Vec::new();
+    for entry in fs::read_dir(parent).map_err(|e| e.to_string())? {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if !file_name.starts_with("workspace") {
+            continue;
+        }
+        let path = entry.path();
+        let copy = if path.is_dir() {
+            (file_name != "workspace" && path.join("manifest.json").exists())
+                .then_some(WorkspaceCopy::Directory(path))
+        } else if file_name.ends_with(".json") {
+            (file_name != "workspace.json").then_some(WorkspaceCopy::File(path))
+        } else if file_name.ends_with(".sqlite3") {
+            (file_name != "workspace.sqlite3").then_some(WorkspaceCopy::Database(path))
+        } else {
+            None
+        };
+        copies.extend(copy.map(|copy| (file_name, copy)));
+    }
+    for path in workspace_store(app)?.conflicted_copies()? {
+        let name = path
+            .strip_prefix(parent)
+            .map_err(|e| e.to_string())?
+            .components()
+            .map(|part| part.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        copies.push((name, WorkspaceCopy::InStore(path)));
+    }
+    Ok(copies)
+}
+
+/// The copy as a whole workspace; copies of single files inside `workspace/` are laid over
+/// `current`.
+fn read_workspace_copy(
+    app: &tauri::AppHandle,
+    copy: &WorkspaceCopy,
+    current: &Workspace,
+) -> Result<Workspace, String> {
+    let mut workspace = match copy {
+        WorkspaceCopy::File(path) => return read_workspace_file(path),
+        WorkspaceCopy::Database(path) => SqliteStore::open(path)?
+            .load()
+            .map_err(|e| e.to_string())?
+            .workspace,
+        WorkspaceCopy::Directory(path) => SplitStore::new(path.clone())
+            .load()
+            .map_err(|e| e.to_string())?
+            .workspace,
+        WorkspaceCopy::InStore(path) => {
+            Some(workspace_store(app)?.workspace_with_copy(path, current)?)
+        }
+    }
+    .ok_or_else(|| "the copy holds no documents".to_string())?;
+    validate::repair_workspace(&mut workspace);
+    Ok(workspace)
+}
+
+/// Copies of the workspace that can be merged. The newest backup taken before a copy was
+/// last written stands in for the common ancestor; an older base only turns more edits
+/// into conflicts, while a newer one could take edits back.
+#[tauri::command]
+fn list_merge_candidates(app: tauri::AppHandle) -> Result<Vec<MergeCandidate>, String> {
+    let path = workspace_json_path(&app)?;
+    let backups = backup::list_backups(&backup::backup_dir(&path))?;
+    let mut candidates = Vec::new();
+    for (file_name, copy) in workspace_copies(&app)? {
+        let modified_at_millis = fs::metadata(copy.dated_by())
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |duration| duration.as_millis());
+        let base_file_name = backups
+            .iter()
+            .find(|backup| backup.created_at_millis < modified_at_millis)
+            .map(|backup| backup.file_name.clone());
+        candidates.push(MergeCandidate {
+            file_name,
+            modified_at_millis,
+            base_file_name,
+        });
+    }
+    candidates.sort_by_key(|c| std::cmp::Reverse(c.modified_at_millis));
+    Ok(candidates)
+}
+
+/// Merges another copy of the workspace into the one the editor holds, against the backup
+/// `base_file_name`. `file_name` must be one of `list_merge_candidates`. Nothing is saved
+/// here; the editor replaces its workspace with the result and saves it as usual, after
+/// the current file has been backed up.
+#[tauri::command]
+fn merge_workspace(
+    app: tauri::AppHandle,
+    workspace: Workspace,
+    file_name: String,
+    base_file_name: String,
+) -> Result<MergedWorkspace, String> {
+    if !backup::is_backup_file_name(&base_file_name) {
+        return Err(format!("not a backup file: {base_file_name}"));
+    }
+    let copy = workspace_copies(&app)?
+        .into_iter()
+        .find(|(name, _)| *name == file_name)
+        .map(|(_, copy)| copy)
+        .ok_or_else(|| format!("not a workspace copy: {file_name}"))?;
+    let path = workspace_json_path(&app)?;
+    let dir = backup::backup_dir(&path);
+    let theirs = read_workspace_copy(&app, &copy, &workspace)?;
+    let base = read_workspace_file(&dir.join(&base_file_name))?;
+
+    let store = open_store(&app)?;
+    if let Some(current) = current_workspace_text(store.as_ref(), &path) {
+        backup::create_backup(&dir, now_millis(), &current)?;
+    }
+    Ok(merge::merge_workspaces(
+        &base,
+        &workspace,
+        &theirs,
+        now_millis() as u64,
+    ))
+}
+
+/// One-time move of the saved workspace (split layout, or a legacy `workspace.json`) into
+/// `workspace.sqlite3`. The database is built under a temporary name and renamed into place,
+/// so an interrupted import leaves the old store in charge. The old files are left as they
+/// are; they are simply no longer read.
+#[tauri::command]
+fn import_into_sqlite(app: tauri::AppHandle) -> Result<usize, String> {
+    let target = sqlite_path(&app)?;
+    if target.exists() {
+        return Err("workspace is already stored in SQLite".to_string());
+    }
+
+    let split = workspace_store(&app)?;
+    let legacy_path = workspace_json_path(&app)?;
+    let mut workspace = if split.exists() {
+        split.load().map_err(|e| e.to_string())?.workspace
+    } else if legacy_path.exists() {
+        let text = fs::read_to_string(&legacy_path).map_err(|e| e.to_string())?;
+        Some(
+            migrate::parse_workspace(&text)
+                .map_err(|e| e.to_string())?
+                .workspace,
+        )
+    } else {
+        None
+    }
+    .ok_or_else(|| "there is no saved workspace to import".to_string())?;
+    validate::repair_workspace(&mut workspace);
+
+    let parent = target.parent().unwrap_or_else(|| Path::new("."));
+    let staging = parent.join("workspace.sqlite3.importing");
+    store::remove_database(&staging);
+    let limits = load_settings(&app)?.history;
+    let imported = SqliteStore::open(&staging)
+        .and_then(|db| db.with_history_limits(limits).save_all(&workspace));
+    if let Err(e) = imported {
+        store::remove_database(&staging);
+        return Err(e);
+    }
+    fs::rename(&staging, &target).map_err(|e| e.to_string())?;
+    Ok(workspace.documents.len())
+}
+
+#[tauri::command]
+fn load_document(app: tauri::AppHandle, doc_id: String) -> Result<Option<Document>, String> {
+    let mut doc = open_store(&app)?.load_document(&doc_id)?;
+    if let Some(doc) = &mut doc {
+        validate::repair_document(doc);
+    }
+    Ok(doc)
+}
+
+#[tauri::command]
+fn describe_history(app: tauri::AppHandle, doc_id: String) -> Result<HistoryDescription, String> {
+    let Some(doc) = open_store(&app)?.load_document(&doc_id)? else {
+        return Err(format!("document {doc_id} does not exist"));
+    };
+    let describe = |stack: &[model::HistoryEntry]| -> Vec<String> {
+        stack
+            .iter()
+            .rev()
+            .map(|entry| undo::describe_entry(entry, &doc.nodes))
+            .collect()
+    };
+    Ok(HistoryDescription {
+        undo: describe(&doc.undo_stack),
+        redo: describe(&doc.redo_stack),
+    })
+}
+
+/// Takes the document as the editor holds it, so history not yet saved is included.
+#[tauri::command]
+fn list_undo_branches(document: Document) -> Vec<UndoBranch> {
+    undo_tree::branches(&document)
+}
+
+#[tauri::command]
+fn jump_to_undo_state(mut document: Document, node_id: String) -> Result<Document, String> {
+    undo_tree::jump(&mut document, &node_id)?;
+    Ok(document)
+}
+
+#[tauri::command]
+fn restore_undo_branch(mut document: Document, tip_id: String) -> Result<Document, String> {
+    undo_tree::restore_branch(&mut document, &tip_id)?;
+    Ok(document)
+}
+
+/// Newest first. Revisions outlive their tab, so a closed document's id still lists them.
+#[tauri::command]
+fn list_revisions(app: tauri::AppHandle, doc_id: String) -> Result<Vec<RevisionInfo>, String> {
+    let path = revision::revision_path(&revisions_dir(&app)?, &doc_id);
+    let revisions = revision::list(&path)?;
+    Ok(revisions
+        .iter()
+        .enumerate()
+        .map(|(index, revision)| {
+            let doc = revision.to_document(&doc_id);
+            RevisionInfo {
+                saved_at_millis: revision.saved_at_millis,
+                title: doc.title().to_string(),
+                node_count: doc.nodes.len(),
+                changes: revisions
+                    .get(index + 1)
+                    .map(|older| tree_diff::diff(&older.state, &revision.state).summary()),
+            }
+        })
+        .collect())
+}
+
+#[tauri::command]
+fn preview_revision(
+    app: tauri::AppHandle,
+    doc_id: String,
+    saved_at_millis: u128,
+) -> Result<DocumentState, String> {
+    let path = revision::revision_path(&revisions_dir(&app)?, &doc_id);
+    Ok(revision::find(&path, saved_at_millis)?.state)
+}
+
+/// Takes the document as the editor holds it and returns it with the revision restored as
+/// an undoable step.
+#[tauri::command]
+fn restore_revision(
+    app: tauri::AppHandle,
+    mut document: Document,
+    saved_at_millis: u128,
+) -> Result<Document, String> {
+    let path = revision::revision_path(&revisions_dir(&app)?, &document.id);
+    let revision = revision::find(&path, saved_at_millis)?;
+    revision::restore(&mut document, &revision, now_millis())?;
+    Ok(document)
+}
+
+/// The revision as a new document for its own tab; the editor assigns a fresh id if this
+/// one is taken.
+#[tauri::command]
+fn fork_revision(
+    app: tauri::AppHandle,
+    doc_id: String,
+    saved_at_millis: u128,
+) -> Result<Document, String> {
+    let path = revision::revision_path(&revisions_dir(&app)?, &doc_id);
+    let revision = revision::find(&path, saved_at_millis)?;
+    let mut doc = revision.to_document(&format!("{doc_id}-{saved_at_millis}"));
+    validate::repair_document(&mut doc);
+    Ok(doc)
+}
+
+/// Compares two trees the editor holds, such as two open tabs.
+#[tauri::command]
+fn diff_documents(before: DocumentState, after: DocumentState) -> TreeDiff {
+    tree_diff::diff(&before, &after)
+}
+
+/// From the revision to the document as the editor holds it.
+#[tauri::command]
+fn diff_revision(
+    app: tauri::AppHandle,
+    document: DocumentState,
+    doc_id: String,
+    saved_at_millis: u128,
+) -> Result<TreeDiff, String> {
+    let path = revision::revision_path(&revisions_dir(&app)?, &doc_id);
+    let revision = revision::find(&path, saved_at_millis)?;
+    Ok(tree_diff::diff(&revision.state, &document))
+}
+
+/// From the backup's copy of the document to the document as the editor holds it.
+#[tauri::command]
+fn diff_backup(
+    app: tauri::AppHandle,
+    document: DocumentState,
+    doc_id: String,
+    file_name: String,
+) -> Result<TreeDiff, String> {
+    let dir = backup::backup_dir(&workspace_json_path(&app)?);
+    let text = backup::read_backup(&dir, &file_name)?;
+    let mut workspace = migrate::parse_workspace(&text)
+        .map_err(|e| e.to_string())?
+        .workspace;
+    validate::repair_workspace(&mut workspace);
+    let Some(backed_up) = workspace.documents.get(&doc_id) else {
+        return Err(format!("document {doc_id} is not in {file_name}"));
+    };
+    let before = DocumentState {
+        root_id: backed_up.root_id.clone(),
+        cursor_id: backed_up.cursor_id.clone(),
+        nodes: backed_up.nodes.clone(),
+    };
+    Ok(tree_diff::diff(&before, &document))
+}
+
+/// Writes the document as the editor holds it to `path`, chosen by the user in a save dialog.
+#[tauri::command]
+fn export_document(document: Document, path: String, format: ExportFormat) -> Result<(), String> {
+    let bytes = format::export(&document, &format)?;
+    durable::write_atomic(&durable::RealFs, Path::new(&path), &bytes)
+}
+
+/// Reads an outline file the user picked in an open dialog into a new document for its own
+/// tab. The editor assigns another document id if this one is taken.
+#[tauri::command]
+fn import_document(path: String) -> Result<Imported, String> {
+    read_outline(Path::new(&path))
+}
+
+/// Converts an outline file to another format without starting the app, for
+/// `vikokoro export <input> <output> [--doc <id>]`. Both formats follow the file
+/// extensions; the input may also be the app's own data (see `read_export_input`). Returns
+/// what the import had to leave out.
+pub fn export_file(
+    input: &Path,
+    output: &Path,
+    doc_id: Option<&str>,
+) -> Result<Vec<String>, String> {
+    let extension = output.extension().and_then(|e| e.to_str()).unwrap_or_default();
+    let format = format::format_for_extension(extension)?;
+    let imported = read_export_input(input, doc_id)?;
+    let bytes = format::export(&imported.document, &format)?;
+    durable::write_atomic(&durable::RealFs, output, &bytes)?;
+    Ok(imported.warnings)
+}
+
+/// An outline file, or one document of a workspace: `workspace.json` (or a backup of it),
+/// a single document as JSON, the `workspace/` directory or `workspace.sqlite3`. Without
+/// `doc_id` a workspace gives its active document.
+fn read_export_input(path: &Path, doc_id: Option<&str>) -> Result<Imported, String> {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+    let (mut workspace, warnings) = if path.join("manifest.json").is_file() {
+        let loaded = SplitStore::new(path.to_path_buf())
+            .load()
+            .map_err(|e| e.to_string())?;
+        (loaded.workspace, loaded.warnings)
+    } else if extension == "sqlite3" {
+        if !path.is_file() {
+            return Err(format!("{} does not exist", path.display()));
+        }
+        let loaded = SqliteStore::open(path)?.load().map_err(|e| e.to_string())?;
+        (loaded.workspace, loaded.warnings)
+    } else if extension == "json" {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let value: serde_json::Value = serde_json::from_str(&text).map_err(|e| e.to_string())?;
+        if value.get("rootId").is_some() {
+            let mut document: Document =
+                serde_json::from_value(value).map_err(|e| e.to_string())?;
+            validate::repair_document(&mut document);
+            return Ok(Imported {
+                document,
+                warnings: Vec::new(),
+            });
+        }
+        let workspace = migrate::parse_workspace(&text)
+            .map_err(|e| e.to_string())?
+            .workspace;
+        (Some(workspace), Vec::new())
+    } else {
+        return read_outline(path);
+    };
+    let Some(workspace) = workspace.as_mut() else {
+        return Err(format!("{} holds no documents", path.display()));
+    };
+    validate::repair_workspace(workspace);
+    let doc_id = doc_id.unwrap_or(&workspace.active_doc_id).to_string();
+    let Some(document) = workspace.documents.remove(&doc_id) else {
+        return Err(format!("document {doc_id} is not in {}", path.display()));
+    };
+    Ok(Imported { document, warnings })
+}
+
+fn read_outline(path: &Path) -> Result<Imported, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+    let title = path.file_stem().and_then(|s| s.to_str()).unwrap_or("Imported");
+    let mut imported =
+        format::import(&text, extension, title, &format!("import-{}", now_millis()))?;
+    validate::repair_document(&mut imported.document);
+    Ok(imported)
+}
+
+#[tauri::command]
+fn search_workspace(
+    app: tauri::AppHandle,
+    query: String,
+    limit: Option<usize>,
+) -> Result<Vec<NodeMatch>, String> {
+    if query.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    open_store(&app)?.search_nodes(&query, limit.unwrap_or(100))
+}
+
+#[tauri::command]
+fn get_settings(app: tauri::AppHandle) -> Result<Settings, String> {
+    load_settings(&app)
+}
+
+/// Takes effect from the next save: history beyond the new limits is dropped the next time
+/// a document is written.
+#[tauri::command]
+fn set_settings(app: tauri::AppHandle, settings: Settings) -> Result<(), String> {
+    let path = settings_path(&app)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    settings::save(&path, &settings)
+}
+
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 #[tauri::command]
 fn greet(name: &str) -> String {
@@ -129,7 +980,37 @@ fn greet(name: &str) -> String {
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
-        .invoke_handler(tauri::generate_handler![greet, load_workspace, save_workspace])
+        .plugin(tauri_plugin_dialog::init())
+        .invoke_handler(tauri::generate_handler![
+            greet,
+            load_workspace,
+            save_workspace,
+            save_workspace_changes,
+            list_broken_workspaces,
+            recover_broken_workspace,
+            list_backups,
+            restore_backup,
+            list_merge_candidates,
+            merge_workspace,
+            import_into_sqlite,
+            load_document,
+            search_workspace,
+            describe_history,
+            list_undo_branches,
+            jump_to_undo_state,
+            restore_undo_branch,
+            list_revisions,
+            preview_revision,
+            restore_revision,
+            fork_revision,
+            diff_documents,
+            diff_revision,
+            diff_backup,
+            export_document,
+            import_document,
+            get_settings,
+            set_settings
+        ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
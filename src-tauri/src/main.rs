@@ -1,6 +1,32 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-fn main() {
-    vikokoro_lib::run()
+use std::path::Path;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let export = match args.as_slice() {
+        [command, input, output] if command == "export" => Some((input, output, None)),
+        [command, input, output, flag, doc_id] if command == "export" && flag == "--doc" => {
+            Some((input, output, Some(doc_id.as_str())))
+        }
+        _ => None,
+    };
+    if let Some((input, output, doc_id)) = export {
+        return match vikokoro_lib::export_file(Path::new(input), Path::new(output), doc_id) {
+            Ok(warnings) => {
+                for warning in warnings {
+                    eprintln!("warning: {warning}");
+                }
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                eprintln!("error: {e}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+    vikokoro_lib::run();
+    ExitCode::SUCCESS
 }
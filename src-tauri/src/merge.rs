@@ -0,0 +1,666 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+use crate::model::{Document, DocumentState, Node, TabRef, UndoTree, Workspace};
+use crate::undo;
+use crate::undo_tree;
+use crate::validate;
+
+type Nodes = HashMap<String, Node>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Side {
+    Ours,
+    Theirs,
+}
+
+/// A change the merge could not take from both sides. Each says which version was kept, so
+/// nothing is lost silently.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(
+    tag = "kind",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum Conflict {
+    /// Both sides changed the text differently; ours was kept.
+    TextEditedBoth {
+        doc_id: String,
+        node_id: String,
+        base: String,
+        ours: String,
+        theirs: String,
+    },
+    /// One side deleted a node the other re-texted; the node was kept with the new text.
+    DeletedAndEdited {
+        doc_id: String,
+        node_id: String,
+        deleted_in: Side,
+        text: String,
+    },
+    /// Both sides moved the node to different parents; ours was kept.
+    MovedBoth {
+        doc_id: String,
+        node_id: String,
+        ours_parent_id: Option<String>,
+        theirs_parent_id: Option<String>,
+    },
+    /// Taking both sides' moves would put the node inside its own subtree; it stays under
+    /// our parent.
+    MovedIntoCycle {
+        doc_id: String,
+        node_id: String,
+        ours_parent_id: Option<String>,
+        theirs_parent_id: Option<String>,
+    },
+    /// One side closed a document the other edited; the document was kept.
+    DocumentDeletedAndEdited { doc_id: String, deleted_in: Side },
+    /// The two sides have different roots for the same document id; ours was kept.
+    Unrelated { doc_id: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergedWorkspace {
+    pub workspace: Workspace,
+    pub conflicts: Vec<Conflict>,
+}
+
+/// `Some` with the merged value when at most one side changed it (or both changed it the
+/// same way), `None` when both changed it differently.
+fn pick<T: PartialEq + Clone>(base: &T, ours: &T, theirs: &T) -> Option<T> {
+    if ours == theirs || theirs == base {
+        Some(ours.clone())
+    } else if ours == base {
+        Some(theirs.clone())
+    } else {
+        None
+    }
+}
+
+struct Merged {
+    text: String,
+    parent_id: Option<String>,
+}
+
+fn merge_node(
+    doc_id: &str,
+    id: &str,
+    sides: (Option<&Node>, Option<&Node>, Option<&Node>),
+    conflicts: &mut Vec<Conflict>,
+) -> Option<Merged> {
+    let keep = |node: &Node| Merged {
+        text: node.text.clone(),
+        parent_id: node.parent_id.clone(),
+    };
+    match sides {
+        (Some(base), Some(ours), Some(theirs)) => {
+            let text = pick(&base.text, &ours.text, &theirs.text).unwrap_or_else(|| {
+                conflicts.push(Conflict::TextEditedBoth {
+                    doc_id: doc_id.to_string(),
+                    node_id: id.to_string(),
+                    base: base.text.clone(),
+                    ours: ours.text.clone(),
+                    theirs: theirs.text.clone(),
+                });
+                ours.text.clone()
+            });
+            let parent_id = pick(&base.parent_id, &ours.parent_id, &theirs.parent_id)
+                .unwrap_or_else(|| {
+                    conflicts.push(Conflict::MovedBoth {
+                        doc_id: doc_id.to_string(),
+                        node_id: id.to_string(),
+                        ours_parent_id: ours.parent_id.clone(),
+                        theirs_parent_id: theirs.parent_id.clone(),
+                    });
+                    ours.parent_id.clone()
+                });
+            Some(Merged { text, parent_id })
+        }
+        (Some(base), None, Some(kept)) | (Some(base), Some(kept), None) => {
+            if kept.text == base.text {
+                return None;
+            }
+            let deleted_in = match sides.1 {
+                None => Side::Ours,
+                Some(_) => Side::Theirs,
+            };
+            conflicts.push(Conflict::DeletedAndEdited {
+                doc_id: doc_id.to_string(),
+                node_id: id.to_string(),
+                deleted_in,
+                text: kept.text.clone(),
+            });
+            Some(keep(kept))
+        }
+        (None, Some(ours), Some(theirs)) => {
+            if ours.text != theirs.text {
+                conflicts.push(Conflict::TextEditedBoth {
+                    doc_id: doc_id.to_string(),
+                    node_id: id.to_string(),
+                    base: String::new(),
+                    ours: ours.text.clone(),
+                    theirs: theirs.text.clone(),
+                });
+            }
+            Some(keep(ours))
+        }
+        (None, Some(added), None) | (None, None, Some(added)) => Some(keep(added)),
+        (Some(_), None, None) | (None, None, None) => None,
+    }
+}
+
+/// Children of `parent_id` in merged order: the order of the side that changed it (ours if
+/// both did), with children only the other side placed here inserted after the sibling they
+/// followed there.
+fn merge_children(
+    parent_id: &str,
+    trees: [&Nodes; 3],
+    children_of: &HashMap<String, Vec<String>>,
+) -> Vec<String> {
+    let belongs: HashSet<&String> = children_of
+        .get(parent_id)
+        .map(|ids| ids.iter().collect())
+        .unwrap_or_default();
+    let [base, ours, theirs] = trees.map(|nodes| {
+        nodes
+            .get(parent_id)
+            .map_or(&[][..], |node| node.children_ids.as_slice())
+    });
+    let (primary, secondary) = if ours == base {
+        (theirs, ours)
+    } else {
+        (ours, theirs)
+    };
+
+    let mut result: Vec<String> = Vec::new();
+    for id in primary {
+        if belongs.contains(id) && !result.contains(id) {
+            result.push(id.clone());
+        }
+    }
+    for (index, id) in secondary.iter().enumerate() {
+        if !belongs.contains(id) || result.contains(id) {
+            continue;
+        }
+        let at = secondary[..index]
+            .iter()
+            .rev()
+            .find_map(|prev| result.iter().position(|r| r == prev))
+            .map_or(0, |position| position + 1);
+        result.insert(at, id.clone());
+    }
+    for id in children_of.get(parent_id).into_iter().flatten() {
+        if !result.contains(id) {
+            result.push(id.clone());
+        }
+    }
+    result
+}
+
+/// The nodes of the first loop found among `parents`, in order along the loop.
+fn find_cycle(parents: &HashMap<String, Option<String>>) -> Option<Vec<String>> {
+    let mut ids: Vec<&String> = parents.keys().collect();
+    ids.sort();
+    let mut done: HashSet<&String> = HashSet::new();
+    for start in ids {
+        let mut path: Vec<&String> = Vec::new();
+        let mut at = Some(start);
+        while let Some(id) = at.filter(|id| !done.contains(id)) {
+            if let Some(index) = path.iter().position(|p| *p == id) {
+                return Some(path[index..].iter().map(|id| id.to_string()).collect());
+            }
+            path.push(id);
+            at = parents.get(id).and_then(|parent| parent.as_ref());
+        }
+        done.extend(path);
+    }
+    None
+}
+
+fn merge_nodes(
+    doc_id: &str,
+    base: &Nodes,
+    ours: &Nodes,
+    theirs: &Nodes,
+    conflicts: &mut Vec<Conflict>,
+) -> Nodes {
+    let mut ids: Vec<&String> = base.keys().chain(ours.keys()).chain(theirs.keys()).collect();
+    ids.sort();
+    ids.dedup();
+
+    let mut merged: HashMap<String, Merged> = HashMap::new();
+    for id in ids {
+        let sides = (base.get(id), ours.get(id), theirs.get(id));
+        if let Some(node) = merge_node(doc_id, id, sides, conflicts) {
+            merged.insert(id.clone(), node);
+        }
+    }
+
+    // A deleted node's children were promoted to its parent, so anything still pointing at
+    // it (a child the other side added, say) goes there too.
+    let surviving = |mut parent_id: Option<String>| {
+        let mut seen = HashSet::new();
+        while let Some(missing) = parent_id.clone().filter(|p| !merged.contains_key(p)) {
+            if !seen.insert(missing.clone()) {
+                break;
+            }
+            parent_id = base.get(&missing).and_then(|node| node.parent_id.clone());
+        }
+        parent_id
+    };
+    let mut parents: HashMap<String, Option<String>> = merged
+        .iter()
+        .map(|(id, node)| (id.clone(), surviving(node.parent_id.clone())))
+        .collect();
+
+    // Moves that are fine on their own can still put a node inside its own subtree when
+    // both are taken. Our tree has no loops, so each loop has a node that got its parent
+    // from theirs; it goes back to ours.
+    while let Some(cycle) = find_cycle(&parents) {
+        let Some((id, parent_id)) = cycle.iter().find_map(|id| {
+            let parent_id = surviving(ours.get(id)?.parent_id.clone());
+            (parent_id != parents[id]).then(|| (id.clone(), parent_id))
+        }) else {
+            break;
+        };
+        conflicts.push(Conflict::MovedIntoCycle {
+            doc_id: doc_id.to_string(),
+            node_id: id.clone(),
+            ours_parent_id: parent_id.clone(),
+            theirs_parent_id: parents[&id].clone(),
+        });
+        parents.insert(id, parent_id);
+    }
+
+    let mut children_of: HashMap<String, Vec<String>> = HashMap::new();
+    let mut ids: Vec<&String> = parents.keys().collect();
+    ids.sort();
+    for id in ids {
+        if let Some(parent_id) = &parents[id] {
+            children_of.entry(parent_id.clone()).or_default().push(id.clone());
+        }
+    }
+
+    merged
+        .into_iter()
+        .map(|(id, node)| {
+            let node = Node {
+                id: id.clone(),
+                text: node.text,
+                parent_id: parents.remove(&id).flatten(),
+                children_ids: merge_children(&id, [base, ours, theirs], &children_of),
+            };
+            (id, node)
+        })
+        .collect()
+}
+
+fn state_of(doc: &Document) -> DocumentState {
+    DocumentState {
+        root_id: doc.root_id.clone(),
+        cursor_id: doc.cursor_id.clone(),
+        nodes: doc.nodes.clone(),
+    }
+}
+
+/// Merges the trees and keeps our history, with the merge itself recorded on top as one
+/// undoable step; a merge that changes nothing leaves our document as it is. `base` is
+/// `None` when the document did not exist in the common ancestor.
+pub fn merge_document(
+    base: Option<&Document>,
+    ours: &Document,
+    theirs: &Document,
+    now_millis: u64,
+) -> (Document, Vec<Conflict>) {
+    let mut conflicts = Vec::new();
+    if ours.root_id != theirs.root_id {
+        conflicts.push(Conflict::Unrelated {
+            doc_id: ours.id.clone(),
+        });
+        return (ours.clone(), conflicts);
+    }
+    let empty = Nodes::new();
+    let base_nodes = base
+        .filter(|base| base.root_id == ours.root_id)
+        .map_or(&empty, |base| &base.nodes);
+
+    let mut merged = Document {
+        nodes: merge_nodes(&ours.id, base_nodes, &ours.nodes, &theirs.nodes, &mut conflicts),
+        ..ours.clone()
+    };
+    if !merged.nodes.contains_key(&merged.cursor_id) {
+        merged.cursor_id = merged.root_id.clone();
+    }
+    validate::repair_document(&mut merged);
+
+    let entry = undo::entry_between(&state_of(ours), &state_of(&merged));
+    if entry.ops.is_empty() {
+        return (ours.clone(), conflicts);
+    }
+    let mut doc = ours.clone();
+    if undo::apply_entry(&mut doc.nodes, &mut doc.cursor_id, &entry).is_ok() {
+        undo_tree::push(&mut doc, entry, now_millis);
+    } else {
+        doc = Document {
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            undo_tree: UndoTree::default(),
+            ..merged
+        };
+    }
+    (doc, conflicts)
+}
+
+/// Three-way merge of two workspaces that diverged from `base`. Documents and nodes are
+/// matched by id; tabs follow ours, with documents only theirs has opened appended.
+pub fn merge_workspaces(
+    base: &Workspace,
+    ours: &Workspace,
+    theirs: &Workspace,
+    now_millis: u64,
+) -> MergedWorkspace {
+    let mut conflicts = Vec::new();
+    let mut documents = HashMap::new();
+    let mut ids: Vec<&String> = base
+        .documents
+        .keys()
+        .chain(ours.documents.keys())
+        .chain(theirs.documents.keys())
+        .collect();
+    ids.sort();
+    ids.dedup();
+
+    for id in ids {
+        let sides = (
+            base.documents.get(id),
+            ours.documents.get(id),
+            theirs.documents.get(id),
+        );
+        let doc = match sides {
+            (base, Some(ours), Some(theirs)) => {
+                let (doc, found) = merge_document(base, ours, theirs, now_millis);
+                conflicts.extend(found);
+                Some(doc)
+            }
+            (Some(base), None, Some(kept)) | (Some(base), Some(kept), None) => {
+                (kept.nodes != base.nodes).then(|| {
+                    let deleted_in = match sides.1 {
+                        None => Side::Ours,
+                        Some(_) => Side::Theirs,
+                    };
+                    conflicts.push(Conflict::DocumentDeletedAndEdited {
+                        doc_id: id.clone(),
+                        deleted_in,
+                    });
+                    kept.clone()
+                })
+            }
+            (None, Some(added), None) | (None, None, Some(added)) => Some(added.clone()),
+            (Some(_), None, None) | (None, None, None) => None,
+        };
+        if let Some(doc) = doc {
+            documents.insert(id.clone(), doc);
+        }
+    }
+
+    let mut tabs: Vec<TabRef> = Vec::new();
+    for tab in ours.tabs.iter().chain(&theirs.tabs) {
+        if documents.contains_key(&tab.doc_id) && !tabs.iter().any(|t| t.doc_id == tab.doc_id) {
+            tabs.push(tab.clone());
+        }
+    }
+    let mut untabbed: Vec<&String> = documents
+        .keys()
+        .filter(|id| !tabs.iter().any(|t| &t.doc_id == *id))
+        .collect();
+    untabbed.sort();
+    tabs.extend(untabbed.into_iter().map(|id| TabRef { doc_id: id.clone() }));
+
+    let active_doc_id = if documents.contains_key(&ours.active_doc_id) {
+        ours.active_doc_id.clone()
+    } else {
+        tabs.first().map(|t| t.doc_id.clone()).unwrap_or_default()
+    };
+    MergedWorkspace {
+        workspace: Workspace {
+            schema_version: ours.schema_version,
+            tabs,
+            active_doc_id,
+            documents,
+        },
+        conflicts,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{document, state};
+
+    #[test]
+    fn merges_edits_from_both_sides_and_reports_conflicts() {
+        let base = document(&[
+            ("root", "Plan", &["a", "b", "c"]),
+            ("a", "Budget", &[]),
+            ("b", "Schedule", &[]),
+            ("c", "Team", &[]),
+        ]);
+        let ours = document(&[
+            ("root", "Plan", &["a", "n", "b"]),
+            ("a", "Budget 2025", &[]),
+            ("n", "Risks", &[]),
+            ("b", "Timeline", &[]),
+        ]);
+        let theirs = document(&[
+            ("root", "Plan", &["a", "b", "c"]),
+            ("a", "Budget", &["m"]),
+            ("m", "Hiring", &[]),
+            ("b", "Dates", &[]),
+            ("c", "Team members", &[]),
+        ]);
+
+        let (merged, conflicts) = merge_document(Some(&base), &ours, &theirs, 1);
+
+        let root = &merged.nodes["root"];
+        assert_eq!(root.children_ids, vec!["a", "n", "b", "c"]);
+        assert_eq!(merged.nodes["a"].text, "Budget 2025");
+        assert_eq!(merged.nodes["a"].children_ids, vec!["m"]);
+        assert_eq!(merged.nodes["b"].text, "Timeline");
+        assert_eq!(
+            conflicts,
+            vec![
+                Conflict::TextEditedBoth {
+                    doc_id: "doc".to_string(),
+                    node_id: "b".to_string(),
+                    base: "Schedule".to_string(),
+                    ours: "Timeline".to_string(),
+                    theirs: "Dates".to_string(),
+                },
+                Conflict::DeletedAndEdited {
+                    doc_id: "doc".to_string(),
+                    node_id: "c".to_string(),
+                    deleted_in: Side::Ours,
+                    text: "Team members".to_string(),
+                },
+            ]
+        );
+        assert_eq!(merged.undo_stack.len(), 1);
+    }
+
+    #[test]
+    fn merge_without_changes_keeps_history() {
+        let tree: &[(&str, &str, &[&str])] = &[("root", "Plan", &["a"]), ("a", "Budget", &[])];
+        let base = document(&[("root", "Plan", &[])]);
+        let mut ours = base.clone();
+        undo_tree::push(
+            &mut ours,
+            undo::entry_between(&state_of(&base), &state(tree)),
+            1,
+        );
+        ours.nodes = document(tree).nodes;
+        let theirs = document(tree);
+
+        let (merged, conflicts) = merge_document(Some(&base), &ours, &theirs, 2);
+        assert!(conflicts.is_empty());
+        assert_eq!(merged.nodes, ours.nodes);
+        assert_eq!(merged.undo_stack, ours.undo_stack);
+        assert_eq!(merged.undo_tree, ours.undo_tree);
+    }
+
+    fn named(id: &str, tree: &[(&str, &str, &[&str])]) -> Document {
+        Document {
+            id: id.to_string(),
+            ..document(tree)
+        }
+    }
+
+    fn workspace(tabs: &[&str], docs: Vec<Document>) -> Workspace {
+        Workspace {
+            schema_version: 2,
+            tabs: tabs
+                .iter()
+                .map(|id| TabRef {
+                    doc_id: id.to_string(),
+                })
+                .collect(),
+            active_doc_id: tabs.first().unwrap_or(&"").to_string(),
+            documents: docs.into_iter().map(|doc| (doc.id.clone(), doc)).collect(),
+        }
+    }
+
+    fn tab_ids(workspace: &Workspace) -> Vec<&str> {
+        workspace.tabs.iter().map(|t| t.doc_id.as_str()).collect()
+    }
+
+    const ONE: &[(&str, &str, &[&str])] = &[("root", "One", &[])];
+
+    #[test]
+    fn workspaces_merge_tabs_and_documents() {
+        let base = workspace(&["a", "b"], vec![named("a", ONE), named("b", ONE)]);
+        let ours = workspace(
+            &["b", "a", "o"],
+            vec![named("a", ONE), named("b", ONE), named("o", ONE)],
+        );
+        let theirs = workspace(
+            &["a", "t", "b"],
+            vec![named("a", ONE), named("b", ONE), named("t", ONE)],
+        );
+
+        let merged = merge_workspaces(&base, &ours, &theirs, 1);
+        assert!(merged.conflicts.is_empty());
+        assert_eq!(tab_ids(&merged.workspace), vec!["b", "a", "o", "t"]);
+        assert_eq!(merged.workspace.active_doc_id, "b");
+        assert_eq!(merged.workspace.documents.len(), 4);
+    }
+
+    #[test]
+    fn closed_document_is_kept_only_if_the_other_side_edited_it() {
+        let edited: &[(&str, &str, &[&str])] = &[("root", "One edited", &[])];
+        let base = workspace(&["a", "b"], vec![named("a", ONE), named("b", ONE)]);
+        let ours = workspace(&["b"], vec![named("b", ONE)]);
+        let theirs = workspace(&["a", "b"], vec![named("a", edited), named("b", ONE)]);
+
+        let merged = merge_workspaces(&base, &ours, &theirs, 1);
+        assert_eq!(
+            merged.conflicts,
+            vec![Conflict::DocumentDeletedAndEdited {
+                doc_id: "a".to_string(),
+                deleted_in: Side::Ours,
+            }]
+        );
+        assert_eq!(
+            merged.workspace.documents["a"].nodes["root"].text,
+            "One edited"
+        );
+        assert_eq!(tab_ids(&merged.workspace), vec!["b", "a"]);
+
+        // Closed on one side and untouched on the other: it stays closed.
+        let theirs = workspace(&["a", "b"], vec![named("a", ONE), named("b", ONE)]);
+        let merged = merge_workspaces(&base, &ours, &theirs, 1);
+        assert!(merged.conflicts.is_empty());
+        assert_eq!(tab_ids(&merged.workspace), vec!["b"]);
+    }
+
+    #[test]
+    fn moves_to_different_parents_keep_ours() {
+        let base = document(&[
+            ("root", "Plan", &["a", "b", "c"]),
+            ("a", "A", &[]),
+            ("b", "B", &[]),
+            ("c", "C", &[]),
+        ]);
+        let ours = document(&[
+            ("root", "Plan", &["a", "b"]),
+            ("a", "A", &["c"]),
+            ("b", "B", &[]),
+            ("c", "C", &[]),
+        ]);
+        let theirs = document(&[
+            ("root", "Plan", &["a", "b"]),
+            ("a", "A", &[]),
+            ("b", "B", &["c"]),
+            ("c", "C", &[]),
+        ]);
+
+        let (merged, conflicts) = merge_document(Some(&base), &ours, &theirs, 1);
+        assert_eq!(
+            conflicts,
+            vec![Conflict::MovedBoth {
+                doc_id: "doc".to_string(),
+                node_id: "c".to_string(),
+                ours_parent_id: Some("a".to_string()),
+                theirs_parent_id: Some("b".to_string()),
+            }]
+        );
+        assert_eq!(merged.nodes["a"].children_ids, vec!["c"]);
+        assert!(merged.nodes["b"].children_ids.is_empty());
+    }
+
+    #[test]
+    fn opposing_moves_that_would_loop_are_reported() {
+        let base = document(&[
+            ("root", "Plan", &["a", "b"]),
+            ("a", "A", &[]),
+            ("b", "B", &[]),
+        ]);
+        // Ours moves A under B; theirs moves B under A.
+        let ours = document(&[
+            ("root", "Plan", &["b"]),
+            ("a", "A", &[]),
+            ("b", "B", &["a"]),
+        ]);
+        let theirs = document(&[
+            ("root", "Plan", &["a"]),
+            ("a", "A", &["b"]),
+            ("b", "B", &[]),
+        ]);
+
+        let (merged, conflicts) = merge_document(Some(&base), &ours, &theirs, 1);
+        assert_eq!(
+            conflicts,
+            vec![Conflict::MovedIntoCycle {
+                doc_id: "doc".to_string(),
+                node_id: "b".to_string(),
+                ours_parent_id: Some("root".to_string()),
+                theirs_parent_id: Some("a".to_string()),
+            }]
+        );
+        assert_eq!(merged.nodes, ours.nodes);
+    }
+
+    #[test]
+    fn documents_with_different_roots_are_not_merged() {
+        let ours = document(&[("root", "Plan", &[])]);
+        let theirs = document(&[("other", "Plan", &[])]);
+        let (merged, conflicts) = merge_document(None, &ours, &theirs, 1);
+        assert_eq!(
+            conflicts,
+            vec![Conflict::Unrelated {
+                doc_id: "doc".to_string()
+            }]
+        );
+        assert_eq!(merged.nodes, ours.nodes);
+    }
+}
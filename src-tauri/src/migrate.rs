@@ -0,0 +1,241 @@
+use serde_json::Value;
+
+use crate::model::{DocumentState, HistoryEntry, Workspace};
+use crate::undo;
+
+type Migration = fn(&mut Value) -> Result<(), String>;
+
+/// `MIGRATIONS[n]` upgrades a workspace from schema version `n` to `n + 1`.
+/// Append new steps here; never edit a step that has already shipped.
+const MIGRATIONS: &[Migration] = &[migrate_v0_to_v1, migrate_v1_to_v2];
+
+pub const CURRENT_SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+#[derive(Debug)]
+pub enum MigrateError {
+    /// The file was written by a newer build; it must be left untouched.
+    TooNew { found: u32, supported: u32 },
+    /// The file is not a workspace we can read at any version.
+    Invalid(String),
+}
+
+impl std::fmt::Display for MigrateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MigrateError::TooNew { found, supported } => write!(
+                f,
+                "workspace.json has schema version {found}, but this build supports up to {supported}"
+            ),
+            MigrateError::Invalid(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+pub struct Migrated {
+    pub workspace: Workspace,
+    pub from_version: u32,
+}
+
+pub fn schema_version_of(value: &Value) -> Result<u32, MigrateError> {
+    let object = value
+        .as_object()
+        .ok_or_else(|| MigrateError::Invalid("workspace.json is not an object".to_string()))?;
+    match object.get("schemaVersion") {
+        None => Ok(0),
+        Some(version) => version
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| MigrateError::Invalid(format!("invalid schemaVersion: {version}"))),
+    }
+}
+
+/// Upgrades `value` in place to `CURRENT_SCHEMA_VERSION` and returns the version it started at.
+pub fn migrate_value(value: &mut Value) -> Result<u32, MigrateError> {
+    let from_version = schema_version_of(value)?;
+    if from_version > CURRENT_SCHEMA_VERSION {
+        return Err(MigrateError::TooNew {
+            found: from_version,
+            supported: CURRENT_SCHEMA_VERSION,
+        });
+    }
+
+    for version in from_version..CURRENT_SCHEMA_VERSION {
+        MIGRATIONS[version as usize](value).map_err(|e| {
+            MigrateError::Invalid(format!("migration v{version} -> v{}: {e}", version + 1))
+        })?;
+        value["schemaVersion"] = Value::from(version + 1);
+    }
+    Ok(from_version)
+}
+
+pub fn parse_workspace(text: &str) -> Result<Migrated, MigrateError> {
+    let mut value: Value =
+        serde_json::from_str(text).map_err(|e| MigrateError::Invalid(e.to_string()))?;
+    let from_version = migrate_value(&mut value)?;
+    let workspace =
+        serde_json::from_value(value).map_err(|e| MigrateError::Invalid(e.to_string()))?;
+    Ok(Migrated {
+        workspace,
+        from_version,
+    })
+}
+
+/// v0 is the unversioned layout from M1/M2; v1 only adds `schemaVersion`.
+fn migrate_v0_to_v1(_value: &mut Value) -> Result<(), String> {
+    Ok(())
+}
+
+/// v2 stores undo/redo entries as edit ops instead of full snapshots. Each pair of
+/// neighbouring snapshots becomes one entry; stacks that cannot be read are dropped, since
+/// losing history is better than losing the document.
+fn migrate_v1_to_v2(value: &mut Value) -> Result<(), String> {
+    let Some(documents) = value.get_mut("documents").and_then(Value::as_object_mut) else {
+        return Ok(());
+    };
+    for doc in documents.values_mut() {
+        let Some(doc) = doc.as_object_mut() else {
+            continue;
+        };
+        let current = serde_json::from_value::<DocumentState>(Value::Object(doc.clone())).ok();
+        let mut stack = |key: &str| -> Vec<DocumentState> {
+            doc.get_mut(key)
+                .map(Value::take)
+                .and_then(|states| serde_json::from_value(states).ok())
+                .unwrap_or_default()
+        };
+        let (undo_states, redo_states) = (stack("undoStack"), stack("redoStack"));
+
+        let mut undo_stack: Vec<HistoryEntry> = Vec::new();
+        let mut redo_stack: Vec<HistoryEntry> = Vec::new();
+        if let Some(current) = current {
+            // undo[i] led from snapshot i to snapshot i + 1; the last one led to `current`.
+            let undo_chain: Vec<&DocumentState> =
+                undo_states.iter().chain([&current]).collect();
+            undo_stack = undo_chain
+                .windows(2)
+                .map(|pair| undo::entry_between(pair[0], pair[1]))
+                .collect();
+            // redo[j] leads from snapshot j + 1 (or `current` for the top) to snapshot j.
+            let redo_chain: Vec<&DocumentState> =
+                redo_states.iter().chain([&current]).collect();
+            redo_stack = redo_chain
+                .windows(2)
+                .map(|pair| undo::entry_between(pair[1], pair[0]))
+                .collect();
+        }
+        doc.insert(
+            "undoStack".to_string(),
+            serde_json::to_value(undo_stack).map_err(|e| e.to_string())?,
+        );
+        doc.insert(
+            "redoStack".to_string(),
+            serde_json::to_value(redo_stack).map_err(|e| e.to_string())?,
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Document, EditOp};
+
+    const V0: &str = include_str!("../tests/fixtures/workspace-v0.json");
+    const V1: &str = include_str!("../tests/fixtures/workspace-v1.json");
+
+    #[test]
+    fn v0_fixture_migrates_to_current() {
+        let migrated = parse_workspace(V0).unwrap();
+        assert_eq!(migrated.from_version, 0);
+        assert_eq!(migrated.workspace.schema_version, CURRENT_SCHEMA_VERSION);
+
+        let doc = &migrated.workspace.documents["doc-1"];
+        assert_eq!(doc.nodes[&doc.root_id].text, "Plan");
+        assert_eq!(doc.nodes[&doc.root_id].children_ids, vec!["n-2", "n-3"]);
+        assert_eq!(doc.undo_stack.len(), 1);
+    }
+
+    #[test]
+    fn v1_fixture_is_loaded_as_is() {
+        let migrated = parse_workspace(V1).unwrap();
+        assert_eq!(migrated.from_version, 1);
+        assert_eq!(migrated.workspace.schema_version, CURRENT_SCHEMA_VERSION);
+        assert_eq!(migrated.workspace.tabs.len(), 2);
+    }
+
+    #[test]
+    fn v1_snapshot_history_becomes_edit_ops() {
+        let mut value: Value = serde_json::from_str(V1).unwrap();
+        migrate_v1_to_v2(&mut value).unwrap();
+        let doc: Document = serde_json::from_value(value["documents"]["doc-2"].take()).unwrap();
+
+        let undo = &doc.undo_stack[..];
+        assert_eq!(undo.len(), 1);
+        assert_eq!(
+            (
+                undo[0].cursor_before.as_str(),
+                undo[0].cursor_after.as_str()
+            ),
+            ("m-1", "m-2")
+        );
+        assert_eq!(
+            undo[0].ops,
+            vec![EditOp::InsertNode {
+                node_id: "m-2".to_string(),
+                parent_id: "m-1".to_string(),
+                index: 0,
+                text: "Outline export".to_string(),
+                children_ids: Vec::new(),
+            }]
+        );
+        assert_eq!(
+            doc.redo_stack
+                .iter()
+                .map(|entry| &entry.ops)
+                .collect::<Vec<_>>(),
+            vec![&vec![EditOp::EditText {
+                node_id: "m-2".to_string(),
+                before: "Outline export".to_string(),
+                after: "Outline and export".to_string(),
+            }]]
+        );
+
+        let mut nodes = doc.nodes.clone();
+        let mut cursor_id = doc.cursor_id.clone();
+        undo::apply_entry(&mut nodes, &mut cursor_id, &undo::invert_entry(&undo[0])).unwrap();
+        assert_eq!(cursor_id, "m-1");
+        assert_eq!(nodes.len(), 1);
+        assert!(nodes["m-1"].children_ids.is_empty());
+    }
+
+    #[test]
+    fn every_migration_step_stamps_its_version() {
+        let mut value: Value = serde_json::from_str(V0).unwrap();
+        migrate_value(&mut value).unwrap();
+        assert_eq!(schema_version_of(&value).unwrap(), CURRENT_SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn newer_schema_is_rejected_without_parsing() {
+        let text = format!(
+            r#"{{"schemaVersion": {}, "somethingNew": true}}"#,
+            CURRENT_SCHEMA_VERSION + 1
+        );
+        assert!(matches!(
+            parse_workspace(&text),
+            Err(MigrateError::TooNew { .. })
+        ));
+    }
+
+    #[test]
+    fn garbage_is_invalid() {
+        assert!(matches!(
+            parse_workspace("{\"tabs\": ["),
+            Err(MigrateError::Invalid(_))
+        ));
+        assert!(matches!(
+            parse_workspace(r#"{"schemaVersion": "one"}"#),
+            Err(MigrateError::Invalid(_))
+        ));
+    }
+}
@@ -0,0 +1,234 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::journal::DocumentOps;
+use crate::undo_tree;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Workspace {
+    #[serde(default)]
+    pub schema_version: u32,
+    pub tabs: Vec<TabRef>,
+    pub active_doc_id: String,
+    pub documents: HashMap<String, Document>,
+}
+
+/// Autosave payload: the whole tab layout and every live document id, but only the
+/// documents that changed since the previous save — either whole (`changed`) or as
+/// journal ops (`journal`).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceChanges {
+    pub tabs: Vec<TabRef>,
+    pub active_doc_id: String,
+    pub doc_ids: Vec<String>,
+    pub changed: Vec<Document>,
+    #[serde(default)]
+    pub journal: Vec<DocumentOps>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TabRef {
+    pub doc_id: String,
+}
+
+/// The stacks are what the editor undoes and redoes with. On disk only the undo tree is
+/// kept (see `StoredDocument`); a document read without stacks gets them back from it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", from = "DocumentFields")]
+pub struct Document {
+    pub id: String,
+    pub root_id: String,
+    pub cursor_id: String,
+    pub nodes: HashMap<String, Node>,
+    pub undo_stack: Vec<HistoryEntry>,
+    pub redo_stack: Vec<HistoryEntry>,
+    pub undo_tree: UndoTree,
+}
+
+/// A document as written to disk: the undo tree holds every history step, so the stacks
+/// are left out. The undo stack is the tree's path to the current state; the redo stack is
+/// the line from there out to `redo_tip_id`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredDocument<'a> {
+    id: &'a str,
+    root_id: &'a str,
+    cursor_id: &'a str,
+    nodes: &'a HashMap<String, Node>,
+    undo_tree: &'a UndoTree,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    redo_tip_id: Option<String>,
+}
+
+impl<'a> From<&'a Document> for StoredDocument<'a> {
+    fn from(doc: &'a Document) -> Self {
+        StoredDocument {
+            id: &doc.id,
+            root_id: &doc.root_id,
+            cursor_id: &doc.cursor_id,
+            nodes: &doc.nodes,
+            undo_tree: &doc.undo_tree,
+            redo_tip_id: undo_tree::redo_tip(&doc.undo_tree, &doc.redo_stack),
+        }
+    }
+}
+
+/// Either shape of a document: with stacks (from the editor, or files older than the
+/// tree), or stored without them.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DocumentFields {
+    id: String,
+    root_id: String,
+    cursor_id: String,
+    nodes: HashMap<String, Node>,
+    undo_stack: Option<Vec<HistoryEntry>>,
+    redo_stack: Option<Vec<HistoryEntry>>,
+    #[serde(default)]
+    undo_tree: UndoTree,
+    #[serde(default)]
+    redo_tip_id: Option<String>,
+}
+
+impl From<DocumentFields> for Document {
+    fn from(fields: DocumentFields) -> Self {
+        let (undo, redo) = undo_tree::stacks(&fields.undo_tree, fields.redo_tip_id.as_deref());
+        Document {
+            id: fields.id,
+            root_id: fields.root_id,
+            cursor_id: fields.cursor_id,
+            nodes: fields.nodes,
+            undo_stack: fields.undo_stack.unwrap_or(undo),
+            redo_stack: fields.redo_stack.unwrap_or(redo),
+            undo_tree: fields.undo_tree,
+        }
+    }
+}
+
+/// The workspace as written to disk, every document in its stored form.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredWorkspace<'a> {
+    schema_version: u32,
+    tabs: &'a [TabRef],
+    active_doc_id: &'a str,
+    documents: HashMap<&'a str, StoredDocument<'a>>,
+}
+
+impl<'a> From<&'a Workspace> for StoredWorkspace<'a> {
+    fn from(workspace: &'a Workspace) -> Self {
+        StoredWorkspace {
+            schema_version: workspace.schema_version,
+            tabs: &workspace.tabs,
+            active_doc_id: &workspace.active_doc_id,
+            documents: workspace
+                .documents
+                .iter()
+                .map(|(id, doc)| (id.as_str(), StoredDocument::from(doc)))
+                .collect(),
+        }
+    }
+}
+
+/// Every state the document has been in, including ones a new edit after undo took off the
+/// redo stack. The stacks are the linear view of it used by undo/redo. Empty until the
+/// first history step.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UndoTree {
+    /// The node matching the document as it is now.
+    pub current_id: String,
+    pub nodes: HashMap<String, UndoNode>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UndoNode {
+    pub id: String,
+    pub parent_id: Option<String>,
+    /// The step from the parent's state to this one; `None` for the root.
+    pub entry: Option<HistoryEntry>,
+    pub created_at_millis: u64,
+}
+
+/// One undoable step: the ops that were applied, in order, and where the cursor was on
+/// either side. Undo applies the inverted ops in reverse.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub cursor_before: String,
+    pub cursor_after: String,
+    pub ops: Vec<EditOp>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(
+    tag = "op",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum EditOp {
+    /// Inserts a node at `index` under `parent_id`. `children_ids` are the siblings from
+    /// `index` on that move under the new node; empty for a new leaf.
+    InsertNode {
+        node_id: String,
+        parent_id: String,
+        index: usize,
+        text: String,
+        children_ids: Vec<String>,
+    },
+    /// The inverse of `InsertNode`: the node's children are promoted into the parent at
+    /// `index`, in order.
+    DeleteNode {
+        node_id: String,
+        parent_id: String,
+        index: usize,
+        text: String,
+        children_ids: Vec<String>,
+    },
+    SwapSiblings {
+        parent_id: String,
+        index: usize,
+        other_index: usize,
+    },
+    EditText {
+        node_id: String,
+        before: String,
+        after: String,
+    },
+    /// Any change the ops above do not describe: `removed` nodes are replaced by `added`
+    /// ones (a changed node appears in both). History converted from snapshots uses this.
+    Patch { removed: Vec<Node>, added: Vec<Node> },
+}
+
+/// A full tree snapshot: how history entries were stored before schema v2. Still read when
+/// migrating older files.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentState {
+    pub root_id: String,
+    pub cursor_id: String,
+    pub nodes: HashMap<String, Node>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Node {
+    pub id: String,
+    pub text: String,
+    pub parent_id: Option<String>,
+    pub children_ids: Vec<String>,
+}
+
+impl Document {
+    /// Same rule as the tab bar: the root text, or `Untitled` when blank.
+    pub fn title(&self) -> &str {
+        match self.nodes.get(&self.root_id) {
+            Some(root) if !root.text.trim().is_empty() => &root.text,
+            _ => "Untitled",
+        }
+    }
+}
@@ -0,0 +1,145 @@
+pub mod pdf;
+pub mod png;
+pub mod svg;
+
+use resvg::usvg;
+use serde::Deserialize;
+
+use crate::model::Document;
+
+/// The editor's themes (`ThemeName` on the frontend).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Theme {
+    #[default]
+    Dark,
+    Light,
+    TokyoNight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl Rgb {
+    pub fn hex(self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.0, self.1, self.2)
+    }
+}
+
+/// Solid colors for a theme. The editor's translucent node and edge colors (see `App.css`)
+/// are blended over the background here, so every renderer draws the same thing.
+#[derive(Debug, Clone, Copy)]
+pub struct Palette {
+    pub background: Rgb,
+    pub node_fill: Rgb,
+    pub node_border: Rgb,
+    pub edge: Rgb,
+    pub text: Rgb,
+}
+
+impl Theme {
+    pub fn palette(self) -> Palette {
+        match self {
+            Theme::Dark => Palette {
+                background: Rgb(0x0b, 0x0d, 0x12),
+                node_fill: Rgb(0x15, 0x17, 0x1b),
+                node_border: Rgb(0x28, 0x2a, 0x2e),
+                edge: Rgb(0x46, 0x4e, 0x65),
+                text: Rgb(0xe7, 0xe9, 0xee),
+            },
+            Theme::Light => Palette {
+                background: Rgb(0xf7, 0xf8, 0xfb),
+                node_fill: Rgb(0xee, 0xef, 0xf2),
+                node_border: Rgb(0xd7, 0xd8, 0xdc),
+                edge: Rgb(0xae, 0xc4, 0xf5),
+                text: Rgb(0x10, 0x13, 0x20),
+            },
+            Theme::TokyoNight => Palette {
+                background: Rgb(0x1a, 0x1b, 0x26),
+                node_fill: Rgb(0x22, 0x24, 0x30),
+                node_border: Rgb(0x31, 0x34, 0x43),
+                edge: Rgb(0x42, 0x54, 0x7e),
+                text: Rgb(0xc0, 0xca, 0xf5),
+            },
+        }
+    }
+}
+
+// Node styling from `App.css`.
+pub const FONT_SIZE: f64 = 14.0;
+pub const FONT_FAMILY: &str = "ui-sans-serif, system-ui, -apple-system, 'Segoe UI', sans-serif";
+pub const TEXT_PADDING: f64 = 10.0;
+pub const CORNER_RADIUS: f64 = 8.0;
+pub const EDGE_WIDTH: f64 = 1.2;
+
+/// A rough advance for `c` at `FONT_SIZE`: full width for CJK and other wide scripts.
+/// Without the font's metrics this only has to be close enough to cut long text.
+pub fn char_width(c: char) -> f64 {
+    let wide = matches!(
+        c,
+        '\u{1100}'..='\u{115f}'
+            | '\u{2e80}'..='\u{a4cf}'
+            | '\u{ac00}'..='\u{d7a3}'
+            | '\u{f900}'..='\u{faff}'
+            | '\u{ff00}'..='\u{ff60}'
+            | '\u{20000}'..
+    );
+    if wide {
+        FONT_SIZE
+    } else {
+        FONT_SIZE * 0.55
+    }
+}
+
+/// The text shown in a node: on one line, like the editor's, and cut with `…` where it
+/// would overflow the box.
+pub fn node_label(text: &str) -> String {
+    let line = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let max_width = crate::layout::NODE_WIDTH - 2.0 * TEXT_PADDING;
+    let mut width = 0.0;
+    let mut label = String::new();
+    for c in line.chars() {
+        if width + char_width(c) > max_width {
+            while width + char_width('…') > max_width {
+                let Some(last) = label.pop() else { break };
+                width -= char_width(last);
+            }
+            label.push('…');
+            return label;
+        }
+        width += char_width(c);
+        label.push(c);
+    }
+    label
+}
+
+/// Tried in order for the generic `sans-serif` family, which otherwise only maps to Arial.
+const SANS_SERIF_FAMILIES: [&str; 6] = [
+    "Segoe UI",
+    "Helvetica Neue",
+    "Arial",
+    "Noto Sans",
+    "DejaVu Sans",
+    "Liberation Sans",
+];
+
+/// The SVG rendering parsed for rasterizing or converting to PDF. Text is set in the
+/// system's fonts, falling back per character to any font that has the glyph.
+fn svg_tree(doc: &Document, theme: Theme) -> Result<usvg::Tree, String> {
+    let mut options = usvg::Options::default();
+    let fonts = options.fontdb_mut();
+    fonts.load_system_fonts();
+    let installed: Vec<String> = fonts
+        .faces()
+        .flat_map(|face| face.families.iter().map(|(family, _)| family.clone()))
+        .collect();
+    let sans_serif = SANS_SERIF_FAMILIES
+        .iter()
+        .find(|family| installed.iter().any(|name| name == *family))
+        .map(|family| family.to_string())
+        .or_else(|| installed.first().cloned());
+    if let Some(family) = sans_serif {
+        fonts.set_sans_serif_family(family);
+    }
+    usvg::Tree::from_str(&svg::render(doc, theme), &options).map_err(|e| e.to_string())
+}
@@ -0,0 +1,124 @@
+use pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use super::{svg_tree, Theme};
+use crate::model::Document;
+
+/// A4 landscape, in points.
+const PAGE_WIDTH: f32 = 842.0;
+const PAGE_HEIGHT: f32 = 595.0;
+const MARGIN: f32 = 36.0;
+/// Points per editor pixel at scale 1 (96 pixels to the inch).
+const POINTS_PER_PIXEL: f32 = 0.75;
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PdfOptions {
+    /// Printed size relative to the editor's: below 1 fits more of the tree on a page.
+    pub scale: f32,
+    pub theme: Theme,
+}
+
+impl Default for PdfOptions {
+    fn default() -> Self {
+        PdfOptions {
+            scale: 1.0,
+            theme: Theme::default(),
+        }
+    }
+}
+
+/// The SVG rendering as vector graphics with embedded text, tiled over as many pages as it
+/// takes: left to right, then top to bottom.
+pub fn render(doc: &Document, options: &PdfOptions) -> Result<Vec<u8>, String> {
+    if !(options.scale.is_finite() && options.scale > 0.0) {
+        return Err(format!("invalid scale {}", options.scale));
+    }
+    let tree = svg_tree(doc, options.theme)?;
+    let width = tree.size().width() * POINTS_PER_PIXEL * options.scale;
+    let height = tree.size().height() * POINTS_PER_PIXEL * options.scale;
+    let (area_width, area_height) = (PAGE_WIDTH - 2.0 * MARGIN, PAGE_HEIGHT - 2.0 * MARGIN);
+    let columns = (width / area_width).ceil().max(1.0) as usize;
+    let rows = (height / area_height).ceil().max(1.0) as usize;
+
+    let mut alloc = Ref::new(1);
+    let catalog_id = alloc.bump();
+    let page_tree_id = alloc.bump();
+    // The whole tree is one form XObject, which every page draws shifted and clipped.
+    let (chunk, svg_id) = svg2pdf::to_chunk(&tree, svg2pdf::ConversionOptions::default())
+        .map_err(|e| e.to_string())?;
+    let mut renumbered = HashMap::new();
+    let chunk = chunk.renumber(|old| *renumbered.entry(old).or_insert_with(|| alloc.bump()));
+    let svg_id = renumbered[&svg_id];
+    let svg_name = Name(b"Tree");
+
+    let mut pdf = Pdf::new();
+    let mut page_ids = Vec::new();
+    for row in 0..rows {
+        for column in 0..columns {
+            let page_id = alloc.bump();
+            let content_id = alloc.bump();
+            page_ids.push(page_id);
+
+            let mut page = pdf.page(page_id);
+            page.media_box(Rect::new(0.0, 0.0, PAGE_WIDTH, PAGE_HEIGHT));
+            page.parent(page_tree_id);
+            page.contents(content_id);
+            page.resources().x_objects().pair(svg_name, svg_id);
+            page.finish();
+
+            // PDF's y axis points up: the tree's top edge starts at the top margin of the
+            // first row.
+            let x = MARGIN - column as f32 * area_width;
+            let top = PAGE_HEIGHT - MARGIN + row as f32 * area_height;
+            let mut content = Content::new();
+            content.save_state();
+            content.rect(MARGIN, MARGIN, area_width, area_height);
+            content.clip_nonzero();
+            content.end_path();
+            content.transform([width, 0.0, 0.0, height, x, top - height]);
+            content.x_object(svg_name);
+            content.restore_state();
+            pdf.stream(content_id, &content.finish());
+        }
+    }
+    pdf.catalog(catalog_id).pages(page_tree_id);
+    pdf.pages(page_tree_id)
+        .count(page_ids.len() as i32)
+        .kids(page_ids);
+    pdf.extend(&chunk);
+    Ok(pdf.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::document;
+
+    #[test]
+    fn tiles_a_tall_tree_over_pages() {
+        let leaf_ids: Vec<String> = (0..30).map(|i| format!("n{i}")).collect();
+        let leaf_ids: Vec<&str> = leaf_ids.iter().map(String::as_str).collect();
+        let mut rows: Vec<(&str, &str, &[&str])> = vec![("root", "Plan", &leaf_ids)];
+        rows.extend(leaf_ids.iter().map(|id| (*id, *id, &[] as &[&str])));
+        let doc = document(&rows);
+
+        // 30 leaves are 1548 pixels tall: three pages at full size, two at half.
+        let count_pages = |options: &PdfOptions| {
+            let pdf = render(&doc, options).unwrap();
+            assert!(pdf.starts_with(b"%PDF-"));
+            let text = String::from_utf8_lossy(&pdf).into_owned();
+            ["/Count 1", "/Count 2", "/Count 3"]
+                .iter()
+                .position(|count| text.contains(count))
+                .map(|index| index + 1)
+        };
+        assert_eq!(count_pages(&PdfOptions::default()), Some(3));
+        let half = PdfOptions {
+            scale: 0.5,
+            ..PdfOptions::default()
+        };
+        assert_eq!(count_pages(&half), Some(2));
+    }
+}
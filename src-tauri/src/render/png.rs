@@ -0,0 +1,72 @@
+use resvg::tiny_skia::{Pixmap, Transform};
+use serde::Deserialize;
+
+use super::{svg_tree, Theme};
+use crate::model::Document;
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PngOptions {
+    /// Pixels per editor pixel: 2 for a sharp image on high-density screens.
+    pub scale: f32,
+    pub theme: Theme,
+}
+
+impl Default for PngOptions {
+    fn default() -> Self {
+        PngOptions {
+            scale: 1.0,
+            theme: Theme::default(),
+        }
+    }
+}
+
+/// The SVG rendering rasterized on the CPU.
+pub fn render(doc: &Document, options: &PngOptions) -> Result<Vec<u8>, String> {
+    if !(options.scale.is_finite() && options.scale > 0.0) {
+        return Err(format!("invalid scale {}", options.scale));
+    }
+    let tree = svg_tree(doc, options.theme)?;
+    let size = tree.size().to_int_size().scale_by(options.scale);
+    let mut pixmap = size
+        .and_then(|size| Pixmap::new(size.width(), size.height()))
+        .ok_or_else(|| "the image is too large".to_string())?;
+    resvg::render(
+        &tree,
+        Transform::from_scale(options.scale, options.scale),
+        &mut pixmap.as_mut(),
+    );
+    pixmap.encode_png().map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::Rgb;
+    use crate::test_support::document;
+
+    #[test]
+    fn scales_the_image_and_fills_the_background() {
+        let doc = document(&[("root", "Plan", &[])]);
+        let options = PngOptions {
+            scale: 2.0,
+            theme: Theme::Light,
+        };
+        let bytes = render(&doc, &options).unwrap();
+
+        let pixmap = Pixmap::decode_png(&bytes).unwrap();
+        assert_eq!((pixmap.width(), pixmap.height()), (488, 196));
+        let corner = pixmap.pixel(0, 0).unwrap();
+        let Rgb(r, g, b) = Theme::Light.palette().background;
+        assert_eq!((corner.red(), corner.green(), corner.blue()), (r, g, b));
+
+        assert!(render(
+            &doc,
+            &PngOptions {
+                scale: 0.0,
+                ..options
+            }
+        )
+        .is_err());
+    }
+}
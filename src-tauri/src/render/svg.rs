@@ -0,0 +1,88 @@
+use quick_xml::escape::escape;
+
+use super::{node_label, Theme, CORNER_RADIUS, EDGE_WIDTH, FONT_FAMILY, FONT_SIZE, TEXT_PADDING};
+use crate::layout::{compute_layout, edge_points, svg_path_for_edge, NODE_HEIGHT, NODE_WIDTH};
+use crate::model::Document;
+
+/// A standalone SVG of the document as the editor lays it out. Each node carries its full
+/// text as a `<title>`, since long text is cut on the node itself.
+pub fn render(doc: &Document, theme: Theme) -> String {
+    let layout = compute_layout(doc);
+    let palette = theme.palette();
+    let (width, height) = (layout.content_width, layout.content_height);
+    let mut out = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" viewBox=\"0 0 {width} {height}\">\n"
+    );
+    out.push_str(&format!(
+        "<rect width=\"100%\" height=\"100%\" fill=\"{}\"/>\n",
+        palette.background.hex()
+    ));
+
+    out.push_str(&format!(
+        "<g fill=\"none\" stroke=\"{}\" stroke-width=\"{EDGE_WIDTH}\">\n",
+        palette.edge.hex()
+    ));
+    for (parent_id, child_id) in &layout.edges {
+        let (from, to) = edge_points(&layout.positions[parent_id], &layout.positions[child_id]);
+        out.push_str(&format!("<path d=\"{}\"/>\n", svg_path_for_edge(from, to)));
+    }
+    out.push_str("</g>\n");
+
+    out.push_str(&format!(
+        "<g font-family=\"{}\" font-size=\"{FONT_SIZE}\">\n",
+        escape(FONT_FAMILY)
+    ));
+    // Depth first, so the output reads in outline order.
+    let mut ids = vec![doc.root_id.as_str()];
+    ids.extend(layout.edges.iter().map(|(_, child_id)| child_id.as_str()));
+    for id in ids {
+        let (Some(pos), Some(node)) = (layout.positions.get(id), doc.nodes.get(id)) else {
+            continue;
+        };
+        out.push_str("<g>");
+        out.push_str(&format!("<title>{}</title>", escape(&node.text)));
+        out.push_str(&format!(
+            "<rect x=\"{}\" y=\"{}\" width=\"{NODE_WIDTH}\" height=\"{NODE_HEIGHT}\" rx=\"{CORNER_RADIUS}\" fill=\"{}\" stroke=\"{}\"/>",
+            pos.x,
+            pos.y,
+            palette.node_fill.hex(),
+            palette.node_border.hex()
+        ));
+        out.push_str(&format!(
+            "<text x=\"{}\" y=\"{}\" dominant-baseline=\"central\" fill=\"{}\">{}</text>",
+            pos.x + TEXT_PADDING,
+            pos.y + NODE_HEIGHT / 2.0,
+            palette.text.hex(),
+            escape(node_label(&node.text))
+        ));
+        out.push_str("</g>\n");
+    }
+    out.push_str("</g>\n</svg>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::document;
+
+    #[test]
+    fn draws_every_node_and_edge_in_the_theme() {
+        let doc = document(&[
+            ("root", "Plan & <goals>", &["a", "b"]),
+            ("a", "A very long node text that cannot fit in one box", &[]),
+            ("b", "Team", &[]),
+        ]);
+        let svg = render(&doc, Theme::Light);
+
+        assert!(svg
+            .starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"504\" height=\"148\""));
+        assert!(svg.contains("fill=\"#f7f8fb\""));
+        assert_eq!(svg.matches("<path ").count(), 2);
+        assert_eq!(svg.matches("<rect x=").count(), 3);
+        assert!(svg.contains("<path d=\"M 212 74 C 252 74, 252 49, 292 49\"/>"));
+        assert!(svg.contains(">Plan &amp; &lt;goals&gt;</text>"));
+        assert!(svg.contains("<title>A very long node text that cannot fit in one box</title>"));
+        assert!(svg.contains(">A very long node te…</text>"));
+    }
+}
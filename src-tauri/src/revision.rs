@@ -0,0 +1,295 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use crate::backup::{self, RetentionPolicy};
+use crate::durable;
+use crate::model::{Document, DocumentState, UndoTree};
+use crate::store::file_stem;
+use crate::undo;
+use crate::undo_tree;
+
+const MINUTE_MILLIS: u128 = 60 * 1000;
+
+/// A revision is recorded at the first save at least 10 minutes after the previous one, so
+/// every save in between is coalesced into the next revision.
+pub const DEFAULT_POLICY: RetentionPolicy = RetentionPolicy {
+    min_interval_millis: 10 * MINUTE_MILLIS,
+    keep_recent: 48,
+    keep_daily: 30,
+};
+
+/// A document's tree as it was saved at `saved_at_millis`, which also identifies it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Revision {
+    pub saved_at_millis: u128,
+    pub state: DocumentState,
+}
+
+impl Revision {
+    /// The revision as a document of its own, without history.
+    pub fn to_document(&self, doc_id: &str) -> Document {
+        Document {
+            id: doc_id.to_string(),
+            root_id: self.state.root_id.clone(),
+            cursor_id: self.state.cursor_id.clone(),
+            nodes: self.state.nodes.clone(),
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            undo_tree: UndoTree::default(),
+        }
+    }
+}
+
+/// One JSON Lines file per document, oldest revision first.
+pub fn revision_path(dir: &Path, doc_id: &str) -> PathBuf {
+    dir.join(format!("{}.jsonl", file_stem(doc_id)))
+}
+
+/// Newest first. Lines that do not parse are skipped; a missing file means no revisions.
+pub fn list(path: &Path) -> Result<Vec<Revision>, String> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut revisions: Vec<Revision> = text
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect();
+    revisions.sort_by_key(|r| std::cmp::Reverse(r.saved_at_millis));
+    Ok(revisions)
+}
+
+pub fn find(path: &Path, saved_at_millis: u128) -> Result<Revision, String> {
+    list(path)?
+        .into_iter()
+        .find(|r| r.saved_at_millis == saved_at_millis)
+        .ok_or_else(|| format!("no revision saved at {saved_at_millis}"))
+}
+
+/// The file is only written when a revision is recorded, so its modification time stands
+/// in for the newest revision and saves do not have to read it.
+fn is_due(path: &Path, now_millis: u128, policy: RetentionPolicy) -> bool {
+    let Some(modified) = fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+    else {
+        return true;
+    };
+    now_millis.saturating_sub(modified.as_millis()) >= policy.min_interval_millis
+}
+
+/// Called after a save. `document` yields the document as just saved and is only read
+/// when a revision is actually due.
+pub fn record_if_due(
+    path: &Path,
+    now_millis: u128,
+    policy: RetentionPolicy,
+    document: impl FnOnce() -> Option<Document>,
+) -> Result<bool, String> {
+    if !is_due(path, now_millis, policy) {
+        return Ok(false);
+    }
+    let Some(doc) = document() else {
+        return Ok(false);
+    };
+    let mut revisions = list(path)?;
+    revisions.insert(
+        0,
+        Revision {
+            saved_at_millis: now_millis,
+            state: DocumentState {
+                root_id: doc.root_id,
+                cursor_id: doc.cursor_id,
+                nodes: doc.nodes,
+            },
+        },
+    );
+    let times: Vec<u128> = revisions.iter().map(|r| r.saved_at_millis).collect();
+    let mut text = String::new();
+    for (revision, keep) in revisions.iter().zip(backup::keep_mask(&times, policy)).rev() {
+        if keep {
+            text.push_str(&serde_json::to_string(revision).map_err(|e| e.to_string())?);
+            text.push('\n');
+        }
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    durable::write_atomic(&durable::RealFs, path, text.as_bytes())?;
+    Ok(true)
+}
+
+/// Deletes the revision files of documents no longer in the workspace, e.g. after their tab
+/// was closed. Returns how many were removed.
+pub fn remove_unlisted(dir: &Path, doc_ids: &[String]) -> Result<usize, String> {
+    if !dir.exists() {
+        return Ok(0);
+    }
+    let keep: Vec<PathBuf> = doc_ids.iter().map(|id| revision_path(dir, id)).collect();
+    let mut removed = 0;
+    for entry in fs::read_dir(dir).map_err(|e| e.to_string())? {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        if path.extension().is_some_and(|ext| ext == "jsonl") && !keep.contains(&path) {
+            fs::remove_file(&path).map_err(|e| e.to_string())?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// Puts the revision's tree in place as one undoable step, so `u` takes the restore back.
+pub fn restore(doc: &mut Document, revision: &Revision, now_millis: u128) -> Result<(), String> {
+    if revision.state.root_id != doc.root_id {
+        return Err("the revision has a different root node".to_string());
+    }
+    let current = DocumentState {
+        root_id: doc.root_id.clone(),
+        cursor_id: doc.cursor_id.clone(),
+        nodes: doc.nodes.clone(),
+    };
+    let entry = undo::entry_between(&current, &revision.state);
+    if entry.ops.is_empty() {
+        return Ok(());
+    }
+    undo::apply_entry(&mut doc.nodes, &mut doc.cursor_id, &entry)?;
+    undo_tree::push(doc, entry, now_millis as u64);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, Scratch};
+
+    const PLAN: &[(&str, &str, &[&str])] = &[
+        ("root", "Plan", &["n-0", "n-1"]),
+        ("n-0", "Budget", &[]),
+        ("n-1", "Schedule", &[]),
+    ];
+    const BUDGET: &[(&str, &str, &[&str])] =
+        &[("root", "Plan", &["n-0"]), ("n-0", "Budget", &[])];
+    const EMPTY: &[(&str, &str, &[&str])] = &[("root", "Plan", &[])];
+
+    fn record(
+        path: &Path,
+        at: u128,
+        policy: RetentionPolicy,
+        tree: &[(&str, &str, &[&str])],
+    ) -> bool {
+        record_if_due(path, at, policy, || Some(test_support::document(tree))).unwrap()
+    }
+
+    #[test]
+    fn saves_within_the_interval_are_coalesced_and_restore_is_undoable() {
+        let scratch = Scratch::new("revision-coalesce");
+        let path = revision_path(&scratch.dir, "doc");
+        let now = UNIX_EPOCH.elapsed().unwrap().as_millis();
+
+        assert!(record(&path, now, DEFAULT_POLICY, BUDGET));
+        let later = now + MINUTE_MILLIS;
+        assert!(!record(&path, later, DEFAULT_POLICY, EMPTY));
+        assert_eq!(list(&path).unwrap().len(), 1);
+
+        let mut doc = test_support::document(PLAN);
+        restore(&mut doc, &find(&path, now).unwrap(), later).unwrap();
+        assert_eq!(doc.nodes["root"].children_ids, vec!["n-0"]);
+        assert_eq!(doc.undo_stack.len(), 1);
+        assert_eq!(
+            undo::describe_entry(&doc.undo_stack[0], &doc.nodes),
+            "Deleted 'Schedule'"
+        );
+    }
+
+    #[test]
+    fn list_is_newest_first_and_find_picks_by_time() {
+        let scratch = Scratch::new("revision-list");
+        let path = revision_path(&scratch.dir, "doc");
+        assert!(list(&path).unwrap().is_empty());
+
+        let now = UNIX_EPOCH.elapsed().unwrap().as_millis();
+        let times = [now, now + 11 * MINUTE_MILLIS, now + 22 * MINUTE_MILLIS];
+        for (at, tree) in times.into_iter().zip([EMPTY, BUDGET, PLAN]) {
+            assert!(record(&path, at, DEFAULT_POLICY, tree));
+        }
+        // A line that does not parse is skipped.
+        let mut text = fs::read_to_string(&path).unwrap();
+        text.push_str("{not json\n");
+        fs::write(&path, text).unwrap();
+
+        let saved: Vec<u128> = list(&path)
+            .unwrap()
+            .iter()
+            .map(|r| r.saved_at_millis)
+            .collect();
+        assert_eq!(saved, vec![times[2], times[1], times[0]]);
+        assert_eq!(
+            find(&path, times[1]).unwrap().state.nodes["root"].children_ids,
+            vec!["n-0"]
+        );
+        assert!(find(&path, now + 1).is_err());
+    }
+
+    #[test]
+    fn old_revisions_are_pruned_by_the_policy() {
+        let scratch = Scratch::new("revision-retention");
+        let path = revision_path(&scratch.dir, "doc");
+        let policy = RetentionPolicy {
+            min_interval_millis: 0,
+            keep_recent: 2,
+            keep_daily: 0,
+        };
+        for at in 1..=4 {
+            assert!(record(&path, at, policy, PLAN));
+        }
+        let saved: Vec<u128> = list(&path)
+            .unwrap()
+            .iter()
+            .map(|r| r.saved_at_millis)
+            .collect();
+        assert_eq!(saved, vec![4, 3]);
+    }
+
+    #[test]
+    fn fork_is_a_document_without_history() {
+        let revision = Revision {
+            saved_at_millis: 7,
+            state: test_support::state(PLAN),
+        };
+        let doc = revision.to_document("doc-7");
+        assert_eq!(doc.id, "doc-7");
+        assert_eq!(doc.nodes, revision.state.nodes);
+        assert!(doc.undo_stack.is_empty() && doc.redo_stack.is_empty());
+        assert!(doc.undo_tree.nodes.is_empty());
+    }
+
+    #[test]
+    fn files_of_closed_documents_are_removed() {
+        let scratch = Scratch::new("revision-remove");
+        let policy = RetentionPolicy {
+            min_interval_millis: 0,
+            ..DEFAULT_POLICY
+        };
+        for doc_id in ["kept", "closed", "with space"] {
+            assert!(record(
+                &revision_path(&scratch.dir, doc_id),
+                1,
+                policy,
+                PLAN
+            ));
+        }
+        fs::write(scratch.dir.join("notes.txt"), "").unwrap();
+
+        let kept = vec!["kept".to_string(), "with space".to_string()];
+        assert_eq!(remove_unlisted(&scratch.dir, &kept), Ok(1));
+        assert!(!revision_path(&scratch.dir, "closed").exists());
+        assert!(revision_path(&scratch.dir, "with space").exists());
+        assert!(scratch.dir.join("notes.txt").exists());
+        assert_eq!(remove_unlisted(&scratch.dir.join("missing"), &kept), Ok(0));
+    }
+}
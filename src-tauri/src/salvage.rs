@@ -0,0 +1,357 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::migrate::{self, MigrateError};
+use crate::model::{Document, TabRef, Workspace};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LostDocument {
+    /// `None` when the entry was too damaged to even read its key.
+    pub doc_id: Option<String>,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SalvageReport {
+    pub recovered: Vec<String>,
+    pub lost: Vec<LostDocument>,
+    /// Documents that were kept but lost recent edits.
+    pub warnings: Vec<String>,
+}
+
+pub struct Salvaged {
+    /// `None` when not a single document could be recovered.
+    pub workspace: Option<Workspace>,
+    pub report: SalvageReport,
+}
+
+/// Best-effort read of a workspace that failed strict parsing. Every document that still
+/// deserializes is kept; tabs and `activeDocId` are kept when readable and otherwise rebuilt.
+pub fn salvage_workspace(text: &str) -> Result<Salvaged, MigrateError> {
+    let mut value = match serde_json::from_str::<Value>(text) {
+        Ok(value) if value.is_object() => value,
+        _ => scan_workspace(text),
+    };
+
+    migrate::migrate_value(&mut value)?;
+
+    let mut report = SalvageReport::default();
+    if let Some(Value::Array(lost)) = value.get("lostDocuments") {
+        for entry in lost {
+            report.lost.push(LostDocument {
+                doc_id: entry.get("docId").and_then(Value::as_str).map(String::from),
+                reason: entry
+                    .get("reason")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string(),
+            });
+        }
+    }
+
+    let mut workspace = Workspace {
+        schema_version: migrate::CURRENT_SCHEMA_VERSION,
+        tabs: value
+            .get("tabs")
+            .cloned()
+            .and_then(|tabs| serde_json::from_value::<Vec<TabRef>>(tabs).ok())
+            .unwrap_or_default(),
+        active_doc_id: value
+            .get("activeDocId")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string(),
+        documents: Default::default(),
+    };
+
+    let raw_documents = match value.get_mut("documents").map(Value::take) {
+        Some(Value::Object(documents)) => documents,
+        _ => Map::new(),
+    };
+    for (doc_id, raw) in raw_documents {
+        match serde_json::from_value::<Document>(raw) {
+            Ok(doc) => {
+                report.recovered.push(doc_id.clone());
+                workspace.documents.insert(doc_id, doc);
+            }
+            Err(e) => {
+                report.lost.push(LostDocument {
+                    doc_id: Some(doc_id),
+                    reason: e.to_string(),
+                });
+            }
+        }
+    }
+    report.recovered.sort();
+
+    if workspace.documents.is_empty() {
+        return Ok(Salvaged {
+            workspace: None,
+            report,
+        });
+    }
+
+    let documents = &workspace.documents;
+    workspace
+        .tabs
+        .retain(|tab| documents.contains_key(&tab.doc_id));
+    for doc_id in &report.recovered {
+        if !workspace.tabs.iter().any(|tab| tab.doc_id == *doc_id) {
+            workspace.tabs.push(TabRef {
+                doc_id: doc_id.clone(),
+            });
+        }
+    }
+    if !workspace.documents.contains_key(&workspace.active_doc_id) {
+        workspace.active_doc_id.clone_from(&workspace.tabs[0].doc_id);
+    }
+
+    Ok(Salvaged {
+        workspace: Some(workspace),
+        report,
+    })
+}
+
+/// Rebuilds a workspace object from syntactically broken JSON by locating the top-level
+/// keys and reading each `documents` entry as its own balanced `{ ... }` span. Entries that
+/// cannot be read are listed under a synthetic `lostDocuments` key.
+fn scan_workspace(text: &str) -> Value {
+    let bytes = text.as_bytes();
+    let mut object = Map::new();
+
+    if let Some(start) = find_key_value(bytes, "schemaVersion") {
+        let end = bytes[start..]
+            .iter()
+            .position(|b| !b.is_ascii_digit())
+            .map_or(bytes.len(), |len| start + len);
+        if let Ok(version) = text[start..end].parse::<u32>() {
+            object.insert("schemaVersion".to_string(), Value::from(version));
+        }
+    }
+    if let Some(start) = find_key_value(bytes, "activeDocId") {
+        if let Some(end) = string_end(bytes, start) {
+            if let Ok(active) = serde_json::from_str::<Value>(&text[start..end]) {
+                object.insert("activeDocId".to_string(), active);
+            }
+        }
+    }
+    if let Some(start) = find_key_value(bytes, "tabs").filter(|&i| bytes[i] == b'[') {
+        if let Some(end) = balanced_end(bytes, start) {
+            if let Ok(tabs) = serde_json::from_str::<Value>(&text[start..end]) {
+                object.insert("tabs".to_string(), tabs);
+            }
+        }
+    }
+
+    let mut documents = Map::new();
+    let mut lost = Vec::new();
+    if let Some(start) = find_key_value(bytes, "documents").filter(|&i| bytes[i] == b'{') {
+        let mut i = start + 1;
+        loop {
+            i = skip_whitespace_and(bytes, i, b',');
+            if i >= bytes.len() || bytes[i] == b'}' {
+                break;
+            }
+            let Some(key_end) = string_end(bytes, i) else {
+                lost.push(lost_entry(None, "unreadable document key"));
+                break;
+            };
+            let key = serde_json::from_str::<String>(&text[i..key_end]).ok();
+            i = skip_whitespace_and(bytes, key_end, b':');
+            if i >= bytes.len() || bytes[i] != b'{' {
+                lost.push(lost_entry(key, "document is not an object"));
+                break;
+            }
+            let Some(value_end) = balanced_end(bytes, i) else {
+                lost.push(lost_entry(key, "document is truncated"));
+                break;
+            };
+            match (key, serde_json::from_str::<Value>(&text[i..value_end])) {
+                (Some(key), Ok(value)) => {
+                    documents.insert(key, value);
+                }
+                (key, Err(e)) => lost.push(lost_entry(key, &e.to_string())),
+                (None, Ok(_)) => lost.push(lost_entry(None, "unreadable document key")),
+            }
+            i = value_end;
+        }
+    }
+    object.insert("documents".to_string(), Value::Object(documents));
+    if !lost.is_empty() {
+        object.insert("lostDocuments".to_string(), Value::Array(lost));
+    }
+
+    Value::Object(object)
+}
+
+fn lost_entry(doc_id: Option<String>, reason: &str) -> Value {
+    serde_json::json!({ "docId": doc_id, "reason": reason })
+}
+
+/// Returns the index of the first byte of the value for the first `"key":` found outside
+/// any string literal.
+fn find_key_value(bytes: &[u8], key: &str) -> Option<usize> {
+    let needle = format!("\"{key}\"");
+    let needle = needle.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'"' {
+            if bytes[i..].starts_with(needle) {
+                let after = skip_whitespace(bytes, i + needle.len());
+                if after < bytes.len() && bytes[after] == b':' {
+                    let value = skip_whitespace(bytes, after + 1);
+                    return (value < bytes.len()).then_some(value);
+                }
+            }
+            i = string_end(bytes, i)?;
+        } else {
+            i += 1;
+        }
+    }
+    None
+}
+
+/// `start` must point at an opening quote; returns the index just past the closing quote.
+fn string_end(bytes: &[u8], start: usize) -> Option<usize> {
+    if bytes.get(start) != Some(&b'"') {
+        return None;
+    }
+    let mut i = start + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'"' => return Some(i + 1),
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+/// `start` must point at `{` or `[`; returns the index just past its matching closer.
+fn balanced_end(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut i = start;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => {
+                i = string_end(bytes, i)?;
+                continue;
+            }
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' => {
+                depth = depth.checked_sub(1)?;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+fn skip_whitespace(bytes: &[u8], mut i: usize) -> usize {
+    while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+        i += 1;
+    }
+    i
+}
+
+fn skip_whitespace_and(bytes: &[u8], mut i: usize, separator: u8) -> usize {
+    while i < bytes.len() && (bytes[i].is_ascii_whitespace() || bytes[i] == separator) {
+        i += 1;
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::document;
+    use serde_json::json;
+
+    /// Documents `a` and `b` (written in that order), tabs for both and `b` active.
+    fn workspace_json(b: Value) -> String {
+        let mut a = document(&[("root", "Plan", &["x"]), ("x", "Budget", &[])]);
+        a.id = "a".to_string();
+        let workspace = json!({
+            "schemaVersion": migrate::CURRENT_SCHEMA_VERSION,
+            "tabs": [{ "docId": "a" }, { "docId": "b" }],
+            "activeDocId": "b",
+            "documents": { "a": a, "b": b },
+        });
+        serde_json::to_string_pretty(&workspace).unwrap()
+    }
+
+    fn lost(report: &SalvageReport) -> Vec<(Option<&str>, &str)> {
+        report
+            .lost
+            .iter()
+            .map(|lost| (lost.doc_id.as_deref(), lost.reason.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn keeps_the_documents_before_a_truncation() {
+        let mut b = document(&[("root", "Notes", &[])]);
+        b.id = "b".to_string();
+        let text = workspace_json(serde_json::to_value(&b).unwrap());
+        let cut = text.find("Notes").unwrap();
+
+        let salvaged = salvage_workspace(&text[..cut]).unwrap();
+        assert_eq!(salvaged.report.recovered, vec!["a"]);
+        assert_eq!(
+            lost(&salvaged.report),
+            vec![(Some("b"), "document is truncated")]
+        );
+        let workspace = salvaged.workspace.unwrap();
+        assert_eq!(workspace.documents["a"].nodes["x"].text, "Budget");
+        let tabs: Vec<&str> = workspace
+            .tabs
+            .iter()
+            .map(|tab| tab.doc_id.as_str())
+            .collect();
+        assert_eq!(tabs, vec!["a"]);
+        assert_eq!(workspace.active_doc_id, "a");
+    }
+
+    #[test]
+    fn keeps_readable_documents_of_a_partly_invalid_file() {
+        let text = workspace_json(json!({ "id": "b", "rootId": "root", "nodes": "broken" }));
+
+        let salvaged = salvage_workspace(&text).unwrap();
+        assert_eq!(salvaged.report.recovered, vec!["a"]);
+        let [LostDocument { doc_id, .. }] = &salvaged.report.lost[..] else {
+            panic!("expected one lost document: {:?}", salvaged.report.lost);
+        };
+        assert_eq!(doc_id.as_deref(), Some("b"));
+        assert!(salvaged.workspace.unwrap().documents.contains_key("a"));
+
+        // A stray byte in the middle breaks strict parsing; the scan still finds both.
+        let broken = text.replacen("\"tabs\"", "\"tabs\" x", 1);
+        let salvaged = salvage_workspace(&broken).unwrap();
+        assert_eq!(salvaged.report.recovered, vec!["a"]);
+        assert_eq!(salvaged.report.lost.len(), 1);
+        let workspace = salvaged.workspace.unwrap();
+        // The tabs could not be read, so they are rebuilt from what was recovered.
+        assert_eq!(workspace.tabs.len(), 1);
+        assert_eq!(workspace.active_doc_id, "a");
+    }
+
+    #[test]
+    fn reports_when_nothing_can_be_recovered() {
+        let text = workspace_json(json!({}));
+        let cut = text.find("\"a\": {").unwrap() + 10;
+
+        let salvaged = salvage_workspace(&text[..cut]).unwrap();
+        assert!(salvaged.workspace.is_none());
+        assert!(salvaged.report.recovered.is_empty());
+        assert_eq!(
+            lost(&salvaged.report),
+            vec![(Some("a"), "document is truncated")]
+        );
+    }
+}
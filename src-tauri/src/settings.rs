@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+use crate::durable;
+use crate::history::HistoryLimits;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Settings {
+    #[serde(default)]
+    pub history: HistoryLimits,
+}
+
+/// A missing or unreadable settings file means defaults; settings never block startup.
+pub fn load(path: &Path) -> Settings {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default()
+}
+
+pub fn save(path: &Path, settings: &Settings) -> Result<(), String> {
+    let text = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+    durable::write_atomic(&durable::RealFs, path, format!("{text}\n").as_bytes())
+}
@@ -0,0 +1,76 @@
+mod split;
+mod sqlite;
+
+pub use split::{file_stem, SplitStore};
+pub use sqlite::{remove_database, SqliteStore};
+
+use serde::Serialize;
+
+use crate::migrate::MigrateError;
+use crate::model::{Document, Workspace, WorkspaceChanges};
+use crate::salvage::LostDocument;
+
+pub struct StoreLoad {
+    pub workspace: Option<Workspace>,
+    /// Documents that could not be read. File-backed stores move their files aside as
+    /// `<file>.broken-<millis>` so later saves do not delete them.
+    pub lost: Vec<LostDocument>,
+    /// Problems that cost edits but not whole documents, e.g. a journal cut short.
+    pub warnings: Vec<String>,
+    pub manifest_broken: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeMatch {
+    pub doc_id: String,
+    pub node_id: String,
+    pub text: String,
+}
+
+/// Where the workspace lives between sessions. `load_workspace` and the save commands only
+/// talk to this trait; which backend is used is decided once in `open_store`.
+pub trait WorkspaceStore {
+    /// Whether this store holds a saved workspace at all.
+    fn exists(&self) -> bool;
+
+    fn load(&self) -> Result<StoreLoad, MigrateError>;
+
+    /// One document without reading the rest of the workspace.
+    fn load_document(&self, doc_id: &str) -> Result<Option<Document>, String>;
+
+    fn save_all(&self, workspace: &Workspace) -> Result<(), String>;
+
+    fn save_changes(&self, changes: &WorkspaceChanges) -> Result<(), String>;
+
+    /// Case-insensitive substring search over node text, in tab order.
+    fn search_nodes(&self, query: &str, limit: usize) -> Result<Vec<NodeMatch>, String> {
+        let Some(workspace) = self.load().map_err(|e| e.to_string())?.workspace else {
+            return Ok(Vec::new());
+        };
+        let needle = query.to_lowercase();
+        let mut matches = Vec::new();
+        for tab in &workspace.tabs {
+            let Some(doc) = workspace.documents.get(&tab.doc_id) else {
+                continue;
+            };
+            let mut nodes: Vec<_> = doc
+                .nodes
+                .values()
+                .filter(|node| node.text.to_lowercase().contains(&needle))
+                .collect();
+            nodes.sort_by(|a, b| a.id.cmp(&b.id));
+            for node in nodes {
+                if matches.len() == limit {
+                    return Ok(matches);
+                }
+                matches.push(NodeMatch {
+                    doc_id: doc.id.clone(),
+                    node_id: node.id.clone(),
+                    text: node.text.clone(),
+                });
+            }
+        }
+        Ok(matches)
+    }
+}
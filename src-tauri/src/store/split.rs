@@ -0,0 +1,805 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::durable;
+use crate::history::{self, HistoryLimits};
+use crate::journal;
+use crate::migrate::{self, MigrateError, CURRENT_SCHEMA_VERSION};
+use crate::model::{Document, StoredDocument, TabRef, Workspace, WorkspaceChanges};
+use crate::salvage::LostDocument;
+
+use super::{StoreLoad, WorkspaceStore};
+
+const MANIFEST_FILE: &str = "manifest.json";
+const DOCUMENTS_DIR: &str = "documents";
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Manifest {
+    schema_version: u32,
+    tabs: Vec<TabRef>,
+    active_doc_id: String,
+    doc_ids: Vec<String>,
+}
+
+/// Each document file carries its own version: only changed documents are rewritten, so
+/// files from before an upgrade sit next to newer ones. `journal_seq` is the last journal
+/// batch already folded into this snapshot.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DocumentFile<'a> {
+    schema_version: u32,
+    journal_seq: u64,
+    document: StoredDocument<'a>,
+}
+
+/// Workspace stored as `manifest.json` (tabs, active doc, doc ids) plus one file per
+/// document under `documents/`, so a save only touches the documents that changed. Small
+/// edits are appended to a per-document `.journal` instead and folded into the document
+/// file once the journal grows large.
+pub struct SplitStore {
+    root: PathBuf,
+    limits: HistoryLimits,
+}
+
+impl SplitStore {
+    pub fn new(root: PathBuf) -> Self {
+        SplitStore {
+            root,
+            limits: HistoryLimits::default(),
+        }
+    }
+
+    pub fn with_history_limits(mut self, limits: HistoryLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    fn manifest_path(&self) -> PathBuf {
+        self.root.join(MANIFEST_FILE)
+    }
+
+    fn documents_dir(&self) -> PathBuf {
+        self.root.join(DOCUMENTS_DIR)
+    }
+
+    fn document_path(&self, doc_id: &str) -> PathBuf {
+        self.documents_dir().join(document_file_name(doc_id))
+    }
+
+    fn journal_path(&self, doc_id: &str) -> PathBuf {
+        self.documents_dir().join(journal_file_name(doc_id))
+    }
+
+    fn write_document(&self, doc: &Document, journal_seq: u64) -> Result<(), String> {
+        let mut doc = doc.clone();
+        history::enforce_document_limits(&mut doc, self.limits);
+        let text = serde_json::to_string_pretty(&DocumentFile {
+            schema_version: CURRENT_SCHEMA_VERSION,
+            journal_seq,
+            document: StoredDocument::from(&doc),
+        })
+        .map_err(|e| e.to_string())?;
+        durable::write_atomic(
+            &durable::RealFs,
+            &self.document_path(&doc.id),
+            format!("{text}\n").as_bytes(),
+        )
+    }
+
+    /// Folds the journal into the document file. The snapshot is written first; if the
+    /// journal reset is lost to a crash, replay skips the batches the snapshot already has.
+    fn compact(&self, doc_id: &str) -> Result<(), String> {
+        let (mut doc, _, journal_seq) =
+            read_document(&self.document_path(doc_id)).map_err(|e| e.to_string())?;
+        let journal_path = self.journal_path(doc_id);
+        let last_seq = journal::replay(&journal_path, journal_seq, &mut doc)?.last_seq;
+        self.write_document(&doc, last_seq)?;
+        journal::reset(&journal_path, last_seq)
+    }
+
+    /// Replays the journal onto `doc` on load. A journal that cannot be read to the end is
+    /// moved aside as `<file>.broken-<millis>`, after what it did yield is folded into the
+    /// document file, so later appends start from a clean journal. Returns what went wrong.
+    fn replay_journal(&self, doc_id: &str, journal_seq: u64, doc: &mut Document) -> Option<String> {
+        let journal_path = self.journal_path(doc_id);
+        let reason = match journal::replay(&journal_path, journal_seq, doc) {
+            Ok(replayed) => {
+                let reason = replayed.stopped_early?;
+                if let Err(e) = self.write_document(doc, replayed.last_seq) {
+                    return Some(format!("journal of {doc_id}: {reason}; not compacted: {e}"));
+                }
+                reason
+            }
+            Err(e) => e,
+        };
+        let _ = fs::rename(&journal_path, broken_path(&journal_path));
+        Some(format!("journal of {doc_id}: {reason}"))
+    }
+
+    /// `*.json` files in `documents/`; leftovers like `*.broken-*` and copies left by sync
+    /// tools are not included.
+    fn document_files(&self) -> Result<Vec<PathBuf>, String> {
+        Ok(self
+            .files_with_extensions(&["json"])?
+            .into_iter()
+            .filter(|path| !is_copy(path))
+            .collect())
+    }
+
+    /// Files under names this store never writes, such as the copies sync tools leave when
+    /// two machines saved the same file: manifests beside `manifest.json`, and document
+    /// files whose name is not a `file_stem`.
+    pub fn conflicted_copies(&self) -> Result<Vec<PathBuf>, String> {
+        let mut copies: Vec<PathBuf> = self
+            .files_with_extensions(&["json"])?
+            .into_iter()
+            .filter(|path| is_copy(path))
+            .collect();
+        if self.root.exists() {
+            for entry in fs::read_dir(&self.root).map_err(|e| e.to_string())? {
+                let path = entry.map_err(|e| e.to_string())?.path();
+                let name = path.file_name().map(|n| n.to_string_lossy().into_owned());
+                if name.is_some_and(|name| {
+                    name != MANIFEST_FILE && name.starts_with("manifest") && name.ends_with(".json")
+                }) {
+                    copies.push(path);
+                }
+            }
+        }
+        copies.sort();
+        Ok(copies)
+    }
+
+    /// The workspace as it would be with `copy`, one of `conflicted_copies`, in place of the
+    /// file it copies: a document copy replaces that document in `current`; a manifest copy
+    /// brings its own tabs, over the documents saved here.
+    pub fn workspace_with_copy(&self, copy: &Path, current: &Workspace) -> Result<Workspace, String> {
+        if copy.parent() == Some(self.documents_dir().as_path()) {
+            let (doc, _, _) = read_document(copy).map_err(|e| e.to_string())?;
+            let mut workspace = current.clone();
+            workspace.documents.insert(doc.id.clone(), doc);
+            return Ok(workspace);
+        }
+        let (manifest, _) = read_manifest(copy).map_err(|e| e.to_string())?;
+        let mut documents = HashMap::new();
+        for doc_id in manifest.doc_ids {
+            let doc = match current.documents.get(&doc_id) {
+                Some(doc) => Some(doc.clone()),
+                None => self.load_document(&doc_id)?,
+            };
+            if let Some(doc) = doc {
+                documents.insert(doc_id, doc);
+            }
+        }
+        Ok(Workspace {
+            schema_version: CURRENT_SCHEMA_VERSION,
+            tabs: manifest.tabs,
+            active_doc_id: manifest.active_doc_id,
+            documents,
+        })
+    }
+
+    fn files_with_extensions(&self, extensions: &[&str]) -> Result<Vec<PathBuf>, String> {
+        let dir = self.documents_dir();
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(&dir).map_err(|e| e.to_string())? {
+            let path = entry.map_err(|e| e.to_string())?.path();
+            if path
+                .extension()
+                .is_some_and(|ext| extensions.iter().any(|e| ext == *e))
+            {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        Ok(paths)
+    }
+}
+
+impl WorkspaceStore for SplitStore {
+    fn exists(&self) -> bool {
+        self.manifest_path().exists()
+    }
+
+    fn load(&self) -> Result<StoreLoad, MigrateError> {
+        let mut manifest_broken = false;
+
+        let manifest = match read_manifest(&self.manifest_path()) {
+            Ok((manifest, from_version)) => {
+                keep_pre_migration_copy(&self.manifest_path(), from_version);
+                Some(manifest)
+            }
+            Err(e @ MigrateError::TooNew { .. }) => return Err(e),
+            Err(MigrateError::Invalid(_)) => {
+                manifest_broken = true;
+                let _ = fs::rename(self.manifest_path(), broken_path(&self.manifest_path()));
+                None
+            }
+        };
+
+        // Without a manifest every document file is a candidate; tabs are rebuilt from them.
+        let doc_paths: Vec<(Option<String>, PathBuf)> = match &manifest {
+            Some(manifest) => manifest
+                .doc_ids
+                .iter()
+                .map(|doc_id| (Some(doc_id.clone()), self.document_path(doc_id)))
+                .collect(),
+            None => self
+                .document_files()
+                .map_err(MigrateError::Invalid)?
+                .into_iter()
+                .map(|path| (None, path))
+                .collect(),
+        };
+
+        let mut documents = HashMap::new();
+        let mut lost = Vec::new();
+        let mut warnings = Vec::new();
+        for (expected_id, path) in doc_paths {
+            match read_document(&path) {
+                Ok((mut doc, from_version, journal_seq)) => {
+                    keep_pre_migration_copy(&path, from_version);
+                    let doc_id = expected_id.unwrap_or_else(|| doc.id.clone());
+                    if let Some(warning) = self.replay_journal(&doc_id, journal_seq, &mut doc) {
+                        warnings.push(warning);
+                    }
+                    history::enforce_document_limits(&mut doc, self.limits);
+                    documents.insert(doc_id, doc);
+                }
+                Err(e @ MigrateError::TooNew { .. }) => return Err(e),
+                Err(MigrateError::Invalid(reason)) => {
+                    if path.exists() {
+                        let _ = fs::rename(&path, broken_path(&path));
+                    }
+                    lost.push(LostDocument {
+                        doc_id: expected_id,
+                        reason,
+                    });
+                }
+            }
+        }
+
+        if documents.is_empty() {
+            return Ok(StoreLoad {
+                workspace: None,
+                lost,
+                warnings,
+                manifest_broken,
+            });
+        }
+
+        let (tabs, active_doc_id) = match manifest {
+            Some(manifest) => (manifest.tabs, manifest.active_doc_id),
+            None => {
+                let mut doc_ids: Vec<&String> = documents.keys().collect();
+                doc_ids.sort();
+                let tabs: Vec<TabRef> = doc_ids
+                    .into_iter()
+                    .map(|doc_id| TabRef {
+                        doc_id: doc_id.clone(),
+                    })
+                    .collect();
+                let active_doc_id = tabs[0].doc_id.clone();
+                (tabs, active_doc_id)
+            }
+        };
+
+        Ok(StoreLoad {
+            workspace: Some(Workspace {
+                schema_version: CURRENT_SCHEMA_VERSION,
+                tabs,
+                active_doc_id,
+                documents,
+            }),
+            lost,
+            warnings,
+            manifest_broken,
+        })
+    }
+
+    fn save_all(&self, workspace: &Workspace) -> Result<(), String> {
+        let mut doc_ids: Vec<String> = workspace.documents.keys().cloned().collect();
+        doc_ids.sort();
+        self.save_changes(&WorkspaceChanges {
+            tabs: workspace.tabs.clone(),
+            active_doc_id: workspace.active_doc_id.clone(),
+            doc_ids,
+            changed: workspace.documents.values().cloned().collect(),
+            journal: Vec::new(),
+        })
+    }
+
+    /// Documents are written before the manifest, so a crash in between leaves the previous
+    /// manifest pointing at complete files. Stale files are removed only afterwards.
+    fn save_changes(&self, changes: &WorkspaceChanges) -> Result<(), String> {
+        fs::create_dir_all(self.documents_dir()).map_err(|e| e.to_string())?;
+
+        for doc in &changes.changed {
+            // A full document supersedes everything journaled for it so far.
+            let journal_path = self.journal_path(&doc.id);
+            let journal_seq = journal::stats(&journal_path)?.map_or(0, |s| s.last_seq);
+            self.write_document(doc, journal_seq)?;
+            if journal_path.exists() {
+                journal::reset(&journal_path, journal_seq)?;
+            }
+        }
+
+        for entry in &changes.journal {
+            if entry.ops.is_empty() || !changes.doc_ids.contains(&entry.doc_id) {
+                continue;
+            }
+            let document_path = self.document_path(&entry.doc_id);
+            let journal_path = self.journal_path(&entry.doc_id);
+            if !journal::is_verified(&journal_path) {
+                // Changed since this process last read or wrote it: read it in full before
+                // trusting its ends, and deal with an unreadable line the way `load` does.
+                // The save fails once so the loss is reported; the retry appends cleanly.
+                let (mut doc, _, journal_seq) =
+                    read_document(&document_path).map_err(|e| e.to_string())?;
+                if let Some(warning) = self.replay_journal(&entry.doc_id, journal_seq, &mut doc) {
+                    return Err(warning);
+                }
+            }
+            let stats = journal::append(
+                &journal_path,
+                entry.ops.clone(),
+                || read_document(&document_path).map_or(0, |(_, _, seq)| seq),
+            )?;
+            if stats.needs_compaction() {
+                self.compact(&entry.doc_id)?;
+            }
+        }
+
+        let manifest = Manifest {
+            schema_version: CURRENT_SCHEMA_VERSION,
+            tabs: changes.tabs.clone(),
+            active_doc_id: changes.active_doc_id.clone(),
+            doc_ids: changes.doc_ids.clone(),
+        };
+        let text = serde_json::to_string_pretty(&manifest).map_err(|e| e.to_string())?;
+        durable::write_atomic(
+            &durable::RealFs,
+            &self.manifest_path(),
+            format!("{text}\n").as_bytes(),
+        )?;
+
+        let keep: HashSet<String> = changes
+            .doc_ids
+            .iter()
+            .flat_map(|doc_id| [document_file_name(doc_id), journal_file_name(doc_id)])
+            .collect();
+        for path in self.files_with_extensions(&["json", "journal"])? {
+            let file_name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            if !keep.contains(&file_name) {
+                let _ = fs::remove_file(&path);
+            }
+        }
+        Ok(())
+    }
+
+    fn load_document(&self, doc_id: &str) -> Result<Option<Document>, String> {
+        let path = self.document_path(doc_id);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let (mut doc, _, journal_seq) = read_document(&path).map_err(|e| e.to_string())?;
+        // A journal that stops early is dealt with by `load`; the readable batches apply.
+        journal::replay(&self.journal_path(doc_id), journal_seq, &mut doc)?;
+        history::enforce_document_limits(&mut doc, self.limits);
+        Ok(Some(doc))
+    }
+}
+
+fn document_file_name(doc_id: &str) -> String {
+    format!("{}.json", file_stem(doc_id))
+}
+
+fn journal_file_name(doc_id: &str) -> String {
+    format!("{}.journal", file_stem(doc_id))
+}
+
+/// UUIDs are used as-is; any other id is hex-encoded so it is always a safe file name.
+pub fn file_stem(doc_id: &str) -> String {
+    let safe = !doc_id.is_empty()
+        && doc_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if safe {
+        doc_id.to_string()
+    } else {
+        let hex: String = doc_id.bytes().map(|b| format!("{b:02x}")).collect();
+        format!("x{hex}")
+    }
+}
+
+/// A document file whose name `file_stem` would never produce.
+fn is_copy(path: &Path) -> bool {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .is_some_and(|stem| file_stem(&stem) != stem)
+}
+
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!("{file_name}.{suffix}-{millis}"))
+}
+
+fn broken_path(path: &Path) -> PathBuf {
+    sibling_path(path, "broken")
+}
+
+/// Same policy as the single-file layout: the raw file from an older schema is kept as
+/// `<file>.v<version>-<millis>` before the upgraded content can overwrite it.
+fn keep_pre_migration_copy(path: &Path, from_version: u32) {
+    if from_version < CURRENT_SCHEMA_VERSION {
+        let _ = fs::copy(path, sibling_path(path, &format!("v{from_version}")));
+    }
+}
+
+fn read_manifest(path: &Path) -> Result<(Manifest, u32), MigrateError> {
+    let text = fs::read_to_string(path).map_err(|e| MigrateError::Invalid(e.to_string()))?;
+    let mut value: Value =
+        serde_json::from_str(&text).map_err(|e| MigrateError::Invalid(e.to_string()))?;
+    let doc_ids = value.get_mut("docIds").map(Value::take);
+
+    // Migrations are written against the whole-workspace shape; run them on an empty one.
+    value["documents"] = Value::Object(Default::default());
+    let from_version = migrate::migrate_value(&mut value)?;
+    let workspace: Workspace =
+        serde_json::from_value(value).map_err(|e| MigrateError::Invalid(e.to_string()))?;
+    let doc_ids: Vec<String> = doc_ids
+        .map(serde_json::from_value)
+        .transpose()
+        .map_err(|e| MigrateError::Invalid(e.to_string()))?
+        .ok_or_else(|| MigrateError::Invalid("manifest has no docIds".to_string()))?;
+
+    Ok((
+        Manifest {
+            schema_version: CURRENT_SCHEMA_VERSION,
+            tabs: workspace.tabs,
+            active_doc_id: workspace.active_doc_id,
+            doc_ids,
+        },
+        from_version,
+    ))
+}
+
+fn read_document(path: &Path) -> Result<(Document, u32, u64), MigrateError> {
+    let text = fs::read_to_string(path).map_err(|e| MigrateError::Invalid(e.to_string()))?;
+    let mut value: Value =
+        serde_json::from_str(&text).map_err(|e| MigrateError::Invalid(e.to_string()))?;
+    let document = value
+        .get_mut("document")
+        .map(Value::take)
+        .ok_or_else(|| MigrateError::Invalid("document file has no document".to_string()))?;
+
+    let journal_seq = value
+        .get("journalSeq")
+        .and_then(Value::as_u64)
+        .unwrap_or(0);
+    let doc_id = document
+        .get("id")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string();
+
+    // Wrap the document in a one-document workspace so the shared migrations apply to it.
+    let mut workspace = serde_json::json!({
+        "tabs": [],
+        "activeDocId": "",
+        "documents": { doc_id.clone(): document },
+    });
+    if let Some(version) = value.get("schemaVersion") {
+        workspace["schemaVersion"] = version.clone();
+    }
+    let from_version = migrate::migrate_value(&mut workspace)?;
+    let document = workspace["documents"][doc_id.as_str()].take();
+    let document =
+        serde_json::from_value(document).map_err(|e| MigrateError::Invalid(e.to_string()))?;
+    Ok((document, from_version, journal_seq))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::journal::DocumentOps;
+    use crate::test_support::{self, Scratch};
+
+    fn document(id: &str, root_text: &str) -> Document {
+        let mut doc = test_support::document(&[("root", root_text, &["a"]), ("a", "A", &[])]);
+        doc.id = id.to_string();
+        doc
+    }
+
+    fn workspace(docs: &[Document]) -> Workspace {
+        Workspace {
+            schema_version: CURRENT_SCHEMA_VERSION,
+            tabs: docs
+                .iter()
+                .map(|doc| TabRef {
+                    doc_id: doc.id.clone(),
+                })
+                .collect(),
+            active_doc_id: docs[0].id.clone(),
+            documents: docs
+                .iter()
+                .map(|doc| (doc.id.clone(), doc.clone()))
+                .collect(),
+        }
+    }
+
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn root_text(workspace: &Workspace, doc_id: &str) -> String {
+        let doc = &workspace.documents[doc_id];
+        doc.nodes[&doc.root_id].text.clone()
+    }
+
+    #[test]
+    fn manifest_and_documents_round_trip() {
+        let scratch = Scratch::new("split-round-trip");
+        let store = SplitStore::new(scratch.dir.clone());
+        assert!(!store.exists());
+
+        let saved = workspace(&[document("doc-1", "One"), document("a/b", "Two")]);
+        store.save_all(&saved).unwrap();
+        assert!(store.exists());
+        assert_eq!(
+            file_names(&store.documents_dir()),
+            vec!["doc-1.json", "x612f62.json"]
+        );
+
+        let loaded = store.load().unwrap();
+        assert!(loaded.lost.is_empty());
+        assert!(!loaded.manifest_broken);
+        let workspace = loaded.workspace.unwrap();
+        let tabs: Vec<&str> = workspace.tabs.iter().map(|t| t.doc_id.as_str()).collect();
+        assert_eq!(tabs, vec!["doc-1", "a/b"]);
+        assert_eq!(workspace.active_doc_id, "doc-1");
+        assert_eq!(root_text(&workspace, "a/b"), "Two");
+        assert_eq!(
+            workspace.documents["doc-1"].nodes,
+            saved.documents["doc-1"].nodes
+        );
+
+        // Journaled edits are replayed on top of the document file.
+        store
+            .save_changes(&WorkspaceChanges {
+                tabs: vec![TabRef {
+                    doc_id: "doc-1".to_string(),
+                }],
+                active_doc_id: "doc-1".to_string(),
+                doc_ids: vec!["doc-1".to_string()],
+                changed: Vec::new(),
+                journal: vec![DocumentOps {
+                    doc_id: "doc-1".to_string(),
+                    ops: vec![journal::JournalOp::SetText {
+                        node_id: "root".to_string(),
+                        text: "Edited".to_string(),
+                    }],
+                }],
+            })
+            .unwrap();
+        let doc = store.load_document("doc-1").unwrap().unwrap();
+        assert_eq!(doc.nodes["root"].text, "Edited");
+        assert!(store.load_document("a/b").unwrap().is_none());
+        // The closed document's file is gone; the journal stays beside its document.
+        assert_eq!(
+            file_names(&store.documents_dir()),
+            vec!["doc-1.journal", "doc-1.json"]
+        );
+    }
+
+    #[test]
+    fn missing_manifest_rebuilds_tabs_from_document_files() {
+        let scratch = Scratch::new("split-no-manifest");
+        let store = SplitStore::new(scratch.dir.clone());
+        store
+            .save_all(&workspace(&[
+                document("doc-2", "Two"),
+                document("doc-1", "One"),
+            ]))
+            .unwrap();
+        fs::remove_file(store.manifest_path()).unwrap();
+
+        let loaded = store.load().unwrap();
+        assert!(loaded.manifest_broken);
+        let workspace = loaded.workspace.unwrap();
+        let tabs: Vec<&str> = workspace.tabs.iter().map(|t| t.doc_id.as_str()).collect();
+        assert_eq!(tabs, vec!["doc-1", "doc-2"]);
+        assert_eq!(workspace.active_doc_id, "doc-1");
+    }
+
+    #[test]
+    fn broken_manifest_is_moved_aside() {
+        let scratch = Scratch::new("split-broken-manifest");
+        let store = SplitStore::new(scratch.dir.clone());
+        store
+            .save_all(&workspace(&[document("doc-1", "One")]))
+            .unwrap();
+        fs::write(store.manifest_path(), "{ not json").unwrap();
+
+        let loaded = store.load().unwrap();
+        assert!(loaded.manifest_broken);
+        assert_eq!(root_text(&loaded.workspace.unwrap(), "doc-1"), "One");
+        let names = file_names(&scratch.dir);
+        assert_eq!(names.len(), 2);
+        assert_eq!(names[0], "documents");
+        assert!(names[1].starts_with("manifest.json.broken-"));
+    }
+
+    #[test]
+    fn broken_document_is_reported_and_kept_aside() {
+        let scratch = Scratch::new("split-broken-doc");
+        let store = SplitStore::new(scratch.dir.clone());
+        let saved = workspace(&[document("doc-1", "One"), document("doc-2", "Two")]);
+        store.save_all(&saved).unwrap();
+        fs::write(store.document_path("doc-2"), "{\"document\": 3}").unwrap();
+
+        let loaded = store.load().unwrap();
+        assert_eq!(loaded.lost.len(), 1);
+        assert_eq!(loaded.lost[0].doc_id.as_deref(), Some("doc-2"));
+        let workspace = loaded.workspace.unwrap();
+        assert_eq!(workspace.documents.len(), 1);
+        assert_eq!(root_text(&workspace, "doc-1"), "One");
+
+        // The next save must not delete the renamed file.
+        store.save_all(&workspace).unwrap();
+        let names = file_names(&store.documents_dir());
+        assert_eq!(names.len(), 2);
+        assert_eq!(names[0], "doc-1.json");
+        assert!(names[1].starts_with("doc-2.json.broken-"));
+    }
+
+    #[test]
+    fn v1_document_file_is_migrated_and_kept() {
+        let scratch = Scratch::new("split-migrate");
+        let store = SplitStore::new(scratch.dir.clone());
+        store
+            .save_all(&workspace(&[document("doc-1", "One")]))
+            .unwrap();
+        // A v1 file: snapshot history inside the document.
+        let v1 = serde_json::json!({
+            "schemaVersion": 1,
+            "document": {
+                "id": "doc-1",
+                "rootId": "root",
+                "cursorId": "a",
+                "nodes": {
+                    "root": { "id": "root", "text": "One", "parentId": null, "childrenIds": ["a"] },
+                    "a": { "id": "a", "text": "A", "parentId": "root", "childrenIds": [] },
+                },
+                "undoStack": [{
+                    "rootId": "root",
+                    "cursorId": "root",
+                    "nodes": {
+                        "root": { "id": "root", "text": "One", "parentId": null, "childrenIds": [] },
+                    },
+                }],
+                "redoStack": [],
+            },
+        });
+        fs::write(store.document_path("doc-1"), v1.to_string()).unwrap();
+
+        let (doc, from_version, journal_seq) =
+            read_document(&store.document_path("doc-1")).unwrap();
+        assert_eq!((from_version, journal_seq), (1, 0));
+        assert_eq!(doc.undo_stack.len(), 1);
+        assert_eq!(doc.undo_stack[0].cursor_after, "a");
+
+        let workspace = store.load().unwrap().workspace.unwrap();
+        assert_eq!(workspace.documents["doc-1"].undo_stack.len(), 1);
+        let names = file_names(&store.documents_dir());
+        assert_eq!(names.len(), 2);
+        assert_eq!(names[0], "doc-1.json");
+        assert!(names[1].starts_with("doc-1.json.v1-"));
+    }
+
+    #[test]
+    fn torn_journal_is_folded_in_and_moved_aside() {
+        let scratch = Scratch::new("split-torn-journal");
+        let store = SplitStore::new(scratch.dir.clone());
+        store
+            .save_all(&workspace(&[document("doc-1", "One")]))
+            .unwrap();
+        journal::append(
+            &store.journal_path("doc-1"),
+            vec![journal::JournalOp::SetText {
+                node_id: "root".to_string(),
+                text: "Journaled".to_string(),
+            }],
+            || 0,
+        )
+        .unwrap();
+        let mut journal_text = fs::read_to_string(store.journal_path("doc-1")).unwrap();
+        journal_text.push_str("{\"seq\":2,");
+        fs::write(store.journal_path("doc-1"), journal_text).unwrap();
+
+        let loaded = store.load().unwrap();
+        assert!(loaded.lost.is_empty());
+        assert_eq!(loaded.warnings, vec!["journal of doc-1: line 2: cut short"]);
+        assert_eq!(root_text(&loaded.workspace.unwrap(), "doc-1"), "Journaled");
+
+        let names = file_names(&store.documents_dir());
+        assert_eq!(names.len(), 2);
+        assert!(names[0].starts_with("doc-1.journal.broken-"));
+        assert_eq!(names[1], "doc-1.json");
+        let (doc, _, journal_seq) = read_document(&store.document_path("doc-1")).unwrap();
+        assert_eq!(
+            (doc.nodes["root"].text.as_str(), journal_seq),
+            ("Journaled", 1)
+        );
+
+        let reloaded = store.load().unwrap();
+        assert!(reloaded.warnings.is_empty());
+    }
+
+    #[test]
+    fn unreadable_middle_line_is_caught_before_appending() {
+        let scratch = Scratch::new("split-corrupt-journal");
+        let store = SplitStore::new(scratch.dir.clone());
+        let saved = workspace(&[document("doc-1", "One")]);
+        store.save_all(&saved).unwrap();
+        let set_text = |text: &str| WorkspaceChanges {
+            tabs: saved.tabs.clone(),
+            active_doc_id: saved.active_doc_id.clone(),
+            doc_ids: vec!["doc-1".to_string()],
+            changed: Vec::new(),
+            journal: vec![DocumentOps {
+                doc_id: "doc-1".to_string(),
+                ops: vec![journal::JournalOp::SetText {
+                    node_id: "root".to_string(),
+                    text: text.to_string(),
+                }],
+            }],
+        };
+        for text in ["Two", "Three", "Four"] {
+            store.save_changes(&set_text(text)).unwrap();
+        }
+
+        let journal_path = store.journal_path("doc-1");
+        let text = fs::read_to_string(&journal_path).unwrap();
+        let mut lines: Vec<&str> = text.lines().collect();
+        lines[1] = "{\"seq\":2,\"ops\":[";
+        fs::write(&journal_path, format!("{}\n", lines.join("\n"))).unwrap();
+
+        let error = store.save_changes(&set_text("Five")).unwrap_err();
+        assert!(error.starts_with("journal of doc-1: line 2"));
+        store.save_changes(&set_text("Five")).unwrap();
+
+        let loaded = store.load().unwrap();
+        assert!(loaded.warnings.is_empty());
+        assert_eq!(root_text(&loaded.workspace.unwrap(), "doc-1"), "Five");
+        let names = file_names(&store.documents_dir());
+        assert!(names
+            .iter()
+            .any(|name| name.starts_with("doc-1.journal.broken-")));
+    }
+}
@@ -0,0 +1,893 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::{Map, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use crate::history::{self, HistoryLimits};
+use crate::journal::{self, JournalOp};
+use crate::migrate::{self, MigrateError, CURRENT_SCHEMA_VERSION};
+use crate::model::{Document, Node, TabRef, UndoNode, UndoTree, Workspace, WorkspaceChanges};
+use crate::salvage::LostDocument;
+use crate::undo_tree;
+
+use super::{NodeMatch, StoreLoad, WorkspaceStore};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS meta (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS tabs (
+    position INTEGER PRIMARY KEY,
+    doc_id TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS documents (
+    doc_id TEXT PRIMARY KEY,
+    root_id TEXT NOT NULL,
+    cursor_id TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS nodes (
+    doc_id TEXT NOT NULL,
+    node_id TEXT NOT NULL,
+    text TEXT NOT NULL,
+    parent_id TEXT,
+    children_ids TEXT NOT NULL,
+    PRIMARY KEY (doc_id, node_id)
+);
+CREATE TABLE IF NOT EXISTS undo_trees (
+    doc_id TEXT PRIMARY KEY,
+    current_id TEXT NOT NULL,
+    redo_tip_id TEXT
+);
+CREATE TABLE IF NOT EXISTS undo_nodes (
+    doc_id TEXT NOT NULL,
+    node_id TEXT NOT NULL,
+    parent_id TEXT,
+    entry TEXT,
+    created_at_millis INTEGER NOT NULL,
+    PRIMARY KEY (doc_id, node_id)
+);
+";
+
+/// Workspace stored in one SQLite database: a row per node keyed by document and node id,
+/// plus tabs and the undo tree (a row per state), which holds all of the history; the
+/// undo/redo stacks are rebuilt from it on load. Every save runs in a single transaction, so a crash leaves either the previous
+/// or the new workspace, never a mix.
+pub struct SqliteStore {
+    conn: Connection,
+    limits: HistoryLimits,
+}
+
+impl SqliteStore {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let conn = Connection::open(path).map_err(|e| e.to_string())?;
+        conn.execute_batch(SCHEMA).map_err(|e| e.to_string())?;
+        Ok(SqliteStore {
+            conn,
+            limits: HistoryLimits::default(),
+        })
+    }
+
+    pub fn with_history_limits(mut self, limits: HistoryLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    fn meta(&self, key: &str) -> Result<Option<String>, String> {
+        self.conn
+            .query_row("SELECT value FROM meta WHERE key = ?1", [key], |row| {
+                row.get(0)
+            })
+            .optional()
+            .map_err(|e| e.to_string())
+    }
+
+    /// The document in its stored JSON shape, before migrations. History that cannot be read
+    /// is left out and reported in `warnings`; without them it fails the whole read.
+    fn document_value(
+        &self,
+        doc_id: &str,
+        warnings: Option<&mut Vec<String>>,
+    ) -> Result<Option<Value>, String> {
+        let head: Option<(String, String)> = self
+            .conn
+            .query_row(
+                "SELECT root_id, cursor_id FROM documents WHERE doc_id = ?1",
+                [doc_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        let Some((root_id, cursor_id)) = head else {
+            return Ok(None);
+        };
+
+        let mut nodes = Map::new();
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT node_id, text, parent_id, children_ids FROM nodes WHERE doc_id = ?1",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([doc_id], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })
+            .map_err(|e| e.to_string())?;
+        for row in rows {
+            let (node_id, text, parent_id, children_ids) = row.map_err(|e| e.to_string())?;
+            let children_ids: Value = serde_json::from_str(&children_ids)
+                .map_err(|e| format!("node {node_id}: {e}"))?;
+            nodes.insert(
+                node_id.clone(),
+                serde_json::json!({
+                    "id": node_id,
+                    "text": text,
+                    "parentId": parent_id,
+                    "childrenIds": children_ids,
+                }),
+            );
+        }
+
+        let (undo_tree, redo_tip_id) = match (read_tree(&self.conn, doc_id), warnings) {
+            (Ok(history), _) => history,
+            (Err(e), Some(warnings)) => {
+                warnings.push(format!(
+                    "history of {doc_id} could not be read and was dropped: {e}"
+                ));
+                Default::default()
+            }
+            (Err(e), None) => return Err(format!("history of {doc_id}: {e}")),
+        };
+        Ok(Some(serde_json::json!({
+            "id": doc_id,
+            "rootId": root_id,
+            "cursorId": cursor_id,
+            "nodes": nodes,
+            "undoTree": undo_tree,
+            "redoTipId": redo_tip_id,
+        })))
+    }
+
+    fn stored_version(&self) -> Result<Option<Value>, String> {
+        Ok(self
+            .meta("schemaVersion")?
+            .and_then(|version| version.parse::<u32>().ok())
+            .map(Value::from))
+    }
+
+    fn doc_ids(&self) -> Result<Vec<String>, String> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT doc_id FROM documents ORDER BY doc_id")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+    }
+
+    fn tabs(&self) -> Result<Vec<TabRef>, String> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT doc_id FROM tabs ORDER BY position")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| Ok(TabRef { doc_id: row.get(0)? }))
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+    }
+}
+
+impl WorkspaceStore for SqliteStore {
+    fn exists(&self) -> bool {
+        self.meta("activeDocId").is_ok_and(|active| active.is_some())
+    }
+
+    fn load(&self) -> Result<StoreLoad, MigrateError> {
+        let invalid = MigrateError::Invalid;
+
+        let mut lost = Vec::new();
+        let mut warnings = Vec::new();
+        let mut documents = Map::new();
+        for doc_id in self.doc_ids().map_err(invalid)? {
+            match self.document_value(&doc_id, Some(&mut warnings)) {
+                Ok(Some(doc)) => {
+                    documents.insert(doc_id, doc);
+                }
+                Ok(None) => {}
+                Err(reason) => lost.push(LostDocument {
+                    doc_id: Some(doc_id),
+                    reason,
+                }),
+            }
+        }
+
+        let mut value = serde_json::json!({
+            "tabs": self.tabs().map_err(invalid)?,
+            "activeDocId": self.meta("activeDocId").map_err(invalid)?.unwrap_or_default(),
+            "documents": documents,
+        });
+        if let Some(version) = self.stored_version().map_err(invalid)? {
+            value["schemaVersion"] = version;
+        }
+        // Rejects a database written by a newer build.
+        migrate::migrate_value(&mut value)?;
+
+        let mut workspace = Workspace {
+            schema_version: CURRENT_SCHEMA_VERSION,
+            tabs: serde_json::from_value(value["tabs"].take())
+                .map_err(|e| MigrateError::Invalid(e.to_string()))?,
+            active_doc_id: value["activeDocId"].as_str().unwrap_or_default().to_string(),
+            documents: HashMap::new(),
+        };
+        if let Value::Object(raw) = value["documents"].take() {
+            for (doc_id, raw) in raw {
+                match serde_json::from_value::<Document>(raw) {
+                    Ok(doc) => {
+                        workspace.documents.insert(doc_id, doc);
+                    }
+                    Err(e) => lost.push(LostDocument {
+                        doc_id: Some(doc_id),
+                        reason: e.to_string(),
+                    }),
+                }
+            }
+        }
+
+        if workspace.documents.is_empty() {
+            return Ok(StoreLoad {
+                workspace: None,
+                lost,
+                warnings,
+                manifest_broken: false,
+            });
+        }
+
+        Ok(StoreLoad {
+            workspace: Some(workspace),
+            lost,
+            warnings,
+            manifest_broken: false,
+        })
+    }
+
+    fn load_document(&self, doc_id: &str) -> Result<Option<Document>, String> {
+        let Some(document) = self.document_value(doc_id, None)? else {
+            return Ok(None);
+        };
+        let mut workspace = serde_json::json!({
+            "tabs": [],
+            "activeDocId": "",
+            "documents": { doc_id: document },
+        });
+        if let Some(version) = self.stored_version()? {
+            workspace["schemaVersion"] = version;
+        }
+        migrate::migrate_value(&mut workspace).map_err(|e| e.to_string())?;
+        let doc: Document = serde_json::from_value(workspace["documents"][doc_id].take())
+            .map_err(|e| e.to_string())?;
+        Ok(Some(doc))
+    }
+
+    fn save_all(&self, workspace: &Workspace) -> Result<(), String> {
+        let mut doc_ids: Vec<String> = workspace.documents.keys().cloned().collect();
+        doc_ids.sort();
+        self.save_changes(&WorkspaceChanges {
+            tabs: workspace.tabs.clone(),
+            active_doc_id: workspace.active_doc_id.clone(),
+            doc_ids,
+            changed: workspace.documents.values().cloned().collect(),
+            journal: Vec::new(),
+        })
+    }
+
+    fn save_changes(&self, changes: &WorkspaceChanges) -> Result<(), String> {
+        // `unchecked_transaction` because the store is shared by reference; nothing else
+        // uses this connection concurrently.
+        let tx = self
+            .conn
+            .unchecked_transaction()
+            .map_err(|e| e.to_string())?;
+
+        for doc in &changes.changed {
+            let mut doc = doc.clone();
+            history::enforce_document_limits(&mut doc, self.limits);
+            write_document(&tx, &doc)?;
+        }
+        for entry in &changes.journal {
+            if !changes.doc_ids.contains(&entry.doc_id) {
+                continue;
+            }
+            for op in &entry.ops {
+                apply_op(&tx, &entry.doc_id, op)?;
+            }
+            if entry.ops.iter().any(is_history_op) {
+                apply_history(&tx, &entry.doc_id, &entry.ops, self.limits)?;
+            }
+        }
+
+        let keep: HashSet<&String> = changes.doc_ids.iter().collect();
+        for doc_id in self.doc_ids()? {
+            if !keep.contains(&doc_id) {
+                delete_document(&tx, &doc_id)?;
+            }
+        }
+
+        tx.execute("DELETE FROM tabs", []).map_err(|e| e.to_string())?;
+        for (position, tab) in changes.tabs.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO tabs (position, doc_id) VALUES (?1, ?2)",
+                params![position as i64, tab.doc_id],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        set_meta(&tx, "activeDocId", &changes.active_doc_id)?;
+        set_meta(&tx, "schemaVersion", &CURRENT_SCHEMA_VERSION.to_string())?;
+
+        tx.commit().map_err(|e| e.to_string())
+    }
+
+    /// Matches are filtered here rather than with `LIKE`, which folds case for ASCII only;
+    /// rows stream in tab order, so reading stops at `limit`.
+    fn search_nodes(&self, query: &str, limit: usize) -> Result<Vec<NodeMatch>, String> {
+        let needle = query.to_lowercase();
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT nodes.doc_id, nodes.node_id, nodes.text FROM nodes
+                 JOIN tabs ON tabs.doc_id = nodes.doc_id
+                 ORDER BY tabs.position, nodes.node_id",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok(NodeMatch {
+                    doc_id: row.get(0)?,
+                    node_id: row.get(1)?,
+                    text: row.get(2)?,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+        let mut matches = Vec::new();
+        for row in rows {
+            if matches.len() == limit {
+                break;
+            }
+            let found = row.map_err(|e| e.to_string())?;
+            if found.text.to_lowercase().contains(&needle) {
+                matches.push(found);
+            }
+        }
+        Ok(matches)
+    }
+}
+
+fn set_meta(conn: &Connection, key: &str, value: &str) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO meta (key, value) VALUES (?1, ?2)
+         ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+        params![key, value],
+    )
+    .map(|_| ())
+    .map_err(|e| e.to_string())
+}
+
+fn delete_document(conn: &Connection, doc_id: &str) -> Result<(), String> {
+    for sql in [
+        "DELETE FROM documents WHERE doc_id = ?1",
+        "DELETE FROM nodes WHERE doc_id = ?1",
+        "DELETE FROM undo_trees WHERE doc_id = ?1",
+        "DELETE FROM undo_nodes WHERE doc_id = ?1",
+    ] {
+        conn.execute(sql, [doc_id]).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn write_document(conn: &Connection, doc: &Document) -> Result<(), String> {
+    delete_document(conn, &doc.id)?;
+    conn.execute(
+        "INSERT INTO documents (doc_id, root_id, cursor_id) VALUES (?1, ?2, ?3)",
+        params![doc.id, doc.root_id, doc.cursor_id],
+    )
+    .map_err(|e| e.to_string())?;
+    for node in doc.nodes.values() {
+        insert_node(conn, &doc.id, node)?;
+    }
+    set_tree_head(
+        conn,
+        &doc.id,
+        &doc.undo_tree.current_id,
+        undo_tree::redo_tip(&doc.undo_tree, &doc.redo_stack).as_deref(),
+    )?;
+    for node in doc.undo_tree.nodes.values() {
+        put_undo_node(conn, &doc.id, node)?;
+    }
+    Ok(())
+}
+
+fn insert_node(conn: &Connection, doc_id: &str, node: &Node) -> Result<(), String> {
+    let children_ids = serde_json::to_string(&node.children_ids).map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT OR REPLACE INTO nodes (doc_id, node_id, text, parent_id, children_ids)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![doc_id, node.id, node.text, node.parent_id, children_ids],
+    )
+    .map(|_| ())
+    .map_err(|e| e.to_string())
+}
+
+fn set_tree_head(
+    conn: &Connection,
+    doc_id: &str,
+    current_id: &str,
+    redo_tip_id: Option<&str>,
+) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO undo_trees (doc_id, current_id, redo_tip_id) VALUES (?1, ?2, ?3)
+         ON CONFLICT (doc_id) DO UPDATE
+         SET current_id = excluded.current_id, redo_tip_id = excluded.redo_tip_id",
+        params![doc_id, current_id, redo_tip_id],
+    )
+    .map(|_| ())
+    .map_err(|e| e.to_string())
+}
+
+fn put_undo_node(conn: &Connection, doc_id: &str, node: &UndoNode) -> Result<(), String> {
+    let entry = node
+        .entry
+        .as_ref()
+        .map(serde_json::to_string)
+        .transpose()
+        .map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT OR REPLACE INTO undo_nodes (doc_id, node_id, parent_id, entry, created_at_millis)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            doc_id,
+            node.id,
+            node.parent_id,
+            entry,
+            node.created_at_millis as i64
+        ],
+    )
+    .map(|_| ())
+    .map_err(|e| e.to_string())
+}
+
+fn remove_undo_node(conn: &Connection, doc_id: &str, node_id: &str) -> Result<(), String> {
+    conn.execute(
+        "DELETE FROM undo_nodes WHERE doc_id = ?1 AND node_id = ?2",
+        params![doc_id, node_id],
+    )
+    .map(|_| ())
+    .map_err(|e| e.to_string())
+}
+
+/// The undo tree and the id of the redo tip, if there is anything to redo.
+fn read_tree(conn: &Connection, doc_id: &str) -> Result<(UndoTree, Option<String>), String> {
+    let head: Option<(String, Option<String>)> = conn
+        .query_row(
+            "SELECT current_id, redo_tip_id FROM undo_trees WHERE doc_id = ?1",
+            [doc_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    let (current_id, redo_tip_id) = head.unwrap_or_default();
+    let mut tree = UndoTree {
+        current_id,
+        nodes: HashMap::new(),
+    };
+    let mut stmt = conn
+        .prepare(
+            "SELECT node_id, parent_id, entry, created_at_millis FROM undo_nodes WHERE doc_id = ?1",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([doc_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, i64>(3)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+    for row in rows {
+        let (node_id, parent_id, entry, created_at_millis) = row.map_err(|e| e.to_string())?;
+        let entry = entry
+            .map(|entry| serde_json::from_str(&entry))
+            .transpose()
+            .map_err(|e| format!("undo node {node_id}: {e}"))?;
+        tree.nodes.insert(
+            node_id.clone(),
+            UndoNode {
+                id: node_id,
+                parent_id,
+                entry,
+                created_at_millis: created_at_millis as u64,
+            },
+        );
+    }
+    Ok((tree, redo_tip_id))
+}
+
+fn is_history_op(op: &JournalOp) -> bool {
+    matches!(
+        op,
+        JournalOp::PopUndo { .. }
+            | JournalOp::PushUndo { .. }
+            | JournalOp::PopRedo { .. }
+            | JournalOp::PushRedo { .. }
+            | JournalOp::PutUndoNode { .. }
+            | JournalOp::RemoveUndoNode { .. }
+            | JournalOp::SetUndoCurrent { .. }
+    )
+}
+
+/// Applies the history ops of one journal batch. Only the tree is stored, so the ops run in
+/// memory against it and the stacks it stands for; the limits are then applied and the tree
+/// nodes that changed are written back.
+fn apply_history(
+    conn: &Connection,
+    doc_id: &str,
+    ops: &[JournalOp],
+    limits: HistoryLimits,
+) -> Result<(), String> {
+    let (before, redo_tip_id) = read_tree(conn, doc_id)?;
+    let (undo_stack, redo_stack) = undo_tree::stacks(&before, redo_tip_id.as_deref());
+    let mut doc = Document {
+        id: doc_id.to_string(),
+        root_id: String::new(),
+        cursor_id: String::new(),
+        nodes: HashMap::new(),
+        undo_stack,
+        redo_stack,
+        undo_tree: before.clone(),
+    };
+    for op in ops.iter().filter(|op| is_history_op(op)) {
+        journal::apply_op(&mut doc, op.clone());
+    }
+    history::enforce_document_limits(&mut doc, limits);
+
+    let tree = &doc.undo_tree;
+    for node_id in before.nodes.keys().filter(|id| !tree.nodes.contains_key(*id)) {
+        remove_undo_node(conn, doc_id, node_id)?;
+    }
+    for node in tree.nodes.values() {
+        if before.nodes.get(&node.id) != Some(node) {
+            put_undo_node(conn, doc_id, node)?;
+        }
+    }
+    set_tree_head(
+        conn,
+        doc_id,
+        &tree.current_id,
+        undo_tree::redo_tip(tree, &doc.redo_stack).as_deref(),
+    )
+}
+
+/// The row-level counterpart of `journal::apply_op`.
+fn apply_op(conn: &Connection, doc_id: &str, op: &JournalOp) -> Result<(), String> {
+    let updated = match op {
+        JournalOp::AddNode { node } => return insert_node(conn, doc_id, node),
+        JournalOp::SetText { node_id, text } => conn.execute(
+            "UPDATE nodes SET text = ?3 WHERE doc_id = ?1 AND node_id = ?2",
+            params![doc_id, node_id, text],
+        ),
+        JournalOp::MoveNode { node_id, parent_id } => conn.execute(
+            "UPDATE nodes SET parent_id = ?3 WHERE doc_id = ?1 AND node_id = ?2",
+            params![doc_id, node_id, parent_id],
+        ),
+        JournalOp::SetChildren {
+            node_id,
+            children_ids,
+        } => {
+            let children_ids = serde_json::to_string(children_ids).map_err(|e| e.to_string())?;
+            conn.execute(
+                "UPDATE nodes SET children_ids = ?3 WHERE doc_id = ?1 AND node_id = ?2",
+                params![doc_id, node_id, children_ids],
+            )
+        }
+        JournalOp::DeleteNode { node_id } => conn.execute(
+            "DELETE FROM nodes WHERE doc_id = ?1 AND node_id = ?2",
+            params![doc_id, node_id],
+        ),
+        JournalOp::SetCursor { node_id } => conn.execute(
+            "UPDATE documents SET cursor_id = ?2 WHERE doc_id = ?1",
+            params![doc_id, node_id],
+        ),
+        JournalOp::SetRoot { node_id } => conn.execute(
+            "UPDATE documents SET root_id = ?2 WHERE doc_id = ?1",
+            params![doc_id, node_id],
+        ),
+        // History ops go through `apply_history` as a whole batch.
+        JournalOp::PopUndo { .. }
+        | JournalOp::PushUndo { .. }
+        | JournalOp::PopRedo { .. }
+        | JournalOp::PushRedo { .. }
+        | JournalOp::PutUndoNode { .. }
+        | JournalOp::RemoveUndoNode { .. }
+        | JournalOp::SetUndoCurrent { .. } => return Ok(()),
+    };
+    updated.map(|_| ()).map_err(|e| e.to_string())
+}
+
+/// Removes a database together with the sidecar files SQLite may leave next to it.
+pub fn remove_database(path: &Path) {
+    let _ = fs::remove_file(path);
+    for suffix in ["-journal", "-wal", "-shm"] {
+        let file_name = path
+            .file_name()
+            .map(|name| format!("{}{suffix}", name.to_string_lossy()))
+            .unwrap_or_default();
+        let _ = fs::remove_file(path.with_file_name(file_name));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::journal::DocumentOps;
+    use crate::model::{EditOp, HistoryEntry};
+    use crate::test_support::{self, Scratch};
+
+    fn edit_text(before: &str, after: &str) -> HistoryEntry {
+        HistoryEntry {
+            cursor_before: "root".to_string(),
+            cursor_after: "root".to_string(),
+            ops: vec![EditOp::EditText {
+                node_id: "root".to_string(),
+                before: before.to_string(),
+                after: after.to_string(),
+            }],
+        }
+    }
+
+    fn document(id: &str, tree: &[(&str, &str, &[&str])]) -> Document {
+        let mut doc = test_support::document(tree);
+        doc.id = id.to_string();
+        doc
+    }
+
+    fn workspace(docs: Vec<Document>) -> Workspace {
+        Workspace {
+            schema_version: CURRENT_SCHEMA_VERSION,
+            tabs: docs
+                .iter()
+                .map(|doc| TabRef {
+                    doc_id: doc.id.clone(),
+                })
+                .collect(),
+            active_doc_id: docs[0].id.clone(),
+            documents: docs.into_iter().map(|doc| (doc.id.clone(), doc)).collect(),
+        }
+    }
+
+    fn changes(workspace: &Workspace, journal: Vec<DocumentOps>) -> WorkspaceChanges {
+        WorkspaceChanges {
+            tabs: workspace.tabs.clone(),
+            active_doc_id: workspace.active_doc_id.clone(),
+            doc_ids: workspace.documents.keys().cloned().collect(),
+            changed: Vec::new(),
+            journal,
+        }
+    }
+
+    fn set_root_text(doc_id: &str, text: &str) -> DocumentOps {
+        DocumentOps {
+            doc_id: doc_id.to_string(),
+            ops: vec![JournalOp::SetText {
+                node_id: "root".to_string(),
+                text: text.to_string(),
+            }],
+        }
+    }
+
+    fn root_text(store: &SqliteStore, doc_id: &str) -> String {
+        let doc = store.load_document(doc_id).unwrap().unwrap();
+        doc.nodes[&doc.root_id].text.clone()
+    }
+
+    #[test]
+    fn workspace_round_trips_with_history() {
+        let scratch = Scratch::new("sqlite-round-trip");
+        let store = SqliteStore::open(&scratch.dir.join("workspace.sqlite3")).unwrap();
+        assert!(!store.exists());
+
+        let mut doc = document("doc-1", &[("root", "Two", &["a"]), ("a", "A", &[])]);
+        doc.undo_stack = vec![edit_text("One", "Two")];
+        doc.redo_stack = vec![edit_text("Two", "Three")];
+        doc.undo_tree = UndoTree {
+            current_id: "t1".to_string(),
+            nodes: [
+                UndoNode {
+                    id: "t0".to_string(),
+                    parent_id: None,
+                    entry: None,
+                    created_at_millis: 1,
+                },
+                UndoNode {
+                    id: "t1".to_string(),
+                    parent_id: Some("t0".to_string()),
+                    entry: Some(edit_text("One", "Two")),
+                    created_at_millis: 2,
+                },
+                UndoNode {
+                    id: "t2".to_string(),
+                    parent_id: Some("t1".to_string()),
+                    entry: Some(edit_text("Two", "Three")),
+                    created_at_millis: 3,
+                },
+            ]
+            .into_iter()
+            .map(|node| (node.id.clone(), node))
+            .collect(),
+        };
+        let saved = workspace(vec![
+            document("doc-2", &[("root", "Other", &[])]),
+            doc.clone(),
+        ]);
+        store.save_all(&saved).unwrap();
+        assert!(store.exists());
+
+        let loaded = store.load().unwrap();
+        assert!(loaded.lost.is_empty());
+        let workspace = loaded.workspace.unwrap();
+        let tabs: Vec<&str> = workspace.tabs.iter().map(|t| t.doc_id.as_str()).collect();
+        assert_eq!(tabs, vec!["doc-2", "doc-1"]);
+        assert_eq!(workspace.active_doc_id, "doc-2");
+        let loaded_doc = &workspace.documents["doc-1"];
+        assert_eq!(loaded_doc.nodes, doc.nodes);
+        assert_eq!(loaded_doc.undo_stack, doc.undo_stack);
+        assert_eq!(loaded_doc.redo_stack, doc.redo_stack);
+        assert_eq!(loaded_doc.undo_tree, doc.undo_tree);
+
+        // Journal ops update rows in place; documents left out of `doc_ids` are deleted.
+        let mut remaining = workspace.clone();
+        remaining.documents.remove("doc-2");
+        remaining.tabs.remove(0);
+        remaining.active_doc_id = "doc-1".to_string();
+        store
+            .save_changes(&changes(&remaining, vec![set_root_text("doc-1", "Four")]))
+            .unwrap();
+        assert_eq!(root_text(&store, "doc-1"), "Four");
+        assert!(store.load_document("doc-2").unwrap().is_none());
+
+        // History ops move through the tree; the stacks come back from it.
+        let undo = DocumentOps {
+            doc_id: "doc-1".to_string(),
+            ops: vec![
+                JournalOp::PopUndo { count: 1 },
+                JournalOp::PushRedo {
+                    entry: edit_text("One", "Two"),
+                },
+                JournalOp::SetUndoCurrent {
+                    node_id: "t0".to_string(),
+                },
+            ],
+        };
+        store.save_changes(&changes(&remaining, vec![undo])).unwrap();
+        let loaded = store.load_document("doc-1").unwrap().unwrap();
+        assert_eq!(loaded.undo_tree.current_id, "t0");
+        assert!(loaded.undo_stack.is_empty());
+        assert_eq!(
+            loaded.redo_stack,
+            vec![edit_text("Two", "Three"), edit_text("One", "Two")]
+        );
+    }
+
+    #[test]
+    fn failed_save_leaves_the_previous_workspace() {
+        let scratch = Scratch::new("sqlite-rollback");
+        let store = SqliteStore::open(&scratch.dir.join("workspace.sqlite3")).unwrap();
+        let saved = workspace(vec![
+            document("doc-1", &[("root", "One", &[])]),
+            document("doc-2", &[("root", "Two", &[])]),
+        ]);
+        store.save_all(&saved).unwrap();
+
+        // Fail the last statements of the save, after documents and journal are written.
+        store
+            .conn
+            .execute_batch(
+                "CREATE TRIGGER fail_tabs BEFORE INSERT ON tabs
+                 BEGIN SELECT RAISE(ABORT, 'injected failure'); END;",
+            )
+            .unwrap();
+        let mut remaining = saved.clone();
+        remaining.documents.remove("doc-2");
+        remaining.tabs.remove(1);
+        let mut changed = changes(&remaining, vec![set_root_text("doc-1", "Edited")]);
+        changed.changed = vec![document("doc-1", &[("root", "Replaced", &[])])];
+        let error = store.save_changes(&changed).unwrap_err();
+        assert!(error.contains("injected failure"));
+        let error = store.save_all(&remaining).unwrap_err();
+        assert!(error.contains("injected failure"));
+
+        store.conn.execute_batch("DROP TRIGGER fail_tabs").unwrap();
+        let workspace = store.load().unwrap().workspace.unwrap();
+        assert_eq!(workspace.tabs.len(), 2);
+        assert_eq!(root_text(&store, "doc-1"), "One");
+        assert_eq!(root_text(&store, "doc-2"), "Two");
+    }
+
+    #[test]
+    fn unreadable_history_is_reported() {
+        let scratch = Scratch::new("sqlite-broken-history");
+        let store = SqliteStore::open(&scratch.dir.join("workspace.sqlite3")).unwrap();
+        let mut doc = document("doc-1", &[("root", "Two", &[])]);
+        doc.undo_stack = vec![edit_text("One", "Two")];
+        store.save_all(&workspace(vec![doc])).unwrap();
+        store
+            .conn
+            .execute("UPDATE undo_nodes SET entry = 'not json' WHERE entry IS NOT NULL", [])
+            .unwrap();
+
+        let loaded = store.load().unwrap();
+        assert_eq!(loaded.warnings.len(), 1);
+        assert!(loaded.warnings[0].starts_with("history of doc-1"));
+        let doc = &loaded.workspace.unwrap().documents["doc-1"];
+        assert_eq!(doc.nodes["root"].text, "Two");
+        assert!(doc.undo_stack.is_empty());
+        assert!(store.load_document("doc-1").is_err());
+    }
+
+    #[test]
+    fn search_matches_literally_in_tab_order() {
+        let scratch = Scratch::new("sqlite-search");
+        let store = SqliteStore::open(&scratch.dir.join("workspace.sqlite3")).unwrap();
+        store
+            .save_all(&workspace(vec![
+                document(
+                    "doc-b",
+                    &[
+                        ("root", "Budget 100%", &["x", "y"]),
+                        ("x", "a_b", &[]),
+                        ("y", "axb", &[]),
+                    ],
+                ),
+                document(
+                    "doc-a",
+                    &[
+                        ("root", "100 percent", &["w", "z"]),
+                        ("w", "äpfel", &[]),
+                        ("z", "C:\\temp", &[]),
+                    ],
+                ),
+            ]))
+            .unwrap();
+
+        let found = |query: &str, limit: usize| -> Vec<(String, String)> {
+            store
+                .search_nodes(query, limit)
+                .unwrap()
+                .into_iter()
+                .map(|m| (m.doc_id, m.node_id))
+                .collect()
+        };
+        let hit = |doc_id: &str, node_id: &str| (doc_id.to_string(), node_id.to_string());
+
+        assert_eq!(found("%", 10), vec![hit("doc-b", "root")]);
+        assert_eq!(found("_", 10), vec![hit("doc-b", "x")]);
+        assert_eq!(found("\\", 10), vec![hit("doc-a", "z")]);
+        assert_eq!(
+            found("100", 10),
+            vec![hit("doc-b", "root"), hit("doc-a", "root")]
+        );
+        assert_eq!(found("BUDGET", 10), vec![hit("doc-b", "root")]);
+        assert_eq!(found("ÄPFEL", 10), vec![hit("doc-a", "w")]);
+        assert_eq!(found("a", 2), vec![hit("doc-b", "x"), hit("doc-b", "y")]);
+        assert!(found("missing", 10).is_empty());
+    }
+}
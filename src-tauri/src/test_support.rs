@@ -0,0 +1,72 @@
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use crate::model::{Document, DocumentState, Node, UndoTree};
+
+/// Nodes from `(id, text, children)` rows. Each node's `parent_id` is the row that lists it
+/// as a child, so a consistent tree comes out unless the rows themselves disagree.
+pub fn nodes(tree: &[(&str, &str, &[&str])]) -> HashMap<String, Node> {
+    let mut nodes: HashMap<String, Node> = tree
+        .iter()
+        .map(|(id, text, children)| {
+            let node = Node {
+                id: id.to_string(),
+                text: text.to_string(),
+                parent_id: None,
+                children_ids: children.iter().map(|c| c.to_string()).collect(),
+            };
+            (id.to_string(), node)
+        })
+        .collect();
+    for (id, _, children) in tree {
+        for child in children.iter() {
+            if let Some(node) = nodes.get_mut(*child) {
+                node.parent_id = Some(id.to_string());
+            }
+        }
+    }
+    nodes
+}
+
+/// The first row is the root and holds the cursor.
+pub fn state(tree: &[(&str, &str, &[&str])]) -> DocumentState {
+    let root_id = tree.first().map_or("root", |(id, _, _)| *id).to_string();
+    DocumentState {
+        cursor_id: root_id.clone(),
+        root_id,
+        nodes: nodes(tree),
+    }
+}
+
+/// A document `doc` with no history; the first row is the root and holds the cursor.
+pub fn document(tree: &[(&str, &str, &[&str])]) -> Document {
+    let state = state(tree);
+    Document {
+        id: "doc".to_string(),
+        root_id: state.root_id,
+        cursor_id: state.cursor_id,
+        nodes: state.nodes,
+        undo_stack: Vec::new(),
+        redo_stack: Vec::new(),
+        undo_tree: UndoTree::default(),
+    }
+}
+
+/// A fresh directory under the system temp dir, removed again on drop.
+pub struct Scratch {
+    pub dir: PathBuf,
+}
+
+impl Scratch {
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("vikokoro-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Scratch { dir }
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
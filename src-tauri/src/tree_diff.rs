@@ -0,0 +1,300 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+use crate::model::{DocumentState, Node};
+
+type Nodes = HashMap<String, Node>;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffNode {
+    pub node_id: String,
+    pub parent_id: Option<String>,
+    pub text: String,
+}
+
+/// A node under a different parent, or in a different order among the siblings it kept.
+/// Nodes that only shift because a sibling was added or removed are not moves.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MovedNode {
+    pub node_id: String,
+    pub text: String,
+    pub from_parent_id: Option<String>,
+    pub to_parent_id: Option<String>,
+    pub from_index: usize,
+    pub to_index: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetextedNode {
+    pub node_id: String,
+    pub before: String,
+    pub after: String,
+}
+
+/// What changed from one tree to another, keyed by node id. Each list is sorted by id.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TreeDiff {
+    pub added: Vec<DiffNode>,
+    pub removed: Vec<DiffNode>,
+    pub moved: Vec<MovedNode>,
+    pub retexted: Vec<RetextedNode>,
+}
+
+impl TreeDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.moved.is_empty()
+            && self.retexted.is_empty()
+    }
+
+    /// Counts such as "2 added, 1 moved", or "No changes".
+    pub fn summary(&self) -> String {
+        let counts = [
+            (self.added.len(), "added"),
+            (self.removed.len(), "removed"),
+            (self.moved.len(), "moved"),
+            (self.retexted.len(), "edited"),
+        ];
+        let parts: Vec<String> = counts
+            .iter()
+            .filter(|(count, _)| *count > 0)
+            .map(|(count, what)| format!("{count} {what}"))
+            .collect();
+        if parts.is_empty() {
+            return "No changes".to_string();
+        }
+        parts.join(", ")
+    }
+}
+
+pub fn diff(before: &DocumentState, after: &DocumentState) -> TreeDiff {
+    diff_nodes(&before.nodes, &after.nodes)
+}
+
+fn diff_node(node: &Node) -> DiffNode {
+    DiffNode {
+        node_id: node.id.clone(),
+        parent_id: node.parent_id.clone(),
+        text: node.text.clone(),
+    }
+}
+
+fn index_in(nodes: &Nodes, parent_id: Option<&String>, node_id: &str) -> usize {
+    parent_id
+        .and_then(|parent_id| nodes.get(parent_id))
+        .and_then(|parent| parent.children_ids.iter().position(|id| id == node_id))
+        .unwrap_or(0)
+}
+
+/// Positions in `a` of one longest common subsequence of `a` and `b`. The shared prefix
+/// and suffix are kept as they are; since sibling ids are unique, the rest is the longest
+/// increasing run of where `b`'s items sit in `a`, found in O(n log n) so that large child
+/// lists stay cheap.
+fn common_subsequence(a: &[&String], b: &[&String]) -> HashSet<usize> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let mut kept: HashSet<usize> = (0..prefix).chain(a.len() - suffix..a.len()).collect();
+
+    let a_mid = &a[prefix..a.len() - suffix];
+    let b_mid = &b[prefix..b.len() - suffix];
+    let position: HashMap<&String, usize> =
+        a_mid.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+    let seq: Vec<usize> = b_mid
+        .iter()
+        .filter_map(|id| position.get(id).copied())
+        .collect();
+
+    // `tails[k]` ends the best run of length `k + 1` found so far; `prev` links each item
+    // to the one before it in its run.
+    let mut tails: Vec<usize> = Vec::new();
+    let mut prev: Vec<Option<usize>> = vec![None; seq.len()];
+    for (i, &p) in seq.iter().enumerate() {
+        let k = tails.partition_point(|&t| seq[t] < p);
+        if k > 0 {
+            prev[i] = Some(tails[k - 1]);
+        }
+        if k == tails.len() {
+            tails.push(i);
+        } else {
+            tails[k] = i;
+        }
+    }
+    let mut at = tails.last().copied();
+    while let Some(i) = at {
+        kept.insert(prefix + seq[i]);
+        at = prev[i];
+    }
+    kept
+}
+
+/// Ids of children that stayed under the node but left the order they had relative to each
+/// other.
+fn reordered_children(before: &Node, after: &Node) -> Vec<String> {
+    let old: HashSet<&String> = before.children_ids.iter().collect();
+    let new: HashSet<&String> = after.children_ids.iter().collect();
+    let a: Vec<&String> = before.children_ids.iter().filter(|id| new.contains(id)).collect();
+    let b: Vec<&String> = after.children_ids.iter().filter(|id| old.contains(id)).collect();
+    let kept = common_subsequence(&a, &b);
+    a.iter()
+        .enumerate()
+        .filter(|(index, _)| !kept.contains(index))
+        .map(|(_, id)| (*id).clone())
+        .collect()
+}
+
+/// Works on partial maps too, such as the two sides of a `Patch`: a reordered child that
+/// is missing from `after` is reported without its text.
+pub fn diff_nodes(before: &Nodes, after: &Nodes) -> TreeDiff {
+    let mut diff = TreeDiff::default();
+    let mut ids: Vec<&String> = before.keys().chain(after.keys()).collect();
+    ids.sort();
+    ids.dedup();
+
+    let mut reordered: HashSet<String> = HashSet::new();
+    for id in &ids {
+        if let (Some(old), Some(new)) = (before.get(*id), after.get(*id)) {
+            reordered.extend(reordered_children(old, new));
+        }
+    }
+
+    for id in ids {
+        match (before.get(id), after.get(id)) {
+            (None, Some(new)) => diff.added.push(diff_node(new)),
+            (Some(old), None) => diff.removed.push(diff_node(old)),
+            (Some(old), Some(new)) => {
+                if old.text != new.text {
+                    diff.retexted.push(RetextedNode {
+                        node_id: id.clone(),
+                        before: old.text.clone(),
+                        after: new.text.clone(),
+                    });
+                }
+                if old.parent_id != new.parent_id || reordered.remove(id) {
+                    diff.moved.push(MovedNode {
+                        node_id: id.clone(),
+                        text: new.text.clone(),
+                        from_parent_id: old.parent_id.clone(),
+                        to_parent_id: new.parent_id.clone(),
+                        from_index: index_in(before, old.parent_id.as_ref(), id),
+                        to_index: index_in(after, new.parent_id.as_ref(), id),
+                    });
+                }
+            }
+            (None, None) => {}
+        }
+    }
+
+    let mut rest: Vec<String> = reordered.into_iter().collect();
+    rest.sort();
+    for id in rest {
+        let parent_id = before
+            .values()
+            .find(|node| node.children_ids.contains(&id))
+            .map(|node| node.id.clone());
+        diff.moved.push(MovedNode {
+            text: String::new(),
+            from_parent_id: parent_id.clone(),
+            to_parent_id: parent_id.clone(),
+            from_index: index_in(before, parent_id.as_ref(), &id),
+            to_index: index_in(after, parent_id.as_ref(), &id),
+            node_id: id,
+        });
+    }
+    diff.moved.sort_by(|a, b| a.node_id.cmp(&b.node_id));
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::state;
+
+    #[test]
+    fn reports_moves_without_counting_siblings_that_only_shift() {
+        let before = state(&[
+            ("root", "Plan", &["a", "b", "c", "d"]),
+            ("a", "Budget", &[]),
+            ("b", "Schedule", &[]),
+            ("c", "Team", &[]),
+            ("d", "Risks", &[]),
+        ]);
+        let after = state(&[
+            ("root", "Plan", &["e", "c", "a", "b"]),
+            ("a", "Budget", &["d"]),
+            ("b", "Timeline", &[]),
+            ("c", "Team", &[]),
+            ("d", "Risks", &[]),
+            ("e", "Goals", &[]),
+        ]);
+        let diff = diff(&before, &after);
+
+        let added: Vec<&str> = diff.added.iter().map(|n| n.node_id.as_str()).collect();
+        assert_eq!(added, vec!["e"]);
+        assert!(diff.removed.is_empty());
+        let moved: Vec<(&str, Option<&str>, usize)> = diff
+            .moved
+            .iter()
+            .map(|m| (m.node_id.as_str(), m.to_parent_id.as_deref(), m.to_index))
+            .collect();
+        assert_eq!(moved, vec![("c", Some("root"), 1), ("d", Some("a"), 0)]);
+        assert_eq!(diff.retexted[0].after, "Timeline");
+        assert_eq!(diff.summary(), "1 added, 2 moved, 1 edited");
+    }
+
+    #[test]
+    fn common_subsequence_keeps_the_longest_order() {
+        let ids: Vec<String> = "abcdefg".chars().map(String::from).collect();
+        let order = |s: &str| -> Vec<&String> {
+            s.chars().map(|c| &ids[(c as u8 - b'a') as usize]).collect()
+        };
+        let kept = |a: &str, b: &str| -> Vec<usize> {
+            let mut kept: Vec<usize> = common_subsequence(&order(a), &order(b))
+                .into_iter()
+                .collect();
+            kept.sort();
+            kept
+        };
+        assert_eq!(kept("abcdefg", "abcdefg"), vec![0, 1, 2, 3, 4, 5, 6]);
+        assert_eq!(kept("abcdefg", "abdecfg"), vec![0, 1, 3, 4, 5, 6]);
+        assert_eq!(kept("abcdefg", "gabcdef"), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(kept("abcd", "dcba").len(), 1);
+        assert_eq!(kept("abcd", "badc").len(), 2);
+        assert!(kept("", "").is_empty());
+    }
+
+    #[test]
+    fn large_child_lists_report_only_the_moved_child() {
+        let ids: Vec<String> = (0..20_000).map(|i| format!("n{i}")).collect();
+        let mut moved: Vec<&str> = ids.iter().map(String::as_str).collect();
+        let child = moved.remove(10);
+        moved.insert(15_000, child);
+
+        let mut before_rows: Vec<(&str, &str, &[&str])> = vec![("root", "Root", &[])];
+        let mut after_rows = before_rows.clone();
+        let original: Vec<&str> = ids.iter().map(String::as_str).collect();
+        before_rows[0].2 = &original;
+        after_rows[0].2 = &moved;
+        for id in &ids {
+            before_rows.push((id, "", &[]));
+            after_rows.push((id, "", &[]));
+        }
+
+        let diff = diff(&state(&before_rows), &state(&after_rows));
+        let moved: Vec<(&str, usize, usize)> = diff
+            .moved
+            .iter()
+            .map(|m| (m.node_id.as_str(), m.from_index, m.to_index))
+            .collect();
+        assert_eq!(moved, vec![("n10", 10, 15_000)]);
+    }
+}
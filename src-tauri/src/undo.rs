@@ -0,0 +1,659 @@
+use std::collections::HashMap;
+
+use crate::model::{DocumentState, EditOp, HistoryEntry, Node};
+use crate::tree_diff;
+
+type Nodes = HashMap<String, Node>;
+
+const LABEL_MAX_CHARS: usize = 40;
+
+fn node_mut<'a>(nodes: &'a mut Nodes, node_id: &str) -> Result<&'a mut Node, String> {
+    nodes
+        .get_mut(node_id)
+        .ok_or_else(|| format!("node {node_id} does not exist"))
+}
+
+/// Applies one op. Every op checks that the tree is in the state it was recorded against
+/// and fails otherwise; `nodes` may be left half-updated on error, so callers apply to a
+/// copy (see `apply_entry`).
+pub fn apply_op(nodes: &mut Nodes, op: &EditOp) -> Result<(), String> {
+    match op {
+        EditOp::InsertNode {
+            node_id,
+            parent_id,
+            index,
+            text,
+            children_ids,
+        } => {
+            if nodes.contains_key(node_id) {
+                return Err(format!("node {node_id} already exists"));
+            }
+            let parent = node_mut(nodes, parent_id)?;
+            let end = index + children_ids.len();
+            if end > parent.children_ids.len() || parent.children_ids[*index..end] != children_ids[..]
+            {
+                return Err(format!("children of {parent_id} have changed"));
+            }
+            parent.children_ids.splice(*index..end, [node_id.clone()]);
+            for child_id in children_ids {
+                node_mut(nodes, child_id)?.parent_id = Some(node_id.clone());
+            }
+            nodes.insert(
+                node_id.clone(),
+                Node {
+                    id: node_id.clone(),
+                    text: text.clone(),
+                    parent_id: Some(parent_id.clone()),
+                    children_ids: children_ids.clone(),
+                },
+            );
+        }
+        EditOp::DeleteNode {
+            node_id,
+            parent_id,
+            index,
+            text,
+            children_ids,
+        } => {
+            let node = nodes
+                .get(node_id)
+                .ok_or_else(|| format!("node {node_id} does not exist"))?;
+            if node.parent_id.as_ref() != Some(parent_id)
+                || node.text != *text
+                || node.children_ids != *children_ids
+            {
+                return Err(format!("node {node_id} has changed"));
+            }
+            let parent = node_mut(nodes, parent_id)?;
+            if parent.children_ids.get(*index) != Some(node_id) {
+                return Err(format!("children of {parent_id} have changed"));
+            }
+            parent
+                .children_ids
+                .splice(*index..index + 1, children_ids.iter().cloned());
+            for child_id in children_ids {
+                node_mut(nodes, child_id)?.parent_id = Some(parent_id.clone());
+            }
+            nodes.remove(node_id);
+        }
+        EditOp::SwapSiblings {
+            parent_id,
+            index,
+            other_index,
+        } => {
+            let parent = node_mut(nodes, parent_id)?;
+            let len = parent.children_ids.len();
+            if *index >= len || *other_index >= len {
+                return Err(format!("children of {parent_id} have changed"));
+            }
+            parent.children_ids.swap(*index, *other_index);
+        }
+        EditOp::EditText {
+            node_id,
+            before,
+            after,
+        } => {
+            let node = node_mut(nodes, node_id)?;
+            if node.text != *before {
+                return Err(format!("text of {node_id} has changed"));
+            }
+            node.text.clone_from(after);
+        }
+        EditOp::Patch { removed, added } => {
+            for node in removed {
+                if nodes.get(&node.id) != Some(node) {
+                    return Err(format!("node {} has changed", node.id));
+                }
+            }
+            for node in removed {
+                nodes.remove(&node.id);
+            }
+            for node in added {
+                nodes.insert(node.id.clone(), node.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn invert_op(op: &EditOp) -> EditOp {
+    match op.clone() {
+        EditOp::InsertNode {
+            node_id,
+            parent_id,
+            index,
+            text,
+            children_ids,
+        } => EditOp::DeleteNode {
+            node_id,
+            parent_id,
+            index,
+            text,
+            children_ids,
+        },
+        EditOp::DeleteNode {
+            node_id,
+            parent_id,
+            index,
+            text,
+            children_ids,
+        } => EditOp::InsertNode {
+            node_id,
+            parent_id,
+            index,
+            text,
+            children_ids,
+        },
+        swap @ EditOp::SwapSiblings { .. } => swap,
+        EditOp::EditText {
+            node_id,
+            before,
+            after,
+        } => EditOp::EditText {
+            node_id,
+            before: after,
+            after: before,
+        },
+        EditOp::Patch { removed, added } => EditOp::Patch {
+            removed: added,
+            added: removed,
+        },
+    }
+}
+
+/// The entry that undoes `entry`.
+pub fn invert_entry(entry: &HistoryEntry) -> HistoryEntry {
+    HistoryEntry {
+        cursor_before: entry.cursor_after.clone(),
+        cursor_after: entry.cursor_before.clone(),
+        ops: entry.ops.iter().rev().map(invert_op).collect(),
+    }
+}
+
+/// Applies every op of `entry` and moves the cursor, or changes nothing if any op fails.
+pub fn apply_entry(
+    nodes: &mut Nodes,
+    cursor_id: &mut String,
+    entry: &HistoryEntry,
+) -> Result<(), String> {
+    let mut next = nodes.clone();
+    for op in &entry.ops {
+        apply_op(&mut next, op)?;
+    }
+    if !next.contains_key(&entry.cursor_after) {
+        return Err(format!("cursor node {} does not exist", entry.cursor_after));
+    }
+    *nodes = next;
+    cursor_id.clone_from(&entry.cursor_after);
+    Ok(())
+}
+
+/// Expresses the step from `before` to `after` as ops: named ops when they reproduce
+/// `after` exactly, a `Patch` otherwise.
+pub fn entry_between(before: &DocumentState, after: &DocumentState) -> HistoryEntry {
+    let ops = named_ops(&before.nodes, &after.nodes);
+    let mut replayed = before.nodes.clone();
+    let reproduces = ops.iter().all(|op| apply_op(&mut replayed, op).is_ok())
+        && replayed == after.nodes;
+    HistoryEntry {
+        cursor_before: before.cursor_id.clone(),
+        cursor_after: after.cursor_id.clone(),
+        ops: if reproduces {
+            ops
+        } else {
+            vec![patch_between(&before.nodes, &after.nodes)]
+        },
+    }
+}
+
+fn sorted_ids<'a>(ids: impl Iterator<Item = &'a String>) -> Vec<&'a String> {
+    let mut ids: Vec<&String> = ids.collect();
+    ids.sort();
+    ids
+}
+
+fn named_ops(before: &Nodes, after: &Nodes) -> Vec<EditOp> {
+    let mut ops = Vec::new();
+
+    for id in sorted_ids(before.keys().filter(|id| after.contains_key(*id))) {
+        if before[id].text != after[id].text {
+            ops.push(EditOp::EditText {
+                node_id: id.clone(),
+                before: before[id].text.clone(),
+                after: after[id].text.clone(),
+            });
+        }
+    }
+
+    for id in sorted_ids(before.keys().filter(|id| !after.contains_key(*id))) {
+        let node = &before[id];
+        let Some(parent_id) = &node.parent_id else {
+            continue;
+        };
+        let Some(index) = before
+            .get(parent_id)
+            .and_then(|parent| parent.children_ids.iter().position(|c| c == id))
+        else {
+            continue;
+        };
+        ops.push(EditOp::DeleteNode {
+            node_id: id.clone(),
+            parent_id: parent_id.clone(),
+            index,
+            text: node.text.clone(),
+            children_ids: node.children_ids.clone(),
+        });
+    }
+
+    for id in sorted_ids(before.keys().filter(|id| after.contains_key(*id))) {
+        let (old, new) = (&before[id].children_ids, &after[id].children_ids);
+        if old.len() != new.len() {
+            continue;
+        }
+        let moved: Vec<usize> = (0..old.len()).filter(|&i| old[i] != new[i]).collect();
+        if let [index, other_index] = moved[..] {
+            if old[index] == new[other_index] && old[other_index] == new[index] {
+                ops.push(EditOp::SwapSiblings {
+                    parent_id: id.clone(),
+                    index,
+                    other_index,
+                });
+            }
+        }
+    }
+
+    for id in sorted_ids(after.keys().filter(|id| !before.contains_key(*id))) {
+        let node = &after[id];
+        let Some(parent_id) = &node.parent_id else {
+            continue;
+        };
+        let Some(index) = after
+            .get(parent_id)
+            .and_then(|parent| parent.children_ids.iter().position(|c| c == id))
+        else {
+            continue;
+        };
+        ops.push(EditOp::InsertNode {
+            node_id: id.clone(),
+            parent_id: parent_id.clone(),
+            index,
+            text: node.text.clone(),
+            children_ids: node.children_ids.clone(),
+        });
+    }
+
+    ops
+}
+
+fn patch_between(before: &Nodes, after: &Nodes) -> EditOp {
+    let changed = |from: &Nodes, to: &Nodes| -> Vec<Node> {
+        sorted_ids(from.keys())
+            .into_iter()
+            .filter(|id| to.get(*id) != from.get(*id))
+            .map(|id| from[id].clone())
+            .collect()
+    };
+    EditOp::Patch {
+        removed: changed(before, after),
+        added: changed(after, before),
+    }
+}
+
+fn label(text: &str) -> String {
+    let text = text.trim();
+    if text.is_empty() {
+        return "an empty node".to_string();
+    }
+    if text.chars().count() > LABEL_MAX_CHARS {
+        let cut: String = text.chars().take(LABEL_MAX_CHARS).collect();
+        return format!("'{cut}…'");
+    }
+    format!("'{text}'")
+}
+
+fn plural(count: usize, one: &str, many: &str) -> String {
+    match count {
+        1 => format!("1 {one}"),
+        _ => format!("{count} {many}"),
+    }
+}
+
+fn describe_op(op: &EditOp, nodes: &Nodes) -> String {
+    match op {
+        EditOp::InsertNode {
+            text, children_ids, ..
+        } => match children_ids.len() {
+            0 => format!("Added {}", label(text)),
+            n => format!(
+                "Added {} above {}",
+                label(text),
+                plural(n, "node", "nodes")
+            ),
+        },
+        EditOp::DeleteNode {
+            text, children_ids, ..
+        } => match children_ids.len() {
+            0 => format!("Deleted {}", label(text)),
+            n => format!(
+                "Deleted {} and promoted {}",
+                label(text),
+                plural(n, "child", "children")
+            ),
+        },
+        EditOp::SwapSiblings { parent_id, .. } => {
+            let parent = nodes.get(parent_id).map_or("", |node| node.text.as_str());
+            format!("Reordered the children of {}", label(parent))
+        }
+        EditOp::EditText { before, after, .. } => {
+            if before.trim().is_empty() {
+                format!("Typed {}", label(after))
+            } else if after.trim().is_empty() {
+                format!("Cleared {}", label(before))
+            } else {
+                format!("Renamed {} to {}", label(before), label(after))
+            }
+        }
+        EditOp::Patch { removed, added } => {
+            let by_id = |nodes: &[Node]| -> Nodes {
+                nodes.iter().map(|n| (n.id.clone(), n.clone())).collect()
+            };
+            let diff = tree_diff::diff_nodes(&by_id(removed), &by_id(added));
+            if !diff.is_empty() {
+                return format!("Changed the tree: {}", diff.summary());
+            }
+            let mut ids: Vec<&String> = removed.iter().chain(added).map(|n| &n.id).collect();
+            ids.sort();
+            ids.dedup();
+            format!("Changed {}", plural(ids.len(), "node", "nodes"))
+        }
+    }
+}
+
+/// A one-line summary such as "Deleted 'Budget' and promoted 3 children". `nodes` is used
+/// only to name nodes the entry itself does not carry text for.
+pub fn describe_entry(entry: &HistoryEntry, nodes: &Nodes) -> String {
+    if entry.ops.is_empty() {
+        return "Moved the cursor".to_string();
+    }
+    let parts: Vec<String> = entry.ops.iter().map(|op| describe_op(op, nodes)).collect();
+    parts.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    type Tree<'a> = &'a [(&'a str, &'a str, &'a [&'a str])];
+    /// A tree and a check on the single op expected to reach it.
+    type Case<'a> = (Tree<'a>, fn(&EditOp) -> bool);
+
+    fn state(tree: Tree) -> DocumentState {
+        test_support::state(tree)
+    }
+
+    fn insert(node_id: &str, parent_id: &str, index: usize, children: &[&str]) -> EditOp {
+        EditOp::InsertNode {
+            node_id: node_id.to_string(),
+            parent_id: parent_id.to_string(),
+            index,
+            text: node_id.to_uppercase(),
+            children_ids: children.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    fn fails(tree: Tree, op: EditOp) -> String {
+        apply_op(&mut test_support::nodes(tree), &op).unwrap_err()
+    }
+
+    const TREE: Tree = &[
+        ("root", "Root", &["a", "b"]),
+        ("a", "A", &["c"]),
+        ("b", "B", &[]),
+        ("c", "C", &[]),
+    ];
+
+    #[test]
+    fn ops_refuse_a_tree_that_has_moved_on() {
+        assert_eq!(
+            fails(TREE, insert("a", "root", 0, &[])),
+            "node a already exists"
+        );
+        assert_eq!(
+            fails(TREE, insert("x", "zz", 0, &[])),
+            "node zz does not exist"
+        );
+        assert_eq!(
+            fails(TREE, insert("x", "root", 0, &["b"])),
+            "children of root have changed"
+        );
+        assert_eq!(
+            fails(TREE, insert("x", "root", 2, &["b"])),
+            "children of root have changed"
+        );
+        assert_eq!(
+            fails(TREE, invert_op(&insert("b", "root", 1, &["x"]))),
+            "node b has changed"
+        );
+        assert_eq!(
+            fails(TREE, invert_op(&insert("b", "root", 0, &[]))),
+            "children of root have changed"
+        );
+        assert_eq!(
+            fails(
+                TREE,
+                EditOp::SwapSiblings {
+                    parent_id: "root".to_string(),
+                    index: 0,
+                    other_index: 2,
+                }
+            ),
+            "children of root have changed"
+        );
+        assert_eq!(
+            fails(
+                TREE,
+                EditOp::EditText {
+                    node_id: "a".to_string(),
+                    before: "Old".to_string(),
+                    after: "New".to_string(),
+                }
+            ),
+            "text of a has changed"
+        );
+        let mut stale_b = test_support::nodes(TREE)["b"].clone();
+        stale_b.text = "Stale".to_string();
+        assert_eq!(
+            fails(
+                TREE,
+                EditOp::Patch {
+                    removed: vec![stale_b],
+                    added: Vec::new(),
+                }
+            ),
+            "node b has changed"
+        );
+    }
+
+    #[test]
+    fn failed_entry_changes_nothing() {
+        let mut nodes = test_support::nodes(TREE);
+        let mut cursor_id = "a".to_string();
+        let entry = HistoryEntry {
+            cursor_before: "a".to_string(),
+            cursor_after: "x".to_string(),
+            ops: vec![insert("x", "root", 2, &[]), insert("a", "root", 0, &[])],
+        };
+        assert!(apply_entry(&mut nodes, &mut cursor_id, &entry).is_err());
+        assert_eq!(nodes, test_support::nodes(TREE));
+        assert_eq!(cursor_id, "a");
+
+        let entry = HistoryEntry {
+            cursor_before: "a".to_string(),
+            cursor_after: "gone".to_string(),
+            ops: vec![insert("x", "root", 2, &[])],
+        };
+        assert_eq!(
+            apply_entry(&mut nodes, &mut cursor_id, &entry).unwrap_err(),
+            "cursor node gone does not exist"
+        );
+        assert_eq!(nodes, test_support::nodes(TREE));
+    }
+
+    /// `entry_between` reproduces `after`, and its inverse leads back to `before`.
+    fn round_trip(before: &DocumentState, after: &DocumentState) -> HistoryEntry {
+        let entry = entry_between(before, after);
+        let mut nodes = before.nodes.clone();
+        let mut cursor_id = before.cursor_id.clone();
+        apply_entry(&mut nodes, &mut cursor_id, &entry).unwrap();
+        assert_eq!(nodes, after.nodes);
+        assert_eq!(cursor_id, after.cursor_id);
+
+        apply_entry(&mut nodes, &mut cursor_id, &invert_entry(&entry)).unwrap();
+        assert_eq!(nodes, before.nodes);
+        assert_eq!(cursor_id, before.cursor_id);
+        entry
+    }
+
+    #[test]
+    fn named_ops_round_trip() {
+        let before = state(TREE);
+        let cases: [Case; 5] = [
+            (
+                &[
+                    ("root", "Root", &["a", "b", "d"]),
+                    ("a", "A", &["c"]),
+                    ("b", "B", &[]),
+                    ("c", "C", &[]),
+                    ("d", "D", &[]),
+                ],
+                |op| matches!(op, EditOp::InsertNode { .. }),
+            ),
+            (
+                &[
+                    ("root", "Root", &["w", "b"]),
+                    ("w", "W", &["a"]),
+                    ("a", "A", &["c"]),
+                    ("b", "B", &[]),
+                    ("c", "C", &[]),
+                ],
+                |op| matches!(op, EditOp::InsertNode { children_ids, .. } if children_ids == &["a"]),
+            ),
+            (
+                &[
+                    ("root", "Root", &["c", "b"]),
+                    ("b", "B", &[]),
+                    ("c", "C", &[]),
+                ],
+                |op| matches!(op, EditOp::DeleteNode { children_ids, .. } if children_ids == &["c"]),
+            ),
+            (
+                &[
+                    ("root", "Root", &["b", "a"]),
+                    ("a", "A", &["c"]),
+                    ("b", "B", &[]),
+                    ("c", "C", &[]),
+                ],
+                |op| matches!(op, EditOp::SwapSiblings { .. }),
+            ),
+            (
+                &[
+                    ("root", "Root", &["a", "b"]),
+                    ("a", "Renamed", &["c"]),
+                    ("b", "B", &[]),
+                    ("c", "C", &[]),
+                ],
+                |op| matches!(op, EditOp::EditText { .. }),
+            ),
+        ];
+        for (tree, expected) in cases {
+            let mut after = state(tree);
+            after.cursor_id = "b".to_string();
+            let entry = round_trip(&before, &after);
+            assert_eq!(entry.ops.len(), 1, "{:?}", entry.ops);
+            assert!(expected(&entry.ops[0]), "{:?}", entry.ops);
+        }
+    }
+
+    #[test]
+    fn other_changes_fall_back_to_a_patch() {
+        let before = state(TREE);
+        // Moving `c` under `b` is not one of the named ops.
+        let after = state(&[
+            ("root", "Root", &["a", "b"]),
+            ("a", "A", &[]),
+            ("b", "B", &["c"]),
+            ("c", "C", &[]),
+        ]);
+        let entry = round_trip(&before, &after);
+        let [EditOp::Patch { removed, added }] = &entry.ops[..] else {
+            panic!("expected a patch: {:?}", entry.ops);
+        };
+        let ids = |nodes: &[Node]| nodes.iter().map(|n| n.id.clone()).collect::<Vec<_>>();
+        assert_eq!(ids(removed), vec!["a", "b", "c"]);
+        assert_eq!(ids(added), vec!["a", "b", "c"]);
+
+        let unchanged = entry_between(&before, &before);
+        assert!(unchanged.ops.is_empty());
+    }
+
+    #[test]
+    fn entries_are_described_in_one_line() {
+        let nodes = test_support::nodes(TREE);
+        let describe = |ops: Vec<EditOp>| {
+            describe_entry(
+                &HistoryEntry {
+                    cursor_before: "root".to_string(),
+                    cursor_after: "root".to_string(),
+                    ops,
+                },
+                &nodes,
+            )
+        };
+        let edit = |before: &str, after: &str| EditOp::EditText {
+            node_id: "a".to_string(),
+            before: before.to_string(),
+            after: after.to_string(),
+        };
+
+        assert_eq!(describe(Vec::new()), "Moved the cursor");
+        assert_eq!(describe(vec![insert("x", "root", 0, &[])]), "Added 'X'");
+        assert_eq!(
+            describe(vec![insert("x", "root", 0, &["a", "b"])]),
+            "Added 'X' above 2 nodes"
+        );
+        assert_eq!(
+            describe(vec![invert_op(&insert("x", "root", 0, &["a"]))]),
+            "Deleted 'X' and promoted 1 child"
+        );
+        assert_eq!(
+            describe(vec![EditOp::SwapSiblings {
+                parent_id: "root".to_string(),
+                index: 0,
+                other_index: 1,
+            }]),
+            "Reordered the children of 'Root'"
+        );
+        assert_eq!(describe(vec![edit(" ", "Plan")]), "Typed 'Plan'");
+        assert_eq!(describe(vec![edit("Plan", "")]), "Cleared 'Plan'");
+        assert_eq!(
+            describe(vec![edit("Plan", "Budget"), insert("x", "a", 0, &[])]),
+            "Renamed 'Plan' to 'Budget', Added 'X'"
+        );
+        let long = "x".repeat(LABEL_MAX_CHARS + 5);
+        assert_eq!(
+            describe(vec![edit("", &long)]),
+            format!("Typed '{}…'", "x".repeat(LABEL_MAX_CHARS))
+        );
+        assert_eq!(describe(vec![edit("", "")]), "Typed an empty node");
+
+        let same = nodes["b"].clone();
+        assert_eq!(
+            describe(vec![EditOp::Patch {
+                removed: vec![same.clone()],
+                added: vec![same],
+            }]),
+            "Changed 1 node"
+        );
+    }
+}
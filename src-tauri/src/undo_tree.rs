@@ -0,0 +1,722 @@
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use crate::history::HistoryLimits;
+use crate::model::{Document, HistoryEntry, UndoNode, UndoTree};
+use crate::undo;
+
+/// A leaf of the undo tree and where it meets the path to the current state.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UndoBranch {
+    pub tip_id: String,
+    pub fork_id: String,
+    pub created_at_millis: u64,
+    /// Undo steps from the current state back to the fork.
+    pub steps_back: usize,
+    /// Steps from the fork out to the tip.
+    pub steps_forward: usize,
+    pub description: String,
+    /// The tip can be reached with redo alone.
+    pub current: bool,
+}
+
+/// Node ids from `id` up to the root, `id` first. Stops early at a missing parent or a
+/// cycle, so callers check where the walk ended.
+pub fn ancestors(tree: &UndoTree, id: &str) -> Vec<String> {
+    let mut path = Vec::new();
+    let mut seen = HashSet::new();
+    let mut next = tree.nodes.get(id);
+    while let Some(node) = next {
+        if !seen.insert(node.id.as_str()) {
+            break;
+        }
+        path.push(node.id.clone());
+        next = node.parent_id.as_ref().and_then(|parent| tree.nodes.get(parent));
+    }
+    path
+}
+
+fn children(tree: &UndoTree) -> HashMap<&str, Vec<&UndoNode>> {
+    let mut children: HashMap<&str, Vec<&UndoNode>> = HashMap::new();
+    for node in tree.nodes.values() {
+        if let Some(parent_id) = &node.parent_id {
+            children.entry(parent_id.as_str()).or_default().push(node);
+        }
+    }
+    children
+}
+
+/// `base`, or `base-<n>` with the first `n` not taken yet: two steps recorded in the same
+/// millisecond must still get their own nodes.
+fn fresh_id(tree: &UndoTree, base: String) -> String {
+    if !tree.nodes.contains_key(&base) {
+        return base;
+    }
+    let mut n = 1;
+    loop {
+        let id = format!("{base}-{n}");
+        if !tree.nodes.contains_key(&id) {
+            return id;
+        }
+        n += 1;
+    }
+}
+
+fn entries<'a>(tree: &'a UndoTree, ids: &[String]) -> Vec<&'a HistoryEntry> {
+    ids.iter()
+        .filter_map(|id| tree.nodes.get(id).and_then(|node| node.entry.as_ref()))
+        .collect()
+}
+
+/// The redo line from `id`: the newest child at every step, top of the stack last.
+fn newest_line(tree: &UndoTree, id: &str) -> Vec<HistoryEntry> {
+    let children = children(tree);
+    let mut line = Vec::new();
+    let mut at = id;
+    while let Some(newest) = children.get(at).and_then(|nodes| {
+        nodes
+            .iter()
+            .max_by(|a, b| (a.created_at_millis, &a.id).cmp(&(b.created_at_millis, &b.id)))
+    }) {
+        line.extend(newest.entry.clone());
+        at = &newest.id;
+    }
+    line.reverse();
+    line
+}
+
+/// The node the redo stack leads to: the children of the current node followed one redo
+/// entry at a time, top of the stack first. `None` when there is nothing to redo.
+pub fn redo_tip(tree: &UndoTree, redo_stack: &[HistoryEntry]) -> Option<String> {
+    let children = children(tree);
+    let mut tip = None;
+    let mut at = tree.current_id.as_str();
+    for entry in redo_stack.iter().rev() {
+        let Some(next) = children
+            .get(at)
+            .and_then(|nodes| nodes.iter().find(|node| node.entry.as_ref() == Some(entry)))
+        else {
+            break;
+        };
+        tip = Some(next.id.clone());
+        at = &next.id;
+    }
+    tip
+}
+
+/// The undo and redo stacks the tree stands for: the path from the root to the current
+/// node, and the line from there out to `redo_tip_id`.
+pub fn stacks(
+    tree: &UndoTree,
+    redo_tip_id: Option<&str>,
+) -> (Vec<HistoryEntry>, Vec<HistoryEntry>) {
+    let mut path = ancestors(tree, &tree.current_id);
+    path.reverse();
+    let undo = entries(tree, &path).into_iter().cloned().collect();
+    let line = redo_tip_id.map_or_else(Vec::new, |tip| ancestors(tree, tip));
+    let redo = match line.iter().position(|id| *id == tree.current_id) {
+        Some(current) => entries(tree, &line[..current])
+            .into_iter()
+            .cloned()
+            .collect(),
+        None => Vec::new(),
+    };
+    (undo, redo)
+}
+
+pub fn branches(doc: &Document) -> Vec<UndoBranch> {
+    let tree = &doc.undo_tree;
+    let current_path = ancestors(tree, &tree.current_id);
+    let children = children(tree);
+
+    let mut branches: Vec<UndoBranch> = tree
+        .nodes
+        .values()
+        .filter(|node| node.entry.is_some() && !children.contains_key(node.id.as_str()))
+        .filter_map(|tip| {
+            let tip_path = ancestors(tree, &tip.id);
+            let steps_forward = tip_path.iter().position(|id| current_path.contains(id))?;
+            let fork_id = &tip_path[steps_forward];
+            let steps_back = current_path.iter().position(|id| id == fork_id)?;
+            Some(UndoBranch {
+                tip_id: tip.id.clone(),
+                fork_id: fork_id.clone(),
+                created_at_millis: tip.created_at_millis,
+                steps_back,
+                steps_forward,
+                description: tip
+                    .entry
+                    .as_ref()
+                    .map(|entry| undo::describe_entry(entry, &doc.nodes))
+                    .unwrap_or_default(),
+                current: steps_back == 0,
+            })
+        })
+        .collect();
+    branches.sort_by(|a, b| {
+        (b.created_at_millis, &b.tip_id).cmp(&(a.created_at_millis, &a.tip_id))
+    });
+    branches
+}
+
+/// Moves the document to the state recorded at `target_id`: undoes back to where the two
+/// paths meet and replays forward from there. The stacks become the tree's path to the
+/// target and its newest line onwards. Nothing changes if any step fails to apply.
+pub fn jump(doc: &mut Document, target_id: &str) -> Result<(), String> {
+    let tree = &doc.undo_tree;
+    if !tree.nodes.contains_key(target_id) {
+        return Err(format!("history state {target_id} does not exist"));
+    }
+    let up = ancestors(tree, &tree.current_id);
+    let down = ancestors(tree, target_id);
+    let fork = down
+        .iter()
+        .position(|id| up.contains(id))
+        .ok_or("history state is not connected to the current one")?;
+    let back = up.iter().position(|id| *id == down[fork]).unwrap_or(0);
+
+    let mut nodes = doc.nodes.clone();
+    let mut cursor_id = doc.cursor_id.clone();
+    for entry in entries(tree, &up[..back]) {
+        undo::apply_entry(&mut nodes, &mut cursor_id, &undo::invert_entry(entry))?;
+    }
+    let mut forward: Vec<String> = down[..fork].to_vec();
+    forward.reverse();
+    for entry in entries(tree, &forward) {
+        undo::apply_entry(&mut nodes, &mut cursor_id, entry)?;
+    }
+
+    let redo_stack = newest_line(tree, target_id);
+
+    doc.nodes = nodes;
+    doc.cursor_id = cursor_id;
+    doc.undo_tree.current_id = target_id.to_string();
+    doc.undo_stack = stacks(&doc.undo_tree, None).0;
+    doc.redo_stack = redo_stack;
+    Ok(())
+}
+
+/// Brings back a branch a later edit took off the redo stack: goes to the state where it
+/// forked from the current path and makes the branch the redo stack, so redo walks it.
+pub fn restore_branch(doc: &mut Document, tip_id: &str) -> Result<(), String> {
+    let tree = &doc.undo_tree;
+    if !tree.nodes.contains_key(tip_id) {
+        return Err(format!("history state {tip_id} does not exist"));
+    }
+    let up = ancestors(tree, &tree.current_id);
+    let tip_path = ancestors(tree, tip_id);
+    let fork = tip_path
+        .iter()
+        .position(|id| up.contains(id))
+        .ok_or("branch is not connected to the current state")?;
+    let redo_stack: Vec<HistoryEntry> = entries(tree, &tip_path[..fork])
+        .into_iter()
+        .cloned()
+        .collect();
+    let fork_id = tip_path[fork].clone();
+
+    jump(doc, &fork_id)?;
+    doc.redo_stack = redo_stack;
+    Ok(())
+}
+
+/// Records `entry` as a new step from the current state, the way the editor records its
+/// own edits: a child with the same step is reused, and the redo stack is cleared.
+pub fn push(doc: &mut Document, entry: HistoryEntry, now_millis: u64) {
+    let tree = &mut doc.undo_tree;
+    if !tree.nodes.contains_key(&tree.current_id) {
+        let root_id = fresh_id(tree, format!("{}-{now_millis}-root", doc.id));
+        tree.nodes.insert(
+            root_id.clone(),
+            UndoNode {
+                id: root_id.clone(),
+                parent_id: None,
+                entry: None,
+                created_at_millis: now_millis,
+            },
+        );
+        tree.current_id = root_id;
+    }
+    let existing = tree
+        .nodes
+        .values()
+        .find(|node| {
+            node.parent_id.as_ref() == Some(&tree.current_id) && node.entry.as_ref() == Some(&entry)
+        })
+        .map(|node| node.id.clone());
+    let id = existing.unwrap_or_else(|| {
+        let id = fresh_id(tree, format!("{}-{now_millis}", doc.id));
+        tree.nodes.insert(
+            id.clone(),
+            UndoNode {
+                id: id.clone(),
+                parent_id: Some(tree.current_id.clone()),
+                entry: Some(entry.clone()),
+                created_at_millis: now_millis,
+            },
+        );
+        id
+    });
+    tree.current_id = id;
+    doc.undo_stack.push(entry);
+    doc.redo_stack.clear();
+}
+
+/// Gives a document with history but no tree (saved before the tree existed, or after the
+/// tree was reset) one: a single line through the undo stack, with the redo stack ahead.
+pub fn seed(doc: &mut Document) {
+    if !doc.undo_tree.nodes.is_empty()
+        || (doc.undo_stack.is_empty() && doc.redo_stack.is_empty())
+    {
+        return;
+    }
+    let mut tree = UndoTree::default();
+    let mut parent_id: Option<String> = None;
+    let undo = doc.undo_stack.iter().map(Some);
+    let redo = doc.redo_stack.iter().rev().map(Some);
+    for (index, entry) in std::iter::once(None).chain(undo).chain(redo).enumerate() {
+        let id = format!("{}-seed-{index}", doc.id);
+        if index == doc.undo_stack.len() {
+            tree.current_id.clone_from(&id);
+        }
+        tree.nodes.insert(
+            id.clone(),
+            UndoNode {
+                id: id.clone(),
+                parent_id: parent_id.replace(id),
+                entry: entry.cloned(),
+                created_at_millis: 0,
+            },
+        );
+    }
+    doc.undo_tree = tree;
+}
+
+/// Checks that the path from the current node back to the root still undoes cleanly from
+/// the document as it is.
+pub fn check(doc: &Document) -> Result<(), String> {
+    let tree = &doc.undo_tree;
+    if tree.nodes.is_empty() {
+        return Ok(());
+    }
+    let path = ancestors(tree, &tree.current_id);
+    let Some(root) = path.last().and_then(|id| tree.nodes.get(id)) else {
+        return Err(format!("current node {} does not exist", tree.current_id));
+    };
+    if root.parent_id.is_some() {
+        return Err(format!("node {} is cut off from the root", root.id));
+    }
+    let mut nodes = doc.nodes.clone();
+    let mut cursor_id = doc.cursor_id.clone();
+    for entry in entries(tree, &path) {
+        undo::apply_entry(&mut nodes, &mut cursor_id, &undo::invert_entry(entry))?;
+    }
+    Ok(())
+}
+
+/// Drops nodes that no longer hang off the current node's root, e.g. ones recorded under a
+/// node that was pruned in the meantime. Returns how many were dropped.
+pub fn drop_unreachable(tree: &mut UndoTree) -> usize {
+    let Some(root_id) = ancestors(tree, &tree.current_id).pop() else {
+        let dropped = tree.nodes.len();
+        tree.nodes.clear();
+        return dropped;
+    };
+    let children = children(tree);
+    let mut reachable: HashSet<String> = HashSet::new();
+    let mut stack = vec![root_id.as_str()];
+    while let Some(id) = stack.pop() {
+        if !reachable.insert(id.to_string()) {
+            continue;
+        }
+        if let Some(nodes) = children.get(id) {
+            stack.extend(nodes.iter().map(|node| node.id.as_str()));
+        }
+    }
+    let before = tree.nodes.len();
+    tree.nodes.retain(|id, _| reachable.contains(id));
+    before - tree.nodes.len()
+}
+
+fn node_len(node: &UndoNode) -> usize {
+    serde_json::to_vec(node).map_or(0, |bytes| bytes.len())
+}
+
+/// Makes `root_id` the root: it loses its parent and entry, and every node outside its
+/// subtree is removed. Returns the removed ids.
+fn reroot(tree: &mut UndoTree, root_id: &str) -> Vec<String> {
+    let children = children(tree);
+    let mut keep: HashSet<String> = HashSet::new();
+    let mut stack = vec![root_id];
+    while let Some(id) = stack.pop() {
+        if !keep.insert(id.to_string()) {
+            continue;
+        }
+        if let Some(nodes) = children.get(id) {
+            stack.extend(nodes.iter().map(|node| node.id.as_str()));
+        }
+    }
+    let mut removed: Vec<String> = tree
+        .nodes
+        .keys()
+        .filter(|id| !keep.contains(*id))
+        .cloned()
+        .collect();
+    removed.sort();
+    for id in &removed {
+        tree.nodes.remove(id);
+    }
+    if let Some(root) = tree.nodes.get_mut(root_id) {
+        root.parent_id = None;
+        root.entry = None;
+    }
+    removed
+}
+
+/// Shrinks the tree to `limits`. States more than `max_depth` steps behind the current one
+/// go first, and the redo line is cut back to `max_depth` steps by moving `redo_tip` up.
+/// Then the oldest leaves are removed, never touching the path to the current state or the
+/// redo line; if that is still too much, the oldest states on the path are given up and the
+/// tree is re-rooted closer to the current state. Returns the removed ids.
+pub fn prune(
+    tree: &mut UndoTree,
+    redo_tip: &mut Option<String>,
+    limits: HistoryLimits,
+) -> Vec<String> {
+    let mut removed = Vec::new();
+    let path = ancestors(tree, &tree.current_id);
+    if path.len() > limits.max_depth + 1 {
+        removed.extend(reroot(tree, &path[limits.max_depth]));
+    }
+
+    // The redo line runs from the tip up to the current node.
+    let line = redo_tip.as_deref().map_or_else(Vec::new, |tip| ancestors(tree, tip));
+    *redo_tip = match line.iter().position(|id| *id == tree.current_id) {
+        Some(steps) if steps > 0 && limits.max_depth > 0 => {
+            Some(line[steps.saturating_sub(limits.max_depth)].clone())
+        }
+        _ => None,
+    };
+
+    let mut sizes: HashMap<String, usize> = tree
+        .nodes
+        .values()
+        .map(|node| (node.id.clone(), node_len(node)))
+        .collect();
+    let mut bytes: usize = sizes.values().sum();
+    let over = |len: usize, bytes: usize| len > limits.max_tree_nodes || bytes > limits.max_bytes;
+    if !over(tree.nodes.len(), bytes) {
+        return removed;
+    }
+
+    let mut protected: HashSet<String> = ancestors(tree, &tree.current_id).into_iter().collect();
+    if let Some(tip) = redo_tip.as_deref() {
+        protected.extend(ancestors(tree, tip));
+    }
+    let children = children(tree);
+
+    let mut child_counts: HashMap<String, usize> = children
+        .iter()
+        .map(|(id, nodes)| (id.to_string(), nodes.len()))
+        .collect();
+    let mut leaves: BTreeSet<(u64, String)> = tree
+        .nodes
+        .values()
+        .filter(|node| !child_counts.contains_key(&node.id) && !protected.contains(&node.id))
+        .map(|node| (node.created_at_millis, node.id.clone()))
+        .collect();
+
+    while over(tree.nodes.len(), bytes) {
+        let Some((_, id)) = leaves.pop_first() else {
+            break;
+        };
+        let Some(node) = tree.nodes.remove(&id) else {
+            continue;
+        };
+        bytes -= sizes.remove(&id).unwrap_or(0);
+        removed.push(id);
+        let Some(parent_id) = node.parent_id else {
+            continue;
+        };
+        let count = child_counts.entry(parent_id.clone()).or_default();
+        *count = count.saturating_sub(1);
+        if *count == 0 && !protected.contains(&parent_id) {
+            if let Some(parent) = tree.nodes.get(&parent_id) {
+                leaves.insert((parent.created_at_millis, parent_id));
+            }
+        }
+    }
+
+    // Only the current path and the redo line are left.
+    let path = ancestors(tree, &tree.current_id);
+    let mut root = path.len().saturating_sub(1);
+    while root > 0 && over(tree.nodes.len(), bytes) {
+        tree.nodes.remove(&path[root]);
+        bytes -= sizes.remove(&path[root]).unwrap_or(0);
+        removed.push(path[root].clone());
+        root -= 1;
+        if let Some(node) = tree.nodes.get_mut(&path[root]) {
+            node.parent_id = None;
+            node.entry = None;
+            let len = node_len(node);
+            bytes = bytes + len - sizes.insert(path[root].clone(), len).unwrap_or(0);
+        }
+    }
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{DocumentState, Node};
+
+    fn state(children: &[&str]) -> DocumentState {
+        let mut nodes: HashMap<String, Node> = children
+            .iter()
+            .map(|id| {
+                let node = Node {
+                    id: id.to_string(),
+                    text: id.to_uppercase(),
+                    parent_id: Some("r".to_string()),
+                    children_ids: Vec::new(),
+                };
+                (id.to_string(), node)
+            })
+            .collect();
+        nodes.insert(
+            "r".to_string(),
+            Node {
+                id: "r".to_string(),
+                text: "Root".to_string(),
+                parent_id: None,
+                children_ids: children.iter().map(|id| id.to_string()).collect(),
+            },
+        );
+        DocumentState {
+            root_id: "r".to_string(),
+            cursor_id: children.last().unwrap_or(&"r").to_string(),
+            nodes,
+        }
+    }
+
+    fn node(id: &str, parent_id: Option<&str>, entry: Option<HistoryEntry>, at: u64) -> UndoNode {
+        UndoNode {
+            id: id.to_string(),
+            parent_id: parent_id.map(str::to_string),
+            entry,
+            created_at_millis: at,
+        }
+    }
+
+    /// Adds A, then B; undoes B and adds C instead, which takes B off the redo stack.
+    fn edited_after_undo() -> Document {
+        let add_a = undo::entry_between(&state(&[]), &state(&["a"]));
+        let add_b = undo::entry_between(&state(&["a"]), &state(&["a", "b"]));
+        let add_c = undo::entry_between(&state(&["a"]), &state(&["a", "c"]));
+        let now = state(&["a", "c"]);
+        Document {
+            id: "doc".to_string(),
+            root_id: now.root_id,
+            cursor_id: now.cursor_id,
+            nodes: now.nodes,
+            undo_stack: vec![add_a.clone(), add_c.clone()],
+            redo_stack: Vec::new(),
+            undo_tree: UndoTree {
+                current_id: "t3".to_string(),
+                nodes: [
+                    node("t0", None, None, 0),
+                    node("t1", Some("t0"), Some(add_a), 1),
+                    node("t2", Some("t1"), Some(add_b), 2),
+                    node("t3", Some("t1"), Some(add_c), 3),
+                ]
+                .into_iter()
+                .map(|node| (node.id.clone(), node))
+                .collect(),
+            },
+        }
+    }
+
+    fn children_of_root(doc: &Document) -> Vec<String> {
+        doc.nodes["r"].children_ids.clone()
+    }
+
+    #[test]
+    fn discarded_branch_is_listed_and_restored() {
+        let mut doc = edited_after_undo();
+        assert!(check(&doc).is_ok());
+
+        let branches = branches(&doc);
+        assert_eq!(branches.len(), 2);
+        assert_eq!(
+            (branches[0].tip_id.as_str(), branches[0].current),
+            ("t3", true)
+        );
+        assert_eq!(branches[1].tip_id, "t2");
+        assert_eq!(branches[1].fork_id, "t1");
+        assert_eq!((branches[1].steps_back, branches[1].steps_forward), (1, 1));
+        assert_eq!(branches[1].description, "Added 'B'");
+
+        restore_branch(&mut doc, "t2").unwrap();
+        assert_eq!(children_of_root(&doc), vec!["a"]);
+        assert_eq!(doc.undo_tree.current_id, "t1");
+        assert_eq!(doc.undo_stack.len(), 1);
+        assert_eq!(
+            doc.redo_stack,
+            vec![doc.undo_tree.nodes["t2"].entry.clone().unwrap()]
+        );
+    }
+
+    #[test]
+    fn jump_crosses_branches() {
+        let mut doc = edited_after_undo();
+        jump(&mut doc, "t2").unwrap();
+        assert_eq!(children_of_root(&doc), vec!["a", "b"]);
+        assert_eq!(doc.cursor_id, "b");
+        assert_eq!(doc.undo_stack.len(), 2);
+        assert!(doc.redo_stack.is_empty());
+
+        jump(&mut doc, "t0").unwrap();
+        assert!(children_of_root(&doc).is_empty());
+        assert!(doc.undo_stack.is_empty());
+        assert_eq!(
+            doc.redo_stack.len(),
+            2,
+            "redo follows the newest line, through C"
+        );
+        assert_eq!(
+            doc.redo_stack[0],
+            doc.undo_tree.nodes["t3"].entry.clone().unwrap()
+        );
+    }
+
+    fn tree_len(tree: &UndoTree) -> usize {
+        tree.nodes.values().map(node_len).sum()
+    }
+
+    fn limits(max_depth: usize, max_nodes: usize) -> HistoryLimits {
+        HistoryLimits {
+            max_depth,
+            max_bytes: usize::MAX,
+            max_tree_nodes: max_nodes,
+        }
+    }
+
+    /// A document that added `count` children one by one, every step recorded in the
+    /// same millisecond.
+    fn linear(count: usize) -> Document {
+        let ids: Vec<String> = (0..count).map(|i| format!("n{i}")).collect();
+        let ids: Vec<&str> = ids.iter().map(String::as_str).collect();
+        let start = state(&[]);
+        let mut doc = Document {
+            id: "doc".to_string(),
+            root_id: start.root_id,
+            cursor_id: start.cursor_id,
+            nodes: start.nodes,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            undo_tree: UndoTree::default(),
+        };
+        for i in 0..count {
+            let entry = undo::entry_between(&state(&ids[..i]), &state(&ids[..=i]));
+            undo::apply_entry(&mut doc.nodes, &mut doc.cursor_id, &entry).unwrap();
+            push(&mut doc, entry, 5);
+        }
+        doc
+    }
+
+    #[test]
+    fn steps_in_the_same_millisecond_get_their_own_nodes() {
+        let doc = linear(3);
+        let mut ids: Vec<&String> = doc.undo_tree.nodes.keys().collect();
+        ids.sort();
+        assert_eq!(ids, vec!["doc-5", "doc-5-1", "doc-5-2", "doc-5-root"]);
+        assert_eq!(
+            ancestors(&doc.undo_tree, &doc.undo_tree.current_id).len(),
+            4
+        );
+        assert!(check(&doc).is_ok());
+    }
+
+    #[test]
+    fn prune_keeps_the_current_path() {
+        let mut doc = edited_after_undo();
+        let removed = prune(&mut doc.undo_tree, &mut None, limits(10, 3));
+        assert_eq!(removed, vec!["t2"]);
+        assert!(prune(&mut doc.undo_tree, &mut None, limits(10, 3)).is_empty());
+    }
+
+    #[test]
+    fn redo_line_is_kept_up_to_max_depth() {
+        let mut doc = linear(4);
+        jump(&mut doc, "doc-5-root").unwrap();
+        let mut tip = redo_tip(&doc.undo_tree, &doc.redo_stack);
+        assert_eq!(tip.as_deref(), Some("doc-5-3"));
+
+        let removed = prune(&mut doc.undo_tree, &mut tip, limits(2, 3));
+        assert_eq!(tip.as_deref(), Some("doc-5-1"));
+        assert_eq!(removed, vec!["doc-5-3", "doc-5-2"]);
+        let (undo, redo) = stacks(&doc.undo_tree, tip.as_deref());
+        assert!(undo.is_empty());
+        assert_eq!(redo, doc.redo_stack[2..].to_vec());
+    }
+
+    #[test]
+    fn long_linear_history_is_rerooted() {
+        let mut doc = linear(10);
+        let removed = prune(&mut doc.undo_tree, &mut None, limits(100, 4));
+        assert_eq!(removed.len(), 7);
+        assert_eq!(doc.undo_tree.nodes.len(), 4);
+        assert!(check(&doc).is_ok());
+
+        let path = ancestors(&doc.undo_tree, &doc.undo_tree.current_id);
+        assert_eq!(path.len(), 4);
+        let root = &doc.undo_tree.nodes[&path[3]];
+        assert_eq!((root.parent_id.as_ref(), root.entry.as_ref()), (None, None));
+
+        // The oldest state kept is the one after the first seven steps.
+        jump(&mut doc, &path[3]).unwrap();
+        assert_eq!(children_of_root(&doc).len(), 7);
+        assert!(doc.undo_stack.is_empty());
+        assert_eq!(doc.redo_stack.len(), 3);
+    }
+
+    #[test]
+    fn states_past_max_depth_are_dropped_with_their_branches() {
+        let mut doc = edited_after_undo();
+        let removed = prune(&mut doc.undo_tree, &mut None, limits(1, 100));
+        assert_eq!(removed, vec!["t0"]);
+        assert_eq!(doc.undo_tree.nodes["t1"].parent_id, None);
+        assert_eq!(doc.undo_tree.nodes["t1"].entry, None);
+
+        // B forked off a state that is now past the limit too.
+        let removed = prune(&mut doc.undo_tree, &mut None, limits(0, 100));
+        assert_eq!(removed, vec!["t1", "t2"]);
+        assert_eq!(doc.undo_tree.nodes.len(), 1);
+        assert!(check(&doc).is_ok());
+    }
+
+    #[test]
+    fn byte_budget_trims_branches_then_the_path() {
+        let mut doc = edited_after_undo();
+        let full = tree_len(&doc.undo_tree);
+        let budget = HistoryLimits {
+            max_bytes: full - 1,
+            ..limits(100, 100)
+        };
+        assert_eq!(
+            prune(&mut doc.undo_tree, &mut None, budget),
+            vec!["t2"]
+        );
+
+        let mut doc = linear(5);
+        let budget = HistoryLimits {
+            max_bytes: tree_len(&doc.undo_tree) / 2,
+            ..limits(100, 100)
+        };
+        prune(&mut doc.undo_tree, &mut None, budget);
+        assert!(tree_len(&doc.undo_tree) <= budget.max_bytes);
+        assert!(doc.undo_tree.nodes.contains_key(&doc.undo_tree.current_id));
+        assert!(check(&doc).is_ok());
+    }
+}
@@ -0,0 +1,553 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+use crate::model::{Document, HistoryEntry, Node, Workspace};
+use crate::undo;
+use crate::undo_tree;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(
+    tag = "kind",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum Issue {
+    /// `nodes[key].id` differed from its key; the key wins.
+    NodeIdMismatch { node_id: String, found: String },
+    /// `root_id` did not exist; `replacement` is the node promoted (or created) as root.
+    MissingRoot {
+        root_id: String,
+        replacement: String,
+    },
+    RootHasParent { parent_id: String },
+    DanglingChild { parent_id: String, child_id: String },
+    /// A node was listed as a child more than once; only the first occurrence is kept.
+    DuplicateChild { parent_id: String, child_id: String },
+    /// A child reference pointed back at one of its ancestors and was dropped.
+    Cycle { parent_id: String, child_id: String },
+    ParentMismatch {
+        node_id: String,
+        expected: String,
+        found: Option<String>,
+    },
+    OrphanReattached { node_id: String },
+    MissingCursor { cursor_id: String },
+    /// A history entry no longer applied to the tree; it and the `count - 1` entries beyond
+    /// it were dropped.
+    HistoryDropped { count: usize, reason: String },
+    /// The undo tree's current path no longer undid cleanly; the tree was rebuilt from the
+    /// stacks.
+    UndoTreeReset { reason: String },
+    /// Tree nodes cut off from the root (their parent was pruned) were dropped.
+    UndoNodesDropped { count: usize },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum StateLocation {
+    Current,
+    Undo { index: usize },
+    Redo { index: usize },
+    UndoTree,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateReport {
+    pub location: StateLocation,
+    pub issues: Vec<Issue>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentReport {
+    pub doc_id: String,
+    pub states: Vec<StateReport>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RepairReport {
+    pub documents: Vec<DocumentReport>,
+    /// Tabs whose `doc_id` had no document and were removed.
+    pub dropped_tabs: Vec<String>,
+    /// Set when `active_doc_id` was invalid and had to be moved to another tab.
+    pub reset_active_doc_id: Option<String>,
+}
+
+impl RepairReport {
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+            && self.dropped_tabs.is_empty()
+            && self.reset_active_doc_id.is_none()
+    }
+}
+
+pub fn repair_workspace(workspace: &mut Workspace) -> RepairReport {
+    let mut report = RepairReport::default();
+
+    let mut doc_ids: Vec<String> = workspace.documents.keys().cloned().collect();
+    doc_ids.sort();
+    for doc_id in doc_ids {
+        let doc = workspace
+            .documents
+            .get_mut(&doc_id)
+            .expect("doc id collected from keys");
+        doc.id.clone_from(&doc_id);
+        let states = repair_document(doc);
+        if !states.is_empty() {
+            report.documents.push(DocumentReport { doc_id, states });
+        }
+    }
+
+    let documents = &workspace.documents;
+    workspace.tabs.retain(|tab| {
+        let keep = documents.contains_key(&tab.doc_id);
+        if !keep {
+            report.dropped_tabs.push(tab.doc_id.clone());
+        }
+        keep
+    });
+
+    if !workspace.documents.contains_key(&workspace.active_doc_id) {
+        if let Some(first) = workspace.tabs.first() {
+            report.reset_active_doc_id = Some(workspace.active_doc_id.clone());
+            workspace.active_doc_id.clone_from(&first.doc_id);
+        }
+    }
+
+    report
+}
+
+pub fn repair_document(doc: &mut Document) -> Vec<StateReport> {
+    let mut states = Vec::new();
+
+    let issues = repair_tree(&mut doc.root_id, &mut doc.cursor_id, &mut doc.nodes);
+    if !issues.is_empty() {
+        states.push(StateReport {
+            location: StateLocation::Current,
+            issues,
+        });
+    }
+
+    let undo_issue = check_history(doc, &doc.undo_stack, undo::invert_entry)
+        .map(|(index, reason)| (index, drop_from(&mut doc.undo_stack, index, reason)));
+    if let Some((index, issue)) = undo_issue {
+        states.push(StateReport {
+            location: StateLocation::Undo { index },
+            issues: vec![issue],
+        });
+    }
+    let redo_issue = check_history(doc, &doc.redo_stack, HistoryEntry::clone)
+        .map(|(index, reason)| (index, drop_from(&mut doc.redo_stack, index, reason)));
+    if let Some((index, issue)) = redo_issue {
+        states.push(StateReport {
+            location: StateLocation::Redo { index },
+            issues: vec![issue],
+        });
+    }
+
+    let mut tree_issues = Vec::new();
+    if let Err(reason) = undo_tree::check(doc) {
+        doc.undo_tree = Default::default();
+        tree_issues.push(Issue::UndoTreeReset { reason });
+    }
+    let count = undo_tree::drop_unreachable(&mut doc.undo_tree);
+    if count > 0 {
+        tree_issues.push(Issue::UndoNodesDropped { count });
+    }
+    undo_tree::seed(doc);
+    if !tree_issues.is_empty() {
+        states.push(StateReport {
+            location: StateLocation::UndoTree,
+            issues: tree_issues,
+        });
+    }
+
+    states
+}
+
+/// Replays `stack` from its top against a copy of the (already repaired) tree, turning each
+/// entry into the step it stands for with `step`. Returns the index of the first entry that
+/// does not apply, with the reason.
+fn check_history(
+    doc: &Document,
+    stack: &[HistoryEntry],
+    step: fn(&HistoryEntry) -> HistoryEntry,
+) -> Option<(usize, String)> {
+    let mut nodes = doc.nodes.clone();
+    let mut cursor_id = doc.cursor_id.clone();
+    for (index, entry) in stack.iter().enumerate().rev() {
+        if let Err(reason) = undo::apply_entry(&mut nodes, &mut cursor_id, &step(entry)) {
+            return Some((index, reason));
+        }
+    }
+    None
+}
+
+/// Drops the entry at `index` and every older one below it, which can only be reached
+/// through it.
+fn drop_from(stack: &mut Vec<HistoryEntry>, index: usize, reason: String) -> Issue {
+    stack.drain(..=index);
+    Issue::HistoryDropped {
+        count: index + 1,
+        reason,
+    }
+}
+
+/// Makes `nodes` a single tree rooted at `root_id` whose `parent_id` and `children_ids`
+/// agree, with `cursor_id` pointing at an existing node. Nothing reachable is ever dropped;
+/// unreachable nodes are reattached as the last children of the root.
+pub fn repair_tree(
+    root_id: &mut String,
+    cursor_id: &mut String,
+    nodes: &mut HashMap<String, Node>,
+) -> Vec<Issue> {
+    let mut issues = Vec::new();
+
+    let mut keys: Vec<String> = nodes.keys().cloned().collect();
+    keys.sort();
+    for key in &keys {
+        let node = nodes.get_mut(key).expect("key collected from map");
+        if node.id != *key {
+            issues.push(Issue::NodeIdMismatch {
+                node_id: key.clone(),
+                found: std::mem::replace(&mut node.id, key.clone()),
+            });
+        }
+    }
+
+    if !nodes.contains_key(root_id.as_str()) {
+        let replacement = keys
+            .iter()
+            .find(|id| nodes[*id].parent_id.is_none())
+            .cloned()
+            .unwrap_or_else(|| {
+                nodes.insert(
+                    root_id.clone(),
+                    Node {
+                        id: root_id.clone(),
+                        text: String::new(),
+                        parent_id: None,
+                        children_ids: Vec::new(),
+                    },
+                );
+                root_id.clone()
+            });
+        issues.push(Issue::MissingRoot {
+            root_id: root_id.clone(),
+            replacement: replacement.clone(),
+        });
+        *root_id = replacement;
+    }
+
+    let root = nodes.get_mut(root_id.as_str()).expect("root ensured above");
+    if let Some(parent_id) = root.parent_id.take() {
+        issues.push(Issue::RootHasParent { parent_id });
+    }
+
+    let mut assigned_parent: HashMap<String, String> = HashMap::new();
+    let mut visited: HashSet<String> = HashSet::new();
+    visited.insert(root_id.clone());
+    walk(nodes, root_id, &mut visited, &mut assigned_parent, &mut issues);
+
+    let mut orphans: Vec<String> = keys
+        .iter()
+        .filter(|id| !visited.contains(*id))
+        .cloned()
+        .collect();
+    while !orphans.is_empty() {
+        let referenced: HashSet<&String> = orphans
+            .iter()
+            .flat_map(|id| nodes[id].children_ids.iter())
+            .collect();
+        let top = orphans
+            .iter()
+            .find(|id| !referenced.contains(id))
+            .unwrap_or(&orphans[0])
+            .clone();
+
+        nodes
+            .get_mut(root_id.as_str())
+            .expect("root ensured above")
+            .children_ids
+            .push(top.clone());
+        visited.insert(top.clone());
+        assigned_parent.insert(top.clone(), root_id.clone());
+        issues.push(Issue::OrphanReattached {
+            node_id: top.clone(),
+        });
+        walk(nodes, &top, &mut visited, &mut assigned_parent, &mut issues);
+
+        orphans.retain(|id| !visited.contains(id));
+    }
+
+    for key in &keys {
+        let Some(expected) = assigned_parent.get(key) else {
+            continue;
+        };
+        let node = nodes.get_mut(key).expect("key collected from map");
+        if node.parent_id.as_ref() != Some(expected) {
+            issues.push(Issue::ParentMismatch {
+                node_id: key.clone(),
+                expected: expected.clone(),
+                found: node.parent_id.replace(expected.clone()),
+            });
+        }
+    }
+
+    if !nodes.contains_key(cursor_id.as_str()) {
+        issues.push(Issue::MissingCursor {
+            cursor_id: std::mem::replace(cursor_id, root_id.clone()),
+        });
+    }
+
+    issues
+}
+
+/// Iterative pre-order walk from `start` that drops child references which are dangling,
+/// repeated, or point back up the tree. Deep trees must not overflow the stack.
+fn walk(
+    nodes: &mut HashMap<String, Node>,
+    start: &str,
+    visited: &mut HashSet<String>,
+    assigned_parent: &mut HashMap<String, String>,
+    issues: &mut Vec<Issue>,
+) {
+    let mut stack = vec![start.to_string()];
+    while let Some(parent_id) = stack.pop() {
+        let children = std::mem::take(
+            &mut nodes
+                .get_mut(&parent_id)
+                .expect("only existing nodes are pushed")
+                .children_ids,
+        );
+        let mut kept = Vec::with_capacity(children.len());
+        for child_id in children {
+            if !nodes.contains_key(&child_id) {
+                issues.push(Issue::DanglingChild {
+                    parent_id: parent_id.clone(),
+                    child_id,
+                });
+            } else if visited.contains(&child_id) {
+                let issue = if is_ancestor(assigned_parent, &child_id, &parent_id) {
+                    Issue::Cycle {
+                        parent_id: parent_id.clone(),
+                        child_id,
+                    }
+                } else {
+                    Issue::DuplicateChild {
+                        parent_id: parent_id.clone(),
+                        child_id,
+                    }
+                };
+                issues.push(issue);
+            } else {
+                visited.insert(child_id.clone());
+                assigned_parent.insert(child_id.clone(), parent_id.clone());
+                kept.push(child_id);
+            }
+        }
+        stack.extend(kept.iter().rev().cloned());
+        nodes
+            .get_mut(&parent_id)
+            .expect("only existing nodes are pushed")
+            .children_ids = kept;
+    }
+}
+
+fn is_ancestor(assigned_parent: &HashMap<String, String>, candidate: &str, node: &str) -> bool {
+    let mut current = Some(node);
+    while let Some(id) = current {
+        if id == candidate {
+            return true;
+        }
+        current = assigned_parent.get(id).map(String::as_str);
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::EditOp;
+    use crate::test_support::{document, nodes};
+
+    fn repair(root_id: &str, cursor_id: &str, nodes: &mut HashMap<String, Node>) -> Vec<Issue> {
+        let (mut root_id, mut cursor_id) = (root_id.to_string(), cursor_id.to_string());
+        let issues = repair_tree(&mut root_id, &mut cursor_id, nodes);
+        assert_eq!(repair_tree(&mut root_id, &mut cursor_id, nodes), vec![]);
+        issues
+    }
+
+    fn children<'a>(nodes: &'a HashMap<String, Node>, id: &str) -> Vec<&'a str> {
+        nodes[id].children_ids.iter().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn leaves_a_sound_tree_alone() {
+        let mut tree = nodes(&[("root", "", &["a"]), ("a", "", &["b"]), ("b", "", &[])]);
+        let before = tree.clone();
+        assert_eq!(repair("root", "b", &mut tree), vec![]);
+        assert_eq!(tree, before);
+    }
+
+    #[test]
+    fn breaks_cycles_and_drops_repeated_children() {
+        let mut tree = nodes(&[
+            ("root", "", &["a", "b", "a"]),
+            ("a", "", &["c"]),
+            ("b", "", &["c"]),
+            ("c", "", &["root"]),
+        ]);
+        tree.get_mut("c").unwrap().parent_id = Some("a".to_string());
+        let issues = repair("root", "root", &mut tree);
+        // The rows make "c" the root's parent too.
+        assert_eq!(
+            issues,
+            vec![
+                Issue::RootHasParent {
+                    parent_id: "c".to_string(),
+                },
+                Issue::DuplicateChild {
+                    parent_id: "root".to_string(),
+                    child_id: "a".to_string(),
+                },
+                Issue::Cycle {
+                    parent_id: "c".to_string(),
+                    child_id: "root".to_string(),
+                },
+                Issue::DuplicateChild {
+                    parent_id: "b".to_string(),
+                    child_id: "c".to_string(),
+                },
+            ]
+        );
+        assert_eq!(children(&tree, "root"), vec!["a", "b"]);
+        assert_eq!(children(&tree, "a"), vec!["c"]);
+        assert!(children(&tree, "b").is_empty() && children(&tree, "c").is_empty());
+    }
+
+    #[test]
+    fn drops_dangling_references_and_reattaches_orphans() {
+        let mut tree = nodes(&[
+            ("root", "", &["a", "ghost"]),
+            ("a", "", &[]),
+            ("x", "", &["y"]),
+            ("y", "", &[]),
+        ]);
+        tree.get_mut("a").unwrap().parent_id = Some("gone".to_string());
+        tree.get_mut("y").unwrap().parent_id = Some("a".to_string());
+        let issues = repair("root", "root", &mut tree);
+        assert_eq!(
+            issues,
+            vec![
+                Issue::DanglingChild {
+                    parent_id: "root".to_string(),
+                    child_id: "ghost".to_string(),
+                },
+                Issue::OrphanReattached {
+                    node_id: "x".to_string(),
+                },
+                Issue::ParentMismatch {
+                    node_id: "a".to_string(),
+                    expected: "root".to_string(),
+                    found: Some("gone".to_string()),
+                },
+                Issue::ParentMismatch {
+                    node_id: "x".to_string(),
+                    expected: "root".to_string(),
+                    found: None,
+                },
+                Issue::ParentMismatch {
+                    node_id: "y".to_string(),
+                    expected: "x".to_string(),
+                    found: Some("a".to_string()),
+                },
+            ]
+        );
+        assert_eq!(children(&tree, "root"), vec!["a", "x"]);
+        assert_eq!(children(&tree, "x"), vec!["y"]);
+    }
+
+    #[test]
+    fn replaces_a_missing_root_and_cursor() {
+        let mut tree = nodes(&[("a", "", &["b"]), ("b", "", &[])]);
+        tree.get_mut("a").unwrap().id = "A".to_string();
+        let (mut root_id, mut cursor_id) = ("gone".to_string(), "ghost".to_string());
+        let issues = repair_tree(&mut root_id, &mut cursor_id, &mut tree);
+        assert_eq!(
+            issues,
+            vec![
+                Issue::NodeIdMismatch {
+                    node_id: "a".to_string(),
+                    found: "A".to_string(),
+                },
+                Issue::MissingRoot {
+                    root_id: "gone".to_string(),
+                    replacement: "a".to_string(),
+                },
+                Issue::MissingCursor {
+                    cursor_id: "ghost".to_string(),
+                },
+            ]
+        );
+        assert_eq!((root_id.as_str(), cursor_id.as_str()), ("a", "a"));
+
+        // With every node claiming a parent, a fresh empty root takes the old id.
+        let mut tree = nodes(&[("a", "", &["b"]), ("b", "", &["a"])]);
+        tree.get_mut("a").unwrap().parent_id = Some("b".to_string());
+        let (mut root_id, mut cursor_id) = ("gone".to_string(), "a".to_string());
+        let issues = repair_tree(&mut root_id, &mut cursor_id, &mut tree);
+        assert_eq!(
+            issues[0],
+            Issue::MissingRoot {
+                root_id: "gone".to_string(),
+                replacement: "gone".to_string(),
+            }
+        );
+        assert_eq!(root_id, "gone");
+        assert_eq!(tree["gone"].text, "");
+        assert_eq!(children(&tree, "gone"), vec!["a"]);
+    }
+
+    #[test]
+    fn drops_history_from_the_first_entry_that_no_longer_applies() {
+        let rename = |before: &str, after: &str| HistoryEntry {
+            cursor_before: "a".to_string(),
+            cursor_after: "a".to_string(),
+            ops: vec![EditOp::EditText {
+                node_id: "a".to_string(),
+                before: before.to_string(),
+                after: after.to_string(),
+            }],
+        };
+        let mut doc = document(&[("root", "", &["a"]), ("a", "Budget", &[])]);
+        // Undo runs from the top: "Draft" -> "Budget" applies, then "X" -> "Y" does not.
+        doc.undo_stack = vec![rename("", "X"), rename("X", "Y"), rename("Draft", "Budget")];
+        doc.redo_stack = vec![rename("Plan", "Notes"), rename("Budget", "Final")];
+
+        let states = repair_document(&mut doc);
+        assert_eq!(states[0].location, StateLocation::Undo { index: 1 });
+        assert_eq!(
+            states[0].issues,
+            vec![Issue::HistoryDropped {
+                count: 2,
+                reason: "text of a has changed".to_string(),
+            }]
+        );
+        assert_eq!(states[1].location, StateLocation::Redo { index: 0 });
+        assert_eq!(
+            states[1].issues,
+            vec![Issue::HistoryDropped {
+                count: 1,
+                reason: "text of a has changed".to_string(),
+            }]
+        );
+        assert_eq!(states.len(), 2);
+        assert_eq!(doc.undo_stack, vec![rename("Draft", "Budget")]);
+        assert_eq!(doc.redo_stack, vec![rename("Budget", "Final")]);
+        // The tree is seeded from what is left: root, one undo step, one redo step.
+        assert_eq!(doc.undo_tree.nodes.len(), 3);
+    }
+}
@@ -1,9 +1,41 @@
+import { invoke } from "@tauri-apps/api/core";
+import { open, save } from "@tauri-apps/plugin-dialog";
 import { useEffect, useMemo, useReducer, useRef, useState } from "react";
 import "./App.css";
 import { EditorView } from "./editor/EditorView";
-import { TabBar } from "./editor/TabBar";
+import { getTabTitle, TabBar } from "./editor/TabBar";
 import { createInitialAppState, editorReducer } from "./editor/state";
+import type { Document } from "./editor/types";
+import {
+  EXPORT_TARGETS,
+  exportFileName,
+  IMPORT_FILTERS,
+  withTheme,
+  type ImportedDocument,
+} from "./features/export/model";
 import { filterPaletteCommands, type PaletteCommand } from "./features/palette/model";
+import {
+  countRepairIssues,
+  describeBackup,
+  describeRevision,
+  describeTreeDiff,
+  describeUndoBranch,
+  summarizeMergeConflicts,
+  summarizeRepairReport,
+  summarizeSalvageReport,
+  summarizeTreeDiff,
+  type BackupInfo,
+  type BrokenWorkspaceInfo,
+  type HistoryDescription,
+  type LoadedWorkspace,
+  type MergeCandidate,
+  type MergeConflict,
+  type MergedWorkspace,
+  type RecoveredDocuments,
+  type RevisionInfo,
+  type TreeDiff,
+  type UndoBranch,
+} from "./features/persistence/model";
 import { buildSearchResults } from "./features/search/model";
 import { useTheme } from "./hooks/useTheme";
 import { useWorkspacePersistence } from "./hooks/useWorkspacePersistence";
@@ -25,6 +57,16 @@ function App() {
   const [paletteOpen, setPaletteOpen] = useState(false);
   const [paletteQuery, setPaletteQuery] = useState("");
   const [paletteIndex, setPaletteIndex] = useState(0);
+  const [backups, setBackups] = useState<BackupInfo[]>([]);
+  const [history, setHistory] = useState<HistoryDescription>({ undo: [], redo: [] });
+  const [undoBranches, setUndoBranches] = useState<UndoBranch[]>([]);
+  const [revisions, setRevisions] = useState<RevisionInfo[]>([]);
+  const [mergeCandidates, setMergeCandidates] = useState<MergeCandidate[]>([]);
+  const [mergeConflicts, setMergeConflicts] = useState<MergeConflict[]>([]);
+  const [importWarnings, setImportWarnings] = useState<string[]>([]);
+  const [importError, setImportError] = useState<string | null>(null);
+  const [exportError, setExportError] = useState<string | null>(null);
+  const [comparison, setComparison] = useState<{ label: string; diff: TreeDiff } | null>(null);
   const viewportRef = useRef<HTMLDivElement | null>(null);
   const pendingDRef = useRef(false);
   const pendingDTimerRef = useRef<number | null>(null);
@@ -90,6 +132,36 @@ function App() {
           setPaletteOpen(false);
         },
       },
+      {
+        id: "recover-broken-workspace",
+        title: "Recover documents from broken workspace",
+        subtitle: "workspace.json.broken-*",
+        run: () => {
+          void (async () => {
+            try {
+              const infos = await invoke<BrokenWorkspaceInfo[]>("list_broken_workspaces");
+              const latest = infos.find((info) => info.documents.length > 0);
+              if (!latest) return;
+              const recovered = await invoke<RecoveredDocuments>("recover_broken_workspace", {
+                fileName: latest.fileName,
+              });
+              dispatch({ type: "openDocuments", documents: recovered.documents });
+            } catch {
+              // Browser mode: no backend to recover from.
+            }
+          })();
+        },
+      },
+      {
+        id: "import-into-sqlite",
+        title: "Move workspace to SQLite",
+        subtitle: "workspace.sqlite3",
+        run: () => {
+          void invoke<number>("import_into_sqlite").catch(() => {
+            // Browser mode or already imported: nothing to move.
+          });
+        },
+      },
       {
         id: "cycle-theme",
         title: "Cycle theme",
@@ -98,8 +170,312 @@ function App() {
       },
     ];
 
+    commands.push({
+      id: "import-document",
+      title: "Import outline from file",
+      subtitle: IMPORT_FILTERS.flatMap((filter) => filter.extensions.map((ext) => `.${ext}`)).join(" "),
+      run: () => {
+        void (async () => {
+          try {
+            const path = await open({ multiple: false, directory: false, filters: IMPORT_FILTERS });
+            if (!path) return;
+            const imported = await invoke<ImportedDocument>("import_document", { path });
+            dispatch({ type: "openDocuments", documents: [imported.document] });
+            setImportWarnings(imported.warnings);
+            setImportError(null);
+          } catch (e) {
+            // The backend rejects with its error message; browser mode has no backend to ask.
+            if (typeof e === "string") setImportError(e);
+          }
+        })();
+      },
+    });
+    for (const target of EXPORT_TARGETS) {
+      commands.push({
+        id: `export:${target.id}`,
+        title: target.title,
+        subtitle: `.${target.extension}`,
+        run: () => {
+          void (async () => {
+            try {
+              const path = await save({
+                defaultPath: exportFileName(getTabTitle(activeDoc), target.extension),
+                filters: [{ name: target.filterName, extensions: [target.extension] }],
+              });
+              if (!path) return;
+              await invoke("export_document", {
+                document: activeDoc,
+                path,
+                format: withTheme(target.format, theme),
+              });
+              setExportError(null);
+            } catch (e) {
+              // The backend rejects with its error message; browser mode has no file system.
+              if (typeof e === "string") setExportError(e);
+            }
+          })();
+        },
+      });
+    }
+
+    if (history.undo[0]) {
+      commands.push({
+        id: "undo",
+        title: `Undo: ${history.undo[0]}`,
+        subtitle: "u",
+        run: () => dispatch({ type: "undo" }),
+      });
+    }
+    if (history.redo[0]) {
+      commands.push({
+        id: "redo",
+        title: `Redo: ${history.redo[0]}`,
+        subtitle: "Ctrl+r",
+        run: () => dispatch({ type: "redo" }),
+      });
+    }
+
+    const replaceWith = (command: string, args: Record<string, unknown>) => {
+      void invoke<Document>(command, { document: activeDoc, ...args })
+        .then((document) => dispatch({ type: "replaceDocument", document }))
+        .catch(() => {
+          // Browser mode or history that no longer applies: keep the document as is.
+        });
+    };
+    for (const branch of undoBranches) {
+      if (!branch.current) {
+        commands.push({
+          id: `restore-undo-branch:${branch.tipId}`,
+          title: `Restore undo branch: ${branch.description}`,
+          subtitle: describeUndoBranch(branch),
+          run: () => replaceWith("restore_undo_branch", { tipId: branch.tipId }),
+        });
+      }
+      commands.push({
+        id: `jump-to-undo-state:${branch.tipId}`,
+        title: `Jump to undo state: ${branch.description}`,
+        subtitle: describeUndoBranch(branch),
+        run: () => replaceWith("jump_to_undo_state", { nodeId: branch.tipId }),
+      });
+    }
+
+    const compareWith = (label: string, command: string, args: Record<string, unknown>) => {
+      void invoke<TreeDiff>(command, args)
+        .then((diff) => setComparison({ label, diff }))
+        .catch(() => {
+          // Browser mode or nothing to compare against: keep the previous comparison.
+        });
+    };
+    if (comparison) {
+      commands.push({
+        id: "clear-comparison",
+        title: "Clear comparison",
+        subtitle: `vs ${comparison.label}`,
+        run: () => setComparison(null),
+      });
+    }
+    for (const tab of state.workspace.tabs) {
+      const other = state.workspace.documents[tab.docId];
+      if (!other || other.id === activeDoc.id) continue;
+      const title = getTabTitle(other);
+      commands.push({
+        id: `compare-tab:${tab.docId}`,
+        title: `Compare with tab: ${title}`,
+        subtitle: "Changes from that tab to this one",
+        run: () => compareWith(title, "diff_documents", { before: other, after: activeDoc }),
+      });
+    }
+
+    for (const revision of revisions) {
+      const { when, subtitle } = describeRevision(revision);
+      commands.push({
+        id: `compare-revision:${revision.savedAtMillis}`,
+        title: `Compare with revision ${when}`,
+        subtitle,
+        run: () =>
+          compareWith(`revision ${when}`, "diff_revision", {
+            document: activeDoc,
+            docId: activeDoc.id,
+            savedAtMillis: revision.savedAtMillis,
+          }),
+      });
+      commands.push({
+        id: `restore-revision:${revision.savedAtMillis}`,
+        title: `Restore revision ${when}`,
+        subtitle,
+        run: () =>
+          replaceWith("restore_revision", { savedAtMillis: revision.savedAtMillis }),
+      });
+      commands.push({
+        id: `fork-revision:${revision.savedAtMillis}`,
+        title: `Open revision ${when} in new tab`,
+        subtitle,
+        run: () => {
+          void invoke<Document>("fork_revision", {
+            docId: activeDoc.id,
+            savedAtMillis: revision.savedAtMillis,
+          })
+            .then((document) => dispatch({ type: "openDocuments", documents: [document] }))
+            .catch(() => {
+              // Browser mode or unreadable revision: nothing to open.
+            });
+        },
+      });
+    }
+
+    for (const backup of backups) {
+      const { title, subtitle } = describeBackup(backup);
+      if (backup.documents.some((doc) => doc.docId === activeDoc.id)) {
+        const when = new Date(backup.createdAtMillis).toLocaleString();
+        commands.push({
+          id: `compare-backup:${backup.fileName}`,
+          title: `Compare with backup ${when}`,
+          subtitle,
+          run: () =>
+            compareWith(`backup ${when}`, "diff_backup", {
+              document: activeDoc,
+              docId: activeDoc.id,
+              fileName: backup.fileName,
+            }),
+        });
+      }
+      commands.push({
+        id: `restore-backup:${backup.fileName}`,
+        title,
+        subtitle,
+        run: () => {
+          void (async () => {
+            try {
+              const restored = await invoke<LoadedWorkspace>("restore_backup", {
+                fileName: backup.fileName,
+              });
+              dispatch({ type: "replaceWorkspace", workspace: restored.workspace });
+            } catch {
+              // Browser mode or unreadable backup: keep the current workspace.
+            }
+          })();
+        },
+      });
+    }
+
+    for (const candidate of mergeCandidates) {
+      const baseFileName = candidate.baseFileName;
+      if (!baseFileName) continue;
+      commands.push({
+        id: `merge-workspace:${candidate.fileName}`,
+        title: `Merge workspace from ${candidate.fileName}`,
+        subtitle: new Date(candidate.modifiedAtMillis).toLocaleString(),
+        run: () => {
+          void invoke<MergedWorkspace>("merge_workspace", {
+            workspace: state.workspace,
+            fileName: candidate.fileName,
+            baseFileName,
+          })
+            .then((merged) => {
+              dispatch({ type: "replaceWorkspace", workspace: merged.workspace });
+              setMergeConflicts(merged.conflicts);
+            })
+            .catch(() => {
+              // Browser mode or unreadable copy: keep the current workspace.
+            });
+        },
+      });
+    }
+
     return filterPaletteCommands(commands, paletteQuery);
-  }, [cycleTheme, dispatch, paletteQuery]);
+  }, [
+    activeDoc,
+    backups,
+    comparison,
+    cycleTheme,
+    dispatch,
+    history,
+    mergeCandidates,
+    paletteQuery,
+    revisions,
+    state.workspace,
+    theme,
+    undoBranches,
+  ]);
+
+  useEffect(() => {
+    if (!paletteOpen) return;
+    let cancelled = false;
+    invoke<BackupInfo[]>("list_backups")
+      .then((list) => {
+        if (!cancelled) setBackups(list);
+      })
+      .catch(() => {
+        if (!cancelled) setBackups([]);
+      });
+    return () => {
+      cancelled = true;
+    };
+  }, [paletteOpen]);
+
+  useEffect(() => {
+    if (!paletteOpen) return;
+    let cancelled = false;
+    invoke<MergeCandidate[]>("list_merge_candidates")
+      .then((list) => {
+        if (!cancelled) setMergeCandidates(list);
+      })
+      .catch(() => {
+        if (!cancelled) setMergeCandidates([]);
+      });
+    return () => {
+      cancelled = true;
+    };
+  }, [paletteOpen]);
+
+  useEffect(() => {
+    if (!paletteOpen) return;
+    let cancelled = false;
+    invoke<HistoryDescription>("describe_history", { docId: state.workspace.activeDocId })
+      .then((description) => {
+        if (!cancelled) setHistory(description);
+      })
+      .catch(() => {
+        if (!cancelled) setHistory({ undo: [], redo: [] });
+      });
+    return () => {
+      cancelled = true;
+    };
+  }, [paletteOpen, state.workspace.activeDocId]);
+
+  useEffect(() => {
+    if (!paletteOpen) return;
+    let cancelled = false;
+    invoke<UndoBranch[]>("list_undo_branches", { document: activeDoc })
+      .then((branches) => {
+        if (!cancelled) setUndoBranches(branches);
+      })
+      .catch(() => {
+        if (!cancelled) setUndoBranches([]);
+      });
+    return () => {
+      cancelled = true;
+    };
+  }, [paletteOpen, activeDoc]);
+
+  useEffect(() => {
+    if (!paletteOpen) return;
+    let cancelled = false;
+    invoke<RevisionInfo[]>("list_revisions", { docId: state.workspace.activeDocId })
+      .then((list) => {
+        if (!cancelled) setRevisions(list);
+      })
+      .catch(() => {
+        if (!cancelled) setRevisions([]);
+      });
+    return () => {
+      cancelled = true;
+    };
+  }, [paletteOpen, state.workspace.activeDocId]);
+
+  useEffect(() => {
+    setComparison(null);
+  }, [state.workspace.activeDocId]);
 
   useEffect(() => {
     setSearchIndex(0);
@@ -139,7 +515,7 @@ function App() {
     }
   }, [state.mode]);
 
-  const { saveLabel, saveStatus } = useWorkspacePersistence({
+  const { saveLabel, saveStatus, repairReport, salvageReport } = useWorkspacePersistence({
     hydrated: state.hydrated,
     saveRevision: state.saveRevision,
     workspace: state.workspace,
@@ -509,6 +885,80 @@ function App() {
           <span className={"statusValue " + (saveStatus === "saving" ? "statusValueSaving" : "")}>
             {saveLabel}
           </span>
+          {salvageReport && (
+            <>
+              <span className="statusDot">•</span>
+              <span className="statusValue statusValueRepaired" title={summarizeSalvageReport(salvageReport)}>
+                Salvaged {salvageReport.recovered.length}/
+                {salvageReport.recovered.length + salvageReport.lost.length}
+              </span>
+            </>
+          )}
+          {repairReport && (
+            <>
+              <span className="statusDot">•</span>
+              <span className="statusValue statusValueRepaired" title={summarizeRepairReport(repairReport)}>
+                Repaired {countRepairIssues(repairReport)}
+              </span>
+            </>
+          )}
+          {mergeConflicts.length > 0 && (
+            <>
+              <span className="statusDot">•</span>
+              <span
+                className="statusValue statusValueRepaired"
+                title={summarizeMergeConflicts(mergeConflicts)}
+                onClick={() => setMergeConflicts([])}
+              >
+                Merge conflicts {mergeConflicts.length}
+              </span>
+            </>
+          )}
+          {importWarnings.length > 0 && (
+            <>
+              <span className="statusDot">•</span>
+              <span
+                className="statusValue statusValueRepaired"
+                title={importWarnings.join("\n")}
+                onClick={() => setImportWarnings([])}
+              >
+                Import warnings {importWarnings.length}
+              </span>
+            </>
+          )}
+          {importError && (
+            <>
+              <span className="statusDot">•</span>
+              <span
+                className="statusValue statusValueRepaired"
+                title={importError}
+                onClick={() => setImportError(null)}
+              >
+                Import failed
+              </span>
+            </>
+          )}
+          {exportError && (
+            <>
+              <span className="statusDot">•</span>
+              <span
+                className="statusValue statusValueRepaired"
+                title={exportError}
+                onClick={() => setExportError(null)}
+              >
+                Export failed
+              </span>
+            </>
+          )}
+          {comparison && (
+            <>
+              <span className="statusDot">•</span>
+              <span className="statusLabel">Diff vs {comparison.label}</span>
+              <span className="statusValue" title={describeTreeDiff(comparison.diff)}>
+                {summarizeTreeDiff(comparison.diff)}
+              </span>
+            </>
+          )}
         </div>
         <div className="statusRight">
           <button
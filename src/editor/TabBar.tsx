@@ -13,7 +13,7 @@ type Props = {
   onCycleTheme: () => void;
 };
 
-function getTabTitle(doc: Document | undefined): string {
+export function getTabTitle(doc: Document | undefined): string {
   if (!doc) return "(missing)";
   const root = doc.nodes[doc.rootId];
   const text = root?.text ?? "";
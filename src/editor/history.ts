@@ -0,0 +1,217 @@
+import type { Document, DocumentState, EditOp, HistoryEntry, Node, NodeId } from "./types";
+
+type Nodes = Record<NodeId, Node>;
+
+function sameIds(a: NodeId[], b: NodeId[]): boolean {
+  if (a.length !== b.length) return false;
+  for (let i = 0; i < a.length; i += 1) {
+    if (a[i] !== b[i]) return false;
+  }
+  return true;
+}
+
+function sameNode(a: Node | undefined, b: Node | undefined): boolean {
+  if (!a || !b) return a === b;
+  return (
+    a.id === b.id &&
+    a.text === b.text &&
+    a.parentId === b.parentId &&
+    sameIds(a.childrenIds, b.childrenIds)
+  );
+}
+
+// Mirrors `undo::apply_op` on the Rust side. `nodes` is a fresh copy owned by the caller;
+// touched nodes are replaced, never mutated, so untouched ones keep their identity.
+function applyOp(nodes: Nodes, op: EditOp): boolean {
+  switch (op.op) {
+    case "insertNode": {
+      if (nodes[op.nodeId]) return false;
+      const parent = nodes[op.parentId];
+      if (!parent) return false;
+      const end = op.index + op.childrenIds.length;
+      if (end > parent.childrenIds.length) return false;
+      if (!sameIds(parent.childrenIds.slice(op.index, end), op.childrenIds)) return false;
+      const children = [...parent.childrenIds];
+      children.splice(op.index, op.childrenIds.length, op.nodeId);
+      nodes[op.parentId] = { ...parent, childrenIds: children };
+      for (const childId of op.childrenIds) {
+        const child = nodes[childId];
+        if (!child) return false;
+        nodes[childId] = { ...child, parentId: op.nodeId };
+      }
+      nodes[op.nodeId] = {
+        id: op.nodeId,
+        text: op.text,
+        parentId: op.parentId,
+        childrenIds: [...op.childrenIds],
+      };
+      return true;
+    }
+    case "deleteNode": {
+      const node = nodes[op.nodeId];
+      if (!node) return false;
+      if (node.parentId !== op.parentId || node.text !== op.text) return false;
+      if (!sameIds(node.childrenIds, op.childrenIds)) return false;
+      const parent = nodes[op.parentId];
+      if (!parent || parent.childrenIds[op.index] !== op.nodeId) return false;
+      const children = [...parent.childrenIds];
+      children.splice(op.index, 1, ...op.childrenIds);
+      nodes[op.parentId] = { ...parent, childrenIds: children };
+      for (const childId of op.childrenIds) {
+        const child = nodes[childId];
+        if (!child) return false;
+        nodes[childId] = { ...child, parentId: op.parentId };
+      }
+      delete nodes[op.nodeId];
+      return true;
+    }
+    case "swapSiblings": {
+      const parent = nodes[op.parentId];
+      if (!parent) return false;
+      const len = parent.childrenIds.length;
+      if (op.index >= len || op.otherIndex >= len) return false;
+      const children = [...parent.childrenIds];
+      children[op.index] = parent.childrenIds[op.otherIndex];
+      children[op.otherIndex] = parent.childrenIds[op.index];
+      nodes[op.parentId] = { ...parent, childrenIds: children };
+      return true;
+    }
+    case "editText": {
+      const node = nodes[op.nodeId];
+      if (!node || node.text !== op.before) return false;
+      nodes[op.nodeId] = { ...node, text: op.after };
+      return true;
+    }
+    case "patch": {
+      for (const node of op.removed) {
+        if (!sameNode(nodes[node.id], node)) return false;
+      }
+      for (const node of op.removed) {
+        delete nodes[node.id];
+      }
+      for (const node of op.added) {
+        nodes[node.id] = { ...node, childrenIds: [...node.childrenIds] };
+      }
+      return true;
+    }
+  }
+}
+
+function invertOp(op: EditOp): EditOp {
+  switch (op.op) {
+    case "insertNode":
+      return { ...op, op: "deleteNode" };
+    case "deleteNode":
+      return { ...op, op: "insertNode" };
+    case "swapSiblings":
+      return op;
+    case "editText":
+      return { ...op, before: op.after, after: op.before };
+    case "patch":
+      return { op: "patch", removed: op.added, added: op.removed };
+  }
+}
+
+export function invertEntry(entry: HistoryEntry): HistoryEntry {
+  return {
+    cursorBefore: entry.cursorAfter,
+    cursorAfter: entry.cursorBefore,
+    ops: [...entry.ops].reverse().map(invertOp),
+  };
+}
+
+// Returns null, leaving `doc` as it was, when the entry no longer fits the tree.
+export function applyEntry(doc: Document, entry: HistoryEntry): Document | null {
+  const nodes: Nodes = { ...doc.nodes };
+  for (const op of entry.ops) {
+    if (!applyOp(nodes, op)) return null;
+  }
+  if (!nodes[entry.cursorAfter]) return null;
+  return { ...doc, nodes, cursorId: entry.cursorAfter };
+}
+
+function sortedIds(ids: NodeId[]): NodeId[] {
+  return [...ids].sort();
+}
+
+function namedOps(before: Nodes, after: Nodes): EditOp[] {
+  const ops: EditOp[] = [];
+  const kept = sortedIds(Object.keys(before).filter((id) => after[id]));
+
+  for (const id of kept) {
+    if (before[id].text !== after[id].text) {
+      ops.push({ op: "editText", nodeId: id, before: before[id].text, after: after[id].text });
+    }
+  }
+
+  for (const id of sortedIds(Object.keys(before).filter((id) => !after[id]))) {
+    const node = before[id];
+    if (!node.parentId) continue;
+    const index = before[node.parentId]?.childrenIds.indexOf(id) ?? -1;
+    if (index === -1) continue;
+    ops.push({
+      op: "deleteNode",
+      nodeId: id,
+      parentId: node.parentId,
+      index,
+      text: node.text,
+      childrenIds: node.childrenIds,
+    });
+  }
+
+  for (const id of kept) {
+    const prev = before[id].childrenIds;
+    const next = after[id].childrenIds;
+    if (prev.length !== next.length) continue;
+    const moved: number[] = [];
+    for (let i = 0; i < prev.length; i += 1) {
+      if (prev[i] !== next[i]) moved.push(i);
+    }
+    if (moved.length !== 2) continue;
+    const [index, otherIndex] = moved;
+    if (prev[index] === next[otherIndex] && prev[otherIndex] === next[index]) {
+      ops.push({ op: "swapSiblings", parentId: id, index, otherIndex });
+    }
+  }
+
+  for (const id of sortedIds(Object.keys(after).filter((id) => !before[id]))) {
+    const node = after[id];
+    if (!node.parentId) continue;
+    const index = after[node.parentId]?.childrenIds.indexOf(id) ?? -1;
+    if (index === -1) continue;
+    ops.push({
+      op: "insertNode",
+      nodeId: id,
+      parentId: node.parentId,
+      index,
+      text: node.text,
+      childrenIds: node.childrenIds,
+    });
+  }
+
+  return ops;
+}
+
+function patchBetween(before: Nodes, after: Nodes): EditOp {
+  const changed = (from: Nodes, to: Nodes) =>
+    sortedIds(Object.keys(from))
+      .filter((id) => !sameNode(from[id], to[id]))
+      .map((id) => from[id]);
+  return { op: "patch", removed: changed(before, after), added: changed(after, before) };
+}
+
+// Mirrors `undo::entry_between`: named ops when they reproduce `after` exactly, a patch
+// otherwise.
+export function entryBetween(before: DocumentState, after: DocumentState): HistoryEntry {
+  const ops = namedOps(before.nodes, after.nodes);
+  const replayed: Nodes = { ...before.nodes };
+  const reproduces =
+    ops.every((op) => applyOp(replayed, op)) &&
+    Object.keys(replayed).length === Object.keys(after.nodes).length &&
+    Object.keys(after.nodes).every((id) => sameNode(replayed[id], after.nodes[id]));
+  return {
+    cursorBefore: before.cursorId,
+    cursorAfter: after.cursorId,
+    ops: reproduces ? ops : [patchBetween(before.nodes, after.nodes)],
+  };
+}
@@ -1,5 +1,7 @@
 import type { DocumentState, NodeId } from "./types";
 
+// `src-tauri/src/layout.rs` lays out exported images the same way; keep the two in step.
+
 export const NODE_WIDTH = 180;
 export const NODE_HEIGHT = 34;
 export const H_GAP = 80;
@@ -1,4 +1,15 @@
-import type { DocId, Document, DocumentState, Mode, Node, NodeId, Workspace } from "./types";
+import { applyEntry, entryBetween, invertEntry } from "./history";
+import type {
+  DocId,
+  Document,
+  DocumentState,
+  HistoryEntry,
+  Mode,
+  Node,
+  NodeId,
+  UndoTree,
+  Workspace,
+} from "./types";
 
 export type EditorAppState = {
   workspace: Workspace;
@@ -11,10 +22,13 @@ export type EditorAppState = {
 
 export type EditorAction =
   | { type: "finishHydration"; workspace: Workspace | null }
+  | { type: "replaceWorkspace"; workspace: Workspace }
+  | { type: "replaceDocument"; document: Document }
   | { type: "setActiveDoc"; docId: DocId }
   | { type: "switchDocNext" }
   | { type: "switchDocPrev" }
   | { type: "createDoc" }
+  | { type: "openDocuments"; documents: Document[] }
   | { type: "requestCloseActiveDoc" }
   | { type: "cancelCloseConfirm" }
   | { type: "closeActiveDoc" }
@@ -41,6 +55,49 @@ function generateId(): string {
   return String(Date.now()) + Math.random().toString(16).slice(2);
 }
 
+function sameEntry(a: HistoryEntry, b: HistoryEntry): boolean {
+  return a === b || JSON.stringify(a) === JSON.stringify(b);
+}
+
+// Mirrors the Rust undo tree: moving forward by `entry` reuses a child recorded with the
+// same step (a redo) and otherwise records a new one, so edits after undo start a branch.
+function advanceTree(tree: UndoTree, entry: HistoryEntry): UndoTree {
+  const nodes = { ...tree.nodes };
+  let currentId = tree.currentId;
+  if (!nodes[currentId]) {
+    currentId = generateId();
+    nodes[currentId] = { id: currentId, parentId: null, entry: null, createdAtMillis: Date.now() };
+  }
+  const existing = Object.values(nodes).find(
+    (node) => node.parentId === currentId && node.entry !== null && sameEntry(node.entry, entry),
+  );
+  if (existing) return { ...tree, currentId: existing.id };
+  const id = generateId();
+  nodes[id] = { id, parentId: currentId, entry, createdAtMillis: Date.now() };
+  return { currentId: id, nodes };
+}
+
+// Without a parent to step back to the tree no longer matches the stacks; it is dropped
+// and rebuilt from them on the next load.
+function retreatTree(tree: UndoTree): UndoTree {
+  const parentId = tree.nodes[tree.currentId]?.parentId;
+  if (!parentId) return emptyUndoTree();
+  return { ...tree, currentId: parentId };
+}
+
+function emptyUndoTree(): UndoTree {
+  return { currentId: "", nodes: {} };
+}
+
+function pushHistory(doc: Document, entry: HistoryEntry): Document {
+  return {
+    ...doc,
+    undoStack: [...doc.undoStack, entry],
+    redoStack: [],
+    undoTree: advanceTree(doc.undoTree, entry),
+  };
+}
+
 function cloneDocumentState(doc: DocumentState): DocumentState {
   const nodes: Record<NodeId, Node> = {};
   for (const [id, node] of Object.entries(doc.nodes)) {
@@ -97,6 +154,7 @@ function createInitialDocument(title: string): { docId: DocId; doc: Document } {
     nodes: { [rootId]: rootNode },
     undoStack: [],
     redoStack: [],
+    undoTree: emptyUndoTree(),
   };
 
   return {
@@ -221,13 +279,20 @@ function swapSibling(doc: Document, direction: "up" | "down"): Document {
   nextChildren[index] = nextChildren[swapWith];
   nextChildren[swapWith] = tmp;
 
-  return {
-    ...doc,
-    nodes: {
-      ...doc.nodes,
-      [parent.id]: { ...parent, childrenIds: nextChildren },
+  return pushHistory(
+    {
+      ...doc,
+      nodes: {
+        ...doc.nodes,
+        [parent.id]: { ...parent, childrenIds: nextChildren },
+      },
     },
-  };
+    {
+      cursorBefore: doc.cursorId,
+      cursorAfter: doc.cursorId,
+      ops: [{ op: "swapSiblings", parentId: parent.id, index, otherIndex: swapWith }],
+    },
+  );
 }
 
 function addChild(doc: Document): { updated: Document; newNodeId: NodeId } {
@@ -346,6 +411,28 @@ export function editorReducer(state: EditorAppState, action: EditorAction): Edit
         workspace: sanitizeWorkspace(action.workspace),
       };
     }
+    case "replaceWorkspace": {
+      if (state.mode === "insert") return state;
+      return bumpSaveRevision({
+        ...state,
+        closeConfirmDocId: null,
+        workspace: sanitizeWorkspace(action.workspace),
+      });
+    }
+    case "replaceDocument": {
+      if (state.mode === "insert") return state;
+      if (!state.workspace.documents[action.document.id]) return state;
+      return bumpSaveRevision({
+        ...state,
+        workspace: {
+          ...state.workspace,
+          documents: {
+            ...state.workspace.documents,
+            [action.document.id]: action.document,
+          },
+        },
+      });
+    }
     case "setActiveDoc": {
       if (state.mode === "insert") return state;
       if (!state.workspace.documents[action.docId]) return state;
@@ -400,6 +487,26 @@ export function editorReducer(state: EditorAppState, action: EditorAction): Edit
         },
       });
     }
+    case "openDocuments": {
+      if (state.mode === "insert") return state;
+      if (action.documents.length === 0) return state;
+      const documents = { ...state.workspace.documents };
+      const tabs = [...state.workspace.tabs];
+      for (const doc of action.documents) {
+        const docId = documents[doc.id] ? generateId() : doc.id;
+        documents[docId] = { ...doc, id: docId };
+        tabs.push({ docId });
+      }
+      return bumpSaveRevision({
+        ...state,
+        workspace: {
+          ...state.workspace,
+          tabs,
+          activeDocId: tabs[tabs.length - 1].docId,
+          documents,
+        },
+      });
+    }
     case "requestCloseActiveDoc": {
       if (state.mode === "insert") return state;
       if (state.workspace.tabs.length <= 1) return state;
@@ -438,14 +545,24 @@ export function editorReducer(state: EditorAppState, action: EditorAction): Edit
       if (state.mode === "insert") return state;
       const next = updateActiveDoc(state, (doc) => {
         if (doc.cursorId === doc.rootId) return doc;
-        const snapshot = cloneDocumentState(doc);
         const updated = deleteCursorNodeAndPromoteChildren(doc);
         if (updated === doc) return doc;
-        return {
-          ...updated,
-          undoStack: [...doc.undoStack, snapshot],
-          redoStack: [],
-        };
+        const deleted = doc.nodes[doc.cursorId];
+        const parentId = deleted.parentId ?? doc.rootId;
+        return pushHistory(updated, {
+          cursorBefore: doc.cursorId,
+          cursorAfter: updated.cursorId,
+          ops: [
+            {
+              op: "deleteNode",
+              nodeId: deleted.id,
+              parentId,
+              index: doc.nodes[parentId].childrenIds.indexOf(deleted.id),
+              text: deleted.text,
+              childrenIds: deleted.childrenIds,
+            },
+          ],
+        });
       });
       if (next === state) return state;
       return bumpSaveRevision(next);
@@ -530,11 +647,7 @@ export function editorReducer(state: EditorAppState, action: EditorAction): Edit
 
       const next = updateActiveDoc(
         { ...state, mode: "normal", insertOrigin: null },
-        (doc) => ({
-          ...doc,
-          undoStack: [...doc.undoStack, origin.snapshot],
-          redoStack: [],
-        }),
+        (doc) => pushHistory(doc, entryBetween(origin.snapshot, doc)),
       );
 
       return bumpSaveRevision(next);
@@ -556,11 +669,9 @@ export function editorReducer(state: EditorAppState, action: EditorAction): Edit
         return { ...state, mode: "normal", insertOrigin: null };
       }
 
-      const next = updateActiveDoc(state, (doc) => ({
-        ...doc,
-        undoStack: [...doc.undoStack, origin.snapshot],
-        redoStack: [],
-      }));
+      const next = updateActiveDoc(state, (doc) =>
+        pushHistory(doc, entryBetween(origin.snapshot, doc)),
+      );
 
       const nextDoc = next.workspace.documents[docId];
       return bumpSaveRevision({
@@ -574,15 +685,16 @@ export function editorReducer(state: EditorAppState, action: EditorAction): Edit
       const docId = state.workspace.activeDocId;
       if (state.workspace.documents[docId].undoStack.length === 0) return state;
       const next = updateActiveDoc(state, (doc) => {
-        const prev = doc.undoStack[doc.undoStack.length - 1];
-        if (!prev) return doc;
-        const nextUndo = doc.undoStack.slice(0, -1);
-        const currentSnapshot = cloneDocumentState(doc);
+        const entry = doc.undoStack[doc.undoStack.length - 1];
+        if (!entry) return doc;
+        const undone = applyEntry(doc, invertEntry(entry));
+        // History that no longer matches the tree cannot be trusted any further.
+        if (!undone) return { ...doc, undoStack: [], redoStack: [], undoTree: emptyUndoTree() };
         return {
-          ...doc,
-          ...cloneDocumentState(prev),
-          undoStack: nextUndo,
-          redoStack: [...doc.redoStack, currentSnapshot],
+          ...undone,
+          undoStack: doc.undoStack.slice(0, -1),
+          redoStack: [...doc.redoStack, entry],
+          undoTree: retreatTree(doc.undoTree),
         };
       });
       return bumpSaveRevision(next);
@@ -592,15 +704,15 @@ export function editorReducer(state: EditorAppState, action: EditorAction): Edit
       const docId = state.workspace.activeDocId;
       if (state.workspace.documents[docId].redoStack.length === 0) return state;
       const next = updateActiveDoc(state, (doc) => {
-        const next = doc.redoStack[doc.redoStack.length - 1];
-        if (!next) return doc;
-        const nextRedo = doc.redoStack.slice(0, -1);
-        const currentSnapshot = cloneDocumentState(doc);
+        const entry = doc.redoStack[doc.redoStack.length - 1];
+        if (!entry) return doc;
+        const redone = applyEntry(doc, entry);
+        if (!redone) return { ...doc, undoStack: [], redoStack: [], undoTree: emptyUndoTree() };
         return {
-          ...doc,
-          ...cloneDocumentState(next),
-          redoStack: nextRedo,
-          undoStack: [...doc.undoStack, currentSnapshot],
+          ...redone,
+          redoStack: doc.redoStack.slice(0, -1),
+          undoStack: [...doc.undoStack, entry],
+          undoTree: advanceTree(doc.undoTree, entry),
         };
       });
       return bumpSaveRevision(next);
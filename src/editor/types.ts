@@ -16,10 +16,50 @@ export type DocumentState = {
   nodes: Record<NodeId, Node>;
 };
 
+export type EditOp =
+  | {
+      op: "insertNode";
+      nodeId: NodeId;
+      parentId: NodeId;
+      index: number;
+      text: string;
+      childrenIds: NodeId[];
+    }
+  | {
+      op: "deleteNode";
+      nodeId: NodeId;
+      parentId: NodeId;
+      index: number;
+      text: string;
+      childrenIds: NodeId[];
+    }
+  | { op: "swapSiblings"; parentId: NodeId; index: number; otherIndex: number }
+  | { op: "editText"; nodeId: NodeId; before: string; after: string }
+  | { op: "patch"; removed: Node[]; added: Node[] };
+
+export type HistoryEntry = {
+  cursorBefore: NodeId;
+  cursorAfter: NodeId;
+  ops: EditOp[];
+};
+
+export type UndoNode = {
+  id: string;
+  parentId: string | null;
+  entry: HistoryEntry | null;
+  createdAtMillis: number;
+};
+
+export type UndoTree = {
+  currentId: string;
+  nodes: Record<string, UndoNode>;
+};
+
 export type Document = DocumentState & {
   id: DocId;
-  undoStack: DocumentState[];
-  redoStack: DocumentState[];
+  undoStack: HistoryEntry[];
+  redoStack: HistoryEntry[];
+  undoTree: UndoTree;
 };
 
 export type Tab = {
@@ -27,6 +67,7 @@ export type Tab = {
 };
 
 export type Workspace = {
+  schemaVersion?: number;
   tabs: Tab[];
   activeDocId: DocId;
   documents: Record<DocId, Document>;
@@ -0,0 +1,151 @@
+import type { Document } from "../../editor/types";
+import type { ThemeName } from "../../hooks/useTheme";
+
+// Mirrors `format::ExportFormat` on the Rust side.
+export type ExportFormat =
+  | { kind: "markdown"; headingDepth: number }
+  | { kind: "opml" }
+  | { kind: "freemind" }
+  | { kind: "mermaid" }
+  | { kind: "dot" }
+  | { kind: "svg"; theme: ThemeName }
+  | { kind: "png"; scale: number; theme: ThemeName }
+  | { kind: "pdf"; scale: number; theme: ThemeName }
+  | { kind: "html"; theme: ThemeName }
+  | { kind: "canvas" }
+  | { kind: "org" }
+  | { kind: "marp" }
+  | { kind: "reveal"; theme: ThemeName };
+
+export type ExportTarget = {
+  id: string;
+  title: string;
+  extension: string;
+  filterName: string;
+  format: ExportFormat;
+};
+
+export const EXPORT_TARGETS: ExportTarget[] = [
+  {
+    id: "markdown",
+    title: "Export as Markdown",
+    extension: "md",
+    filterName: "Markdown",
+    format: { kind: "markdown", headingDepth: 1 },
+  },
+  {
+    id: "markdown-headings",
+    title: "Export as Markdown (first level as headings)",
+    extension: "md",
+    filterName: "Markdown",
+    format: { kind: "markdown", headingDepth: 2 },
+  },
+  {
+    id: "opml",
+    title: "Export as OPML",
+    extension: "opml",
+    filterName: "OPML",
+    format: { kind: "opml" },
+  },
+  {
+    id: "freemind",
+    title: "Export as FreeMind map",
+    extension: "mm",
+    filterName: "FreeMind",
+    format: { kind: "freemind" },
+  },
+  {
+    id: "mermaid",
+    title: "Export as Mermaid mindmap",
+    extension: "mmd",
+    filterName: "Mermaid",
+    format: { kind: "mermaid" },
+  },
+  {
+    id: "dot",
+    title: "Export as Graphviz DOT",
+    extension: "dot",
+    filterName: "Graphviz",
+    format: { kind: "dot" },
+  },
+  {
+    id: "svg",
+    title: "Export as SVG image",
+    extension: "svg",
+    filterName: "SVG",
+    format: { kind: "svg", theme: "dark" },
+  },
+  {
+    id: "png",
+    title: "Export as PNG image",
+    extension: "png",
+    filterName: "PNG",
+    format: { kind: "png", scale: 2, theme: "dark" },
+  },
+  {
+    id: "pdf",
+    title: "Export as PDF for printing",
+    extension: "pdf",
+    filterName: "PDF",
+    format: { kind: "pdf", scale: 1, theme: "dark" },
+  },
+  {
+    id: "html",
+    title: "Export as interactive HTML",
+    extension: "html",
+    filterName: "HTML",
+    format: { kind: "html", theme: "dark" },
+  },
+  {
+    id: "canvas",
+    title: "Export as JSON Canvas",
+    extension: "canvas",
+    filterName: "JSON Canvas",
+    format: { kind: "canvas" },
+  },
+  {
+    id: "org",
+    title: "Export as Org-mode",
+    extension: "org",
+    filterName: "Org",
+    format: { kind: "org" },
+  },
+  {
+    id: "marp",
+    title: "Export as slides (Marp Markdown)",
+    extension: "md",
+    filterName: "Markdown",
+    format: { kind: "marp" },
+  },
+  {
+    id: "reveal",
+    title: "Export as slides (reveal.js HTML, needs internet to view)",
+    extension: "html",
+    filterName: "HTML",
+    format: { kind: "reveal", theme: "dark" },
+  },
+];
+
+// Images are drawn in the theme the editor is showing.
+export function withTheme(format: ExportFormat, theme: ThemeName): ExportFormat {
+  return "theme" in format ? { ...format, theme } : format;
+}
+
+// Extensions `format::import` on the Rust side reads.
+export const IMPORT_FILTERS = [
+  { name: "Markdown / text outline", extensions: ["md", "markdown", "txt", "text"] },
+  { name: "OPML", extensions: ["opml"] },
+  { name: "FreeMind / Freeplane", extensions: ["mm"] },
+  { name: "JSON Canvas", extensions: ["canvas"] },
+  { name: "Org-mode", extensions: ["org"] },
+];
+
+export type ImportedDocument = {
+  document: Document;
+  warnings: string[];
+};
+
+export function exportFileName(title: string, extension: string): string {
+  const stem = title.replace(/[\\/:*?"<>|\n]/g, "_").trim();
+  return `${stem === "" ? "Untitled" : stem}.${extension}`;
+}
@@ -0,0 +1,112 @@
+import type { Document, HistoryEntry, Node, NodeId, UndoNode } from "../../editor/types";
+
+export type JournalOp =
+  | { op: "addNode"; node: Node }
+  | { op: "setText"; nodeId: NodeId; text: string }
+  | { op: "moveNode"; nodeId: NodeId; parentId: NodeId | null }
+  | { op: "setChildren"; nodeId: NodeId; childrenIds: NodeId[] }
+  | { op: "deleteNode"; nodeId: NodeId }
+  | { op: "setCursor"; nodeId: NodeId }
+  | { op: "setRoot"; nodeId: NodeId }
+  | { op: "popUndo"; count: number }
+  | { op: "pushUndo"; entry: HistoryEntry }
+  | { op: "popRedo"; count: number }
+  | { op: "pushRedo"; entry: HistoryEntry }
+  | { op: "putUndoNode"; node: UndoNode }
+  | { op: "removeUndoNode"; nodeId: string }
+  | { op: "setUndoCurrent"; nodeId: string };
+
+function sameIds(a: NodeId[], b: NodeId[]): boolean {
+  if (a === b) return true;
+  if (a.length !== b.length) return false;
+  for (let i = 0; i < a.length; i += 1) {
+    if (a[i] !== b[i]) return false;
+  }
+  return true;
+}
+
+// Stack ops count from the top so they stay valid after the backend has dropped the oldest
+// entries to keep history within its limits.
+function diffStack(
+  prev: HistoryEntry[],
+  next: HistoryEntry[],
+  pop: (count: number) => JournalOp,
+  push: (entry: HistoryEntry) => JournalOp,
+): JournalOp[] {
+  if (prev === next) return [];
+  let shared = 0;
+  while (shared < prev.length && shared < next.length && prev[shared] === next[shared]) {
+    shared += 1;
+  }
+  const ops: JournalOp[] = [];
+  if (shared < prev.length) ops.push(pop(prev.length - shared));
+  for (let i = shared; i < next.length; i += 1) {
+    ops.push(push(next[i]));
+  }
+  return ops;
+}
+
+// Relies on the reducer's immutable updates: unchanged nodes and stack entries keep their
+// identity, so only what was actually touched becomes an op.
+export function diffDocumentOps(prev: Document, next: Document): JournalOp[] {
+  const ops: JournalOp[] = [];
+
+  if (prev.nodes !== next.nodes) {
+    for (const [id, node] of Object.entries(next.nodes)) {
+      const before = prev.nodes[id];
+      if (before === node) continue;
+      if (!before) {
+        ops.push({ op: "addNode", node });
+        continue;
+      }
+      if (before.text !== node.text) {
+        ops.push({ op: "setText", nodeId: id, text: node.text });
+      }
+      if (before.parentId !== node.parentId) {
+        ops.push({ op: "moveNode", nodeId: id, parentId: node.parentId });
+      }
+      if (!sameIds(before.childrenIds, node.childrenIds)) {
+        ops.push({ op: "setChildren", nodeId: id, childrenIds: node.childrenIds });
+      }
+    }
+    for (const id of Object.keys(prev.nodes)) {
+      if (!next.nodes[id]) ops.push({ op: "deleteNode", nodeId: id });
+    }
+  }
+
+  if (prev.rootId !== next.rootId) ops.push({ op: "setRoot", nodeId: next.rootId });
+  if (prev.cursorId !== next.cursorId) ops.push({ op: "setCursor", nodeId: next.cursorId });
+
+  ops.push(
+    ...diffStack(
+      prev.undoStack,
+      next.undoStack,
+      (count) => ({ op: "popUndo", count }),
+      (entry) => ({ op: "pushUndo", entry }),
+    ),
+    ...diffStack(
+      prev.redoStack,
+      next.redoStack,
+      (count) => ({ op: "popRedo", count }),
+      (entry) => ({ op: "pushRedo", entry }),
+    ),
+  );
+
+  if (prev.undoTree !== next.undoTree) {
+    const before = prev.undoTree.nodes;
+    const after = next.undoTree.nodes;
+    if (before !== after) {
+      for (const [id, node] of Object.entries(after)) {
+        if (before[id] !== node) ops.push({ op: "putUndoNode", node });
+      }
+      for (const id of Object.keys(before)) {
+        if (!after[id]) ops.push({ op: "removeUndoNode", nodeId: id });
+      }
+    }
+    if (prev.undoTree.currentId !== next.undoTree.currentId) {
+      ops.push({ op: "setUndoCurrent", nodeId: next.undoTree.currentId });
+    }
+  }
+
+  return ops;
+}
@@ -0,0 +1,273 @@
+import type { DocId, Document, NodeId, Workspace } from "../../editor/types";
+
+export type RepairIssue =
+  | { kind: "nodeIdMismatch"; nodeId: NodeId; found: string }
+  | { kind: "missingRoot"; rootId: NodeId; replacement: NodeId }
+  | { kind: "rootHasParent"; parentId: NodeId }
+  | { kind: "danglingChild"; parentId: NodeId; childId: NodeId }
+  | { kind: "duplicateChild"; parentId: NodeId; childId: NodeId }
+  | { kind: "cycle"; parentId: NodeId; childId: NodeId }
+  | { kind: "parentMismatch"; nodeId: NodeId; expected: NodeId; found: NodeId | null }
+  | { kind: "orphanReattached"; nodeId: NodeId }
+  | { kind: "missingCursor"; cursorId: NodeId }
+  | { kind: "historyDropped"; count: number; reason: string }
+  | { kind: "undoTreeReset"; reason: string }
+  | { kind: "undoNodesDropped"; count: number };
+
+export type StateLocation =
+  | { kind: "current" }
+  | { kind: "undo"; index: number }
+  | { kind: "redo"; index: number }
+  | { kind: "undoTree" };
+
+export type RepairReport = {
+  documents: {
+    docId: DocId;
+    states: { location: StateLocation; issues: RepairIssue[] }[];
+  }[];
+  droppedTabs: DocId[];
+  resetActiveDocId: DocId | null;
+};
+
+export type LostDocument = {
+  docId: DocId | null;
+  reason: string;
+};
+
+export type SalvageReport = {
+  recovered: DocId[];
+  lost: LostDocument[];
+  warnings: string[];
+};
+
+export type LoadedWorkspace = {
+  workspace: Workspace;
+  repairReport: RepairReport | null;
+  salvageReport: SalvageReport | null;
+};
+
+export type DocumentSummary = {
+  docId: DocId;
+  title: string;
+  nodeCount: number;
+};
+
+export type BrokenWorkspaceInfo = {
+  fileName: string;
+  brokenAtMillis: number;
+  documents: DocumentSummary[];
+  lost: LostDocument[];
+};
+
+export type BackupInfo = {
+  fileName: string;
+  createdAtMillis: number;
+  documents: DocumentSummary[];
+  error: string | null;
+};
+
+export type UndoBranch = {
+  tipId: string;
+  forkId: string;
+  createdAtMillis: number;
+  stepsBack: number;
+  stepsForward: number;
+  description: string;
+  current: boolean;
+};
+
+// One line per entry, most recent first.
+export type HistoryDescription = {
+  undo: string[];
+  redo: string[];
+};
+
+export type RecoveredDocuments = {
+  documents: Document[];
+  report: SalvageReport;
+};
+
+export function countRepairIssues(report: RepairReport): number {
+  let count = report.droppedTabs.length + (report.resetActiveDocId ? 1 : 0);
+  for (const doc of report.documents) {
+    for (const state of doc.states) {
+      count += state.issues.length;
+    }
+  }
+  return count;
+}
+
+export function summarizeRepairReport(report: RepairReport): string {
+  const lines: string[] = [];
+  for (const doc of report.documents) {
+    for (const state of doc.states) {
+      const where =
+        state.location.kind === "current" || state.location.kind === "undoTree"
+          ? state.location.kind
+          : `${state.location.kind}[${state.location.index}]`;
+      const kinds = [...new Set(state.issues.map((issue) => issue.kind))].join(", ");
+      lines.push(`${doc.docId} (${where}): ${kinds}`);
+    }
+  }
+  if (report.droppedTabs.length > 0) {
+    lines.push(`dropped tabs: ${report.droppedTabs.join(", ")}`);
+  }
+  if (report.resetActiveDocId) {
+    lines.push(`active tab reset (was ${report.resetActiveDocId})`);
+  }
+  return lines.join("\n");
+}
+
+export function summarizeSalvageReport(report: SalvageReport): string {
+  const lines = [`recovered: ${report.recovered.length}`];
+  for (const lost of report.lost) {
+    lines.push(`lost ${lost.docId ?? "(unknown)"}: ${lost.reason}`);
+  }
+  for (const warning of report.warnings) {
+    lines.push(warning);
+  }
+  return lines.join("\n");
+}
+
+export function describeBackup(info: BackupInfo): { title: string; subtitle: string } {
+  const title = `Restore backup ${new Date(info.createdAtMillis).toLocaleString()}`;
+  if (info.error) {
+    return { title, subtitle: `unreadable: ${info.error}` };
+  }
+  const subtitle = info.documents.map((doc) => `${doc.title} (${doc.nodeCount})`).join(", ");
+  return { title, subtitle };
+}
+
+export type MergeCandidate = {
+  fileName: string;
+  modifiedAtMillis: number;
+  baseFileName: string | null;
+};
+
+export type MergeSide = "ours" | "theirs";
+
+export type MergeConflict =
+  | {
+      kind: "textEditedBoth";
+      docId: DocId;
+      nodeId: NodeId;
+      base: string;
+      ours: string;
+      theirs: string;
+    }
+  | { kind: "deletedAndEdited"; docId: DocId; nodeId: NodeId; deletedIn: MergeSide; text: string }
+  | {
+      kind: "movedBoth";
+      docId: DocId;
+      nodeId: NodeId;
+      oursParentId: NodeId | null;
+      theirsParentId: NodeId | null;
+    }
+  | {
+      kind: "movedIntoCycle";
+      docId: DocId;
+      nodeId: NodeId;
+      oursParentId: NodeId | null;
+      theirsParentId: NodeId | null;
+    }
+  | { kind: "documentDeletedAndEdited"; docId: DocId; deletedIn: MergeSide }
+  | { kind: "unrelated"; docId: DocId };
+
+export type MergedWorkspace = {
+  workspace: Workspace;
+  conflicts: MergeConflict[];
+};
+
+export function summarizeMergeConflicts(conflicts: MergeConflict[]): string {
+  return conflicts
+    .map((conflict) => {
+      switch (conflict.kind) {
+        case "textEditedBoth":
+          return `${conflict.docId}: kept '${conflict.ours}' over '${conflict.theirs}'`;
+        case "deletedAndEdited":
+          return `${conflict.docId}: kept '${conflict.text}' deleted in ${conflict.deletedIn}`;
+        case "movedBoth":
+          return `${conflict.docId}: kept our move of ${conflict.nodeId}`;
+        case "movedIntoCycle":
+          return `${conflict.docId}: kept ${conflict.nodeId} under our parent, their move made a loop`;
+        case "documentDeletedAndEdited":
+          return `${conflict.docId}: kept document closed in ${conflict.deletedIn}`;
+        case "unrelated":
+          return `${conflict.docId}: different trees, kept ours`;
+      }
+    })
+    .join("\n");
+}
+
+export function describeUndoBranch(branch: UndoBranch): string {
+  const steps = `${branch.stepsBack} back, ${branch.stepsForward} ahead`;
+  if (branch.createdAtMillis === 0) return steps;
+  return `${new Date(branch.createdAtMillis).toLocaleString()} · ${steps}`;
+}
+
+export type RevisionInfo = {
+  savedAtMillis: number;
+  title: string;
+  nodeCount: number;
+  changes: string | null;
+};
+
+export function describeRevision(info: RevisionInfo): { when: string; subtitle: string } {
+  return {
+    when: new Date(info.savedAtMillis).toLocaleString(),
+    subtitle: info.changes
+      ? `${info.title} (${info.nodeCount}) · ${info.changes}`
+      : `${info.title} (${info.nodeCount})`,
+  };
+}
+
+export type DiffNode = {
+  nodeId: NodeId;
+  parentId: NodeId | null;
+  text: string;
+};
+
+export type MovedNode = {
+  nodeId: NodeId;
+  text: string;
+  fromParentId: NodeId | null;
+  toParentId: NodeId | null;
+  fromIndex: number;
+  toIndex: number;
+};
+
+export type RetextedNode = {
+  nodeId: NodeId;
+  before: string;
+  after: string;
+};
+
+export type TreeDiff = {
+  added: DiffNode[];
+  removed: DiffNode[];
+  moved: MovedNode[];
+  retexted: RetextedNode[];
+};
+
+// Mirrors `TreeDiff::summary` on the Rust side.
+export function summarizeTreeDiff(diff: TreeDiff): string {
+  const counts: [number, string][] = [
+    [diff.added.length, "added"],
+    [diff.removed.length, "removed"],
+    [diff.moved.length, "moved"],
+    [diff.retexted.length, "edited"],
+  ];
+  const parts = counts
+    .filter(([count]) => count > 0)
+    .map(([count, what]) => `${count} ${what}`);
+  return parts.length > 0 ? parts.join(", ") : "No changes";
+}
+
+export function describeTreeDiff(diff: TreeDiff): string {
+  const lines: string[] = [];
+  for (const node of diff.added) lines.push(`+ ${node.text}`);
+  for (const node of diff.removed) lines.push(`- ${node.text}`);
+  for (const node of diff.moved) lines.push(`~ ${node.text || node.nodeId}`);
+  for (const node of diff.retexted) lines.push(`${node.before} → ${node.after}`);
+  return lines.join("\n");
+}
@@ -1,7 +1,9 @@
 import { invoke } from "@tauri-apps/api/core";
 import { useEffect, useRef, useState } from "react";
-import type { Workspace } from "../editor/types";
+import type { DocId, Document, Workspace } from "../editor/types";
 import type { EditorAction } from "../editor/state";
+import { diffDocumentOps } from "../features/persistence/journal";
+import type { LoadedWorkspace, RepairReport, SalvageReport } from "../features/persistence/model";
 
 type Params = {
   hydrated: boolean;
@@ -13,19 +15,26 @@ type Params = {
 export function useWorkspacePersistence({ hydrated, saveRevision, workspace, dispatch }: Params) {
   const [tauriAvailable, setTauriAvailable] = useState(true);
   const [saveStatus, setSaveStatus] = useState<"saved" | "saving" | "unavailable">("saved");
+  const [repairReport, setRepairReport] = useState<RepairReport | null>(null);
+  const [salvageReport, setSalvageReport] = useState<SalvageReport | null>(null);
 
   const lastSavedRevisionRef = useRef(0);
   const saveTimerRef = useRef<number | null>(null);
   const savingRef = useRef(false);
   const queuedSaveRef = useRef<{ revision: number; workspace: Workspace } | null>(null);
+  // Documents as of the last successful save. The reducer replaces a document object only
+  // when it changes, so identity tells us which documents need rewriting.
+  const lastSavedDocumentsRef = useRef<Record<DocId, Document> | null>(null);
 
   useEffect(() => {
     let cancelled = false;
     const run = async () => {
       try {
-        const loaded = await invoke<Workspace | null>("load_workspace");
+        const loaded = await invoke<LoadedWorkspace | null>("load_workspace");
         if (cancelled) return;
-        dispatch({ type: "finishHydration", workspace: loaded });
+        setRepairReport(loaded?.repairReport ?? null);
+        setSalvageReport(loaded?.salvageReport ?? null);
+        dispatch({ type: "finishHydration", workspace: loaded?.workspace ?? null });
       } catch {
         if (cancelled) return;
         setTauriAvailable(false);
@@ -77,7 +86,27 @@ export function useWorkspacePersistence({ hydrated, saveRevision, workspace, dis
 
         savingRef.current = true;
         try {
-          await invoke("save_workspace", { workspace: queued.workspace });
+          const lastSaved = lastSavedDocumentsRef.current;
+          if (lastSaved === null) {
+            await invoke("save_workspace", { workspace: queued.workspace });
+          } else {
+            const documents = Object.values(queued.workspace.documents);
+            const changed = documents.filter((doc) => !lastSaved[doc.id]);
+            const journal = documents
+              .filter((doc) => lastSaved[doc.id] && lastSaved[doc.id] !== doc)
+              .map((doc) => ({ docId: doc.id, ops: diffDocumentOps(lastSaved[doc.id], doc) }))
+              .filter((entry) => entry.ops.length > 0);
+            await invoke("save_workspace_changes", {
+              changes: {
+                tabs: queued.workspace.tabs,
+                activeDocId: queued.workspace.activeDocId,
+                docIds: Object.keys(queued.workspace.documents),
+                changed,
+                journal,
+              },
+            });
+          }
+          lastSavedDocumentsRef.current = queued.workspace.documents;
           lastSavedRevisionRef.current = Math.max(lastSavedRevisionRef.current, queued.revision);
           setSaveStatus("saved");
         } catch {
@@ -114,6 +143,8 @@ export function useWorkspacePersistence({ hydrated, saveRevision, workspace, dis
     tauriAvailable,
     saveStatus,
     saveLabel,
+    repairReport,
+    salvageReport,
   };
 }
 